@@ -0,0 +1,54 @@
+//! Compiles the cube and triangle shaders in `shaders/` to SPIR-V ahead of
+//! time, so that the library never needs shaderc at runtime.
+
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+};
+
+fn main() {
+  let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("cargo always sets OUT_DIR"));
+  let mut compiler = shaderc::Compiler::new().expect("Couldn't start shaderc!");
+  compile(
+    &mut compiler,
+    &out_dir,
+    "cube.vert",
+    shaderc::ShaderKind::Vertex,
+  );
+  compile(
+    &mut compiler,
+    &out_dir,
+    "cube.frag",
+    shaderc::ShaderKind::Fragment,
+  );
+  compile(
+    &mut compiler,
+    &out_dir,
+    "triangle.vert",
+    shaderc::ShaderKind::Vertex,
+  );
+  compile(
+    &mut compiler,
+    &out_dir,
+    "triangle.frag",
+    shaderc::ShaderKind::Fragment,
+  );
+}
+
+/// Compiles `shaders/<name>` into `<out_dir>/<name>.spv`.
+fn compile(
+  compiler: &mut shaderc::Compiler, out_dir: &Path, name: &str, kind: shaderc::ShaderKind,
+) {
+  let path = Path::new("shaders").join(name);
+  println!("cargo:rerun-if-changed={}", path.display());
+  let source =
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("Couldn't read {}: {}", path.display(), e));
+  let artifact = compiler
+    .compile_into_spirv(&source, kind, name, "main", None)
+    .unwrap_or_else(|e| panic!("Couldn't compile {}: {}", name, e));
+  fs::write(
+    out_dir.join(format!("{}.spv", name)),
+    artifact.as_binary_u8(),
+  )
+  .unwrap_or_else(|e| panic!("Couldn't write the SPIR-V for {}: {}", name, e));
+}
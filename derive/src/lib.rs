@@ -0,0 +1,128 @@
+//! The derive macros of `learn-gfx-hal`. Use them through that crate, the
+//! code they make refers to it by name.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+  parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Lit, Meta, NestedMeta,
+};
+
+/// Implements `VertexLayout` for a `#[repr(C)]` struct, with one attribute
+/// per field (one per column for matrices).
+///
+/// * `#[per_instance]` on the struct makes the buffer advance once per
+///   instance instead of once per vertex.
+/// * `#[location(n)]` on a field puts it at location `n`. The fields after it
+///   carry on from there. Without any the first field is at location 0.
+/// * `#[format(Name)]` on a field uses `Format::Name` instead of the field
+///   type's `VertexFormat`, eg: `#[format(Rgba8Uint)]` on a `[u8; 4]`.
+#[proc_macro_derive(VertexLayout, attributes(per_instance, location, format))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match vertex_layout(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into(),
+  }
+}
+
+fn vertex_layout(input: &DeriveInput) -> Result<TokenStream2, Error> {
+  // without repr(C) the fields could be anywhere, so the offsets would be wrong
+  if !input.attrs.iter().any(is_repr_c) {
+    return Err(Error::new(
+      input.ident.span(),
+      "VertexLayout needs the struct to be #[repr(C)]",
+    ));
+  }
+  let fields = match &input.data {
+    Data::Struct(data) => &data.fields,
+    _ => {
+      return Err(Error::new(
+        input.ident.span(),
+        "VertexLayout can only be derived for structs",
+      ))
+    }
+  };
+  let per_instance = input
+    .attrs
+    .iter()
+    .any(|attr| attr.path.is_ident("per_instance"));
+
+  let mut pushes = Vec::new();
+  for field in fields {
+    let ty = &field.ty;
+    let mut location = quote!(None);
+    let mut format = quote!(None);
+    for attr in &field.attrs {
+      if attr.path.is_ident("location") {
+        let n = location_attr(attr)?;
+        location = quote!(Some(#n));
+      } else if attr.path.is_ident("format") {
+        let name = format_attr(attr)?;
+        format = quote!(Some(::learn_gfx_hal::__derive::Format::#name));
+      }
+    }
+    pushes.push(quote! {
+      builder.field::<#ty>(#location, #format);
+    });
+  }
+
+  let name = &input.ident;
+  let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+  Ok(quote! {
+    impl #impl_generics ::learn_gfx_hal::VertexLayout for #name #ty_generics #where_clause {
+      const PER_INSTANCE: bool = #per_instance;
+
+      fn attributes(binding: u32) -> Vec<::learn_gfx_hal::__derive::AttributeDesc> {
+        let mut builder = ::learn_gfx_hal::__derive::AttributeBuilder::new(binding);
+        #(#pushes)*
+        builder.finish()
+      }
+    }
+  })
+}
+
+fn is_repr_c(attr: &Attribute) -> bool {
+  if !attr.path.is_ident("repr") {
+    return false;
+  }
+  match attr.parse_meta() {
+    Ok(Meta::List(list)) => list.nested.iter().any(|nested| match nested {
+      NestedMeta::Meta(Meta::Word(word)) => word == "C",
+      _ => false,
+    }),
+    _ => false,
+  }
+}
+
+/// The `n` of `#[location(n)]`.
+fn location_attr(attr: &Attribute) -> Result<u32, Error> {
+  if let Ok(Meta::List(list)) = attr.parse_meta() {
+    if list.nested.len() == 1 {
+      if let NestedMeta::Literal(Lit::Int(n)) = &list.nested[0] {
+        return Ok(n.value() as u32);
+      }
+    }
+  }
+  Err(Error::new(
+    attr.span(),
+    "expected a location number, like #[location(2)]",
+  ))
+}
+
+/// The `Name` of `#[format(Name)]`.
+fn format_attr(attr: &Attribute) -> Result<syn::Ident, Error> {
+  if let Ok(Meta::List(list)) = attr.parse_meta() {
+    if list.nested.len() == 1 {
+      if let NestedMeta::Meta(Meta::Word(name)) = &list.nested[0] {
+        return Ok(name.clone());
+      }
+    }
+  }
+  Err(Error::new(
+    attr.span(),
+    "expected the name of a Format, like #[format(Rgba8Unorm)]",
+  ))
+}
@@ -0,0 +1,19 @@
+#version 450
+layout(set = 0, binding = 0) uniform texture2D tex;
+layout(set = 0, binding = 1) uniform sampler samp;
+
+layout (location = 1) in vec2 frag_uv;
+
+// Turned off with a specialization constant to draw the cubes plain white
+layout (constant_id = 0) const bool TEXTURED = true;
+
+layout (location = 0) out vec4 color;
+
+void main()
+{
+  if (TEXTURED) {
+    color = texture(sampler2D(tex, samp), frag_uv);
+  } else {
+    color = vec4(1.0);
+  }
+}
@@ -0,0 +1,30 @@
+#version 450
+layout (push_constant) uniform PushConsts {
+  mat4 view_proj;
+} push;
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 vert_uv;
+// We need to separate each column because using a matrix
+// attribute that takes up multiple locations doesn't work on dx12
+layout (location = 2) in vec4 model_col1;
+layout (location = 3) in vec4 model_col2;
+layout (location = 4) in vec4 model_col3;
+layout (location = 5) in vec4 model_col4;
+
+layout (location = 0) out gl_PerVertex {
+  vec4 gl_Position;
+};
+
+layout (location = 1) out vec2 frag_uv;
+
+void main()
+{
+  mat4 model = mat4(
+    model_col1,
+    model_col2,
+    model_col3,
+    model_col4);
+  gl_Position = push.view_proj * model * vec4(position, 1.0);
+  frag_uv = vert_uv;
+}
@@ -0,0 +1,217 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::HalError;
+use core::str::FromStr;
+use gfx_hal::{
+  adapter::{Adapter, AdapterInfo, DeviceType, MemoryProperties, PhysicalDevice},
+  queue::QueueType,
+  Backend, Instance, Limits, QueueFamily,
+};
+
+/// Which adapter a `HalState` should use when there's more than one that can
+/// do the job.
+///
+/// The "prefer" options fall back to the first suitable adapter if there isn't
+/// one of the preferred type. Picking by name or index doesn't fall back, it's
+/// an error if that adapter isn't there or can't be used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterPreference {
+  /// Just take the first suitable adapter.
+  First,
+  /// A discrete GPU, eg: the dGPU in a laptop.
+  PreferDiscrete,
+  /// An integrated GPU, usually the low power option.
+  PreferIntegrated,
+  /// A software renderer, like llvmpipe or SwiftShader.
+  PreferCpu,
+  /// The first adapter with this in its name, ignoring case.
+  NameContains(String),
+  /// The adapter at this index in the instance's list of adapters (the same
+  /// index as in [`enumerate_adapters`]).
+  Index(usize),
+}
+impl Default for AdapterPreference {
+  fn default() -> Self {
+    AdapterPreference::First
+  }
+}
+impl FromStr for AdapterPreference {
+  type Err = core::num::ParseIntError;
+
+  /// Parses `first`, `discrete`, `integrated`, `cpu`, `#N` for an index, and
+  /// anything else as a name to look for.
+  ///
+  /// This is handy for picking the adapter with an environment variable.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Ok(match s.to_lowercase().as_str() {
+      "first" => AdapterPreference::First,
+      "discrete" => AdapterPreference::PreferDiscrete,
+      "integrated" => AdapterPreference::PreferIntegrated,
+      "cpu" => AdapterPreference::PreferCpu,
+      _ if s.starts_with('#') => AdapterPreference::Index(s[1..].parse()?),
+      _ => AdapterPreference::NameContains(s.to_string()),
+    })
+  }
+}
+
+/// Everything you'd want to know about an adapter when deciding which one to
+/// use.
+#[derive(Debug, Clone)]
+pub struct AdapterReport {
+  /// The adapter's index in the instance's list of adapters.
+  pub index: usize,
+  pub info: AdapterInfo,
+  pub limits: Limits,
+  pub memory_properties: MemoryProperties,
+  /// The type of each queue family and how many queues it has.
+  pub queue_families: Vec<(QueueType, usize)>,
+}
+
+/// Lists every adapter that the instance can see.
+pub fn enumerate_adapters<B: Backend>(instance: &dyn Instance<Backend = B>) -> Vec<AdapterReport> {
+  instance
+    .enumerate_adapters()
+    .into_iter()
+    .enumerate()
+    .map(|(index, adapter)| AdapterReport {
+      index,
+      info: adapter.info.clone(),
+      limits: adapter.physical_device.limits(),
+      memory_properties: adapter.physical_device.memory_properties(),
+      queue_families: adapter
+        .queue_families
+        .iter()
+        .map(|qf| (qf.queue_type(), qf.max_queues()))
+        .collect(),
+    })
+    .collect()
+}
+
+/// Picks an adapter out of the list according to the preference, only ever
+/// picking one that passes the `suitable` check.
+pub fn select_adapter<B: Backend, F: Fn(&Adapter<B>) -> bool>(
+  adapters: Vec<Adapter<B>>, preference: &AdapterPreference, suitable: F,
+) -> Result<Adapter<B>, HalError> {
+  for adapter in adapters.iter() {
+    info!("Adapter: {:?}", adapter.info);
+  }
+  let mut candidates: Vec<(usize, Adapter<B>)> = adapters
+    .into_iter()
+    .enumerate()
+    .filter(|(_, adapter)| suitable(adapter))
+    .collect();
+  let position = {
+    let first = if candidates.is_empty() { None } else { Some(0) };
+    let of_type = |device_type: DeviceType| {
+      candidates
+        .iter()
+        .position(|(_, adapter)| adapter.info.device_type == device_type)
+        .or(first)
+    };
+    match preference {
+      AdapterPreference::First => first,
+      AdapterPreference::PreferDiscrete => of_type(DeviceType::DiscreteGpu),
+      AdapterPreference::PreferIntegrated => of_type(DeviceType::IntegratedGpu),
+      AdapterPreference::PreferCpu => of_type(DeviceType::Cpu),
+      AdapterPreference::NameContains(name) => {
+        let name = name.to_lowercase();
+        candidates
+          .iter()
+          .position(|(_, adapter)| adapter.info.name.to_lowercase().contains(&name))
+      }
+      AdapterPreference::Index(index) => candidates.iter().position(|(i, _)| i == index),
+    }
+  };
+  let (_, adapter) = position
+    .map(|position| candidates.swap_remove(position))
+    .ok_or(HalError::NoSuitableAdapter)?;
+  info!("Picked Adapter: {:?}", adapter.info);
+  Ok(adapter)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn preferences_parse() {
+    let parse = |s: &str| s.parse::<AdapterPreference>();
+    assert_eq!(parse("first"), Ok(AdapterPreference::First));
+    assert_eq!(parse("Discrete"), Ok(AdapterPreference::PreferDiscrete));
+    assert_eq!(parse("INTEGRATED"), Ok(AdapterPreference::PreferIntegrated));
+    assert_eq!(parse("cpu"), Ok(AdapterPreference::PreferCpu));
+    assert_eq!(parse("#2"), Ok(AdapterPreference::Index(2)));
+    assert!(parse("#two").is_err());
+    assert_eq!(
+      parse("GeForce GTX"),
+      Ok(AdapterPreference::NameContains("GeForce GTX".to_string()))
+    );
+  }
+
+  #[cfg(feature = "empty")]
+  fn adapters(types: &[(&str, DeviceType)]) -> Vec<Adapter<crate::back::Backend>> {
+    types
+      .iter()
+      .map(|(name, device_type)| Adapter {
+        info: AdapterInfo {
+          name: name.to_string(),
+          vendor: 0,
+          device: 0,
+          device_type: device_type.clone(),
+        },
+        physical_device: crate::back::PhysicalDevice,
+        queue_families: Vec::new(),
+      })
+      .collect()
+  }
+
+  #[cfg(feature = "empty")]
+  #[test]
+  fn preferences_pick_adapters() {
+    let list = || {
+      adapters(&[
+        ("llvmpipe", DeviceType::Cpu),
+        ("Intel HD", DeviceType::IntegratedGpu),
+        ("Radeon", DeviceType::DiscreteGpu),
+      ])
+    };
+    let pick = |preference: AdapterPreference| {
+      select_adapter(list(), &preference, |_| true).map(|adapter| adapter.info.name)
+    };
+    assert_eq!(pick(AdapterPreference::First).unwrap(), "llvmpipe");
+    assert_eq!(pick(AdapterPreference::PreferDiscrete).unwrap(), "Radeon");
+    assert_eq!(
+      pick(AdapterPreference::PreferIntegrated).unwrap(),
+      "Intel HD"
+    );
+    assert_eq!(pick(AdapterPreference::Index(1)).unwrap(), "Intel HD");
+    assert_eq!(
+      pick(AdapterPreference::NameContains("radeon".to_string())).unwrap(),
+      "Radeon"
+    );
+    assert!(pick(AdapterPreference::Index(3)).is_err());
+    assert!(pick(AdapterPreference::NameContains("Mali".to_string())).is_err());
+  }
+
+  #[cfg(feature = "empty")]
+  #[test]
+  fn unsuitable_adapters_are_skipped() {
+    let list = || {
+      adapters(&[
+        ("Radeon", DeviceType::DiscreteGpu),
+        ("llvmpipe", DeviceType::Cpu),
+      ])
+    };
+    let not_radeon = |adapter: &Adapter<crate::back::Backend>| adapter.info.name != "Radeon";
+    let pick = |preference: AdapterPreference| {
+      select_adapter(list(), &preference, not_radeon).map(|adapter| adapter.info.name)
+    };
+    // a preferred type that isn't suitable falls back to the first one that is
+    assert_eq!(pick(AdapterPreference::PreferDiscrete).unwrap(), "llvmpipe");
+    assert_eq!(pick(AdapterPreference::First).unwrap(), "llvmpipe");
+    // but naming it doesn't
+    assert!(pick(AdapterPreference::Index(0)).is_err());
+    assert!(select_adapter(list(), &AdapterPreference::First, |_| false).is_err());
+  }
+}
@@ -0,0 +1,415 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::HalError;
+use gfx_hal::{
+  adapter::{Adapter, MemoryType, MemoryTypeId, PhysicalDevice},
+  device::Device,
+  image::Tiling,
+  memory::{Properties, Requirements},
+  Backend,
+};
+
+/// How big the chunks for long lived allocations are (unless the heap is tiny).
+const LONG_LIVED_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How big the chunks for transient allocations are (unless the heap is tiny).
+const TRANSIENT_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How long an allocation is expected to stick around, which decides what
+/// kind of chunk it comes out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifetime {
+  /// Freed again soon, like a staging buffer. These come out of a linear arena
+  /// that starts over once everything in it has been freed.
+  Transient,
+  /// Kept for a while, like meshes and textures. These come out of a free list
+  /// so that the space can be reused in any order.
+  LongLived,
+}
+
+/// A piece of a bigger `B::Memory` that belongs to one buffer or image.
+///
+/// Get the memory itself with [`Allocator::memory`], and bind or map at
+/// `offset`. Give it back with [`Allocator::free`].
+#[derive(Debug)]
+pub struct Allocation {
+  chunk: usize,
+  pub offset: u64,
+  pub size: u64,
+}
+impl Allocation {
+  /// The range of the memory that belongs to this allocation, for mapping.
+  pub fn range(&self) -> core::ops::Range<u64> {
+    self.offset..self.offset + self.size
+  }
+}
+
+/// One part of a free list chunk, either in use or free.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+  offset: u64,
+  size: u64,
+  used: bool,
+}
+
+/// How the space of a chunk is handed out.
+enum Pool {
+  /// The blocks cover the whole chunk in order, and two free blocks are never
+  /// next to each other.
+  FreeList(Vec<Block>),
+  /// Everything below `cursor` is used, and `live` is how many allocations
+  /// there still are.
+  Linear { size: u64, cursor: u64, live: usize },
+}
+impl Pool {
+  fn new(lifetime: Lifetime, size: u64) -> Self {
+    match lifetime {
+      Lifetime::Transient => Pool::Linear {
+        size,
+        cursor: 0,
+        live: 0,
+      },
+      Lifetime::LongLived => Pool::FreeList(vec![Block {
+        offset: 0,
+        size,
+        used: false,
+      }]),
+    }
+  }
+
+  /// Finds a spot for the allocation and marks it as used.
+  fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+    match self {
+      Pool::FreeList(blocks) => {
+        for i in 0..blocks.len() {
+          let block = blocks[i];
+          if block.used {
+            continue;
+          }
+          let start = align_up(block.offset, alignment);
+          let end = start + size;
+          if end > block.offset + block.size {
+            continue;
+          }
+          // Split the free block into padding, the allocation, and the rest.
+          let mut parts = Vec::with_capacity(3);
+          if start > block.offset {
+            parts.push(Block {
+              offset: block.offset,
+              size: start - block.offset,
+              used: false,
+            });
+          }
+          parts.push(Block {
+            offset: start,
+            size,
+            used: true,
+          });
+          if end < block.offset + block.size {
+            parts.push(Block {
+              offset: end,
+              size: block.offset + block.size - end,
+              used: false,
+            });
+          }
+          blocks.splice(i..=i, parts);
+          return Some(start);
+        }
+        None
+      }
+      Pool::Linear {
+        size: pool_size,
+        cursor,
+        live,
+      } => {
+        let start = align_up(*cursor, alignment);
+        let end = start + size;
+        if end > *pool_size {
+          return None;
+        }
+        *cursor = end;
+        *live += 1;
+        Some(start)
+      }
+    }
+  }
+
+  /// Marks the allocation at `offset` as free again, and says if the whole
+  /// pool is now unused.
+  fn free(&mut self, offset: u64) -> bool {
+    match self {
+      Pool::FreeList(blocks) => {
+        let mut i = blocks
+          .iter()
+          .position(|block| block.offset == offset && block.used)
+          .expect("freed an allocation that isn't in its chunk");
+        blocks[i].used = false;
+        if i + 1 < blocks.len() && !blocks[i + 1].used {
+          blocks[i].size += blocks[i + 1].size;
+          blocks.remove(i + 1);
+        }
+        if i > 0 && !blocks[i - 1].used {
+          blocks[i - 1].size += blocks[i].size;
+          blocks.remove(i);
+          i -= 1;
+        }
+        blocks.len() == 1 && !blocks[i].used
+      }
+      Pool::Linear { cursor, live, .. } => {
+        *live -= 1;
+        if *live == 0 {
+          *cursor = 0;
+        }
+        *live == 0
+      }
+    }
+  }
+}
+
+struct Chunk<B: Backend> {
+  memory: B::Memory,
+  memory_type: usize,
+  lifetime: Lifetime,
+  /// Buffers and linear images never share a chunk with optimal images, so
+  /// there's no `bufferImageGranularity` to worry about (gfx-hal 0.1 doesn't
+  /// tell us what it is anyway).
+  tiling: Tiling,
+  /// Made for just one allocation that didn't fit in a normal chunk, so it's
+  /// freed along with that allocation.
+  dedicated: bool,
+  pool: Pool,
+}
+
+/// Hands out pieces of a few big memory allocations instead of making one
+/// device allocation per buffer or image, since drivers only allow so many of
+/// those (Vulkan's `maxMemoryAllocationCount`).
+///
+/// Offsets respect each resource's alignment, linear and optimal tilings get
+/// chunks of their own, and allocations in memory that's mapped but not
+/// coherent cover whole `non_coherent_atom_size` pieces, so that flushing one
+/// never touches its neighbours. All the allocations in a chunk share one
+/// `B::Memory`, so only map one of them at a time, the way this crate always
+/// maps, writes, then releases straight away.
+pub struct Allocator<B: Backend> {
+  memory_types: Vec<MemoryType>,
+  memory_heaps: Vec<u64>,
+  non_coherent_atom_size: u64,
+  /// Freed chunks leave a `None` so that the indexes in allocations stay put.
+  chunks: Vec<Option<Chunk<B>>>,
+}
+impl<B: Backend> Allocator<B> {
+  pub fn new(adapter: &Adapter<B>) -> Self {
+    let memory_properties = adapter.physical_device.memory_properties();
+    Self {
+      memory_types: memory_properties.memory_types,
+      memory_heaps: memory_properties.memory_heaps,
+      non_coherent_atom_size: adapter.physical_device.limits().non_coherent_atom_size as u64,
+      chunks: Vec::new(),
+    }
+  }
+
+  /// Finds room for a resource with the given requirements in memory that has
+  /// all of the `properties`, making a new chunk if none of them have space.
+  ///
+  /// Buffers should pass `Tiling::Linear`. Asking for 0 bytes is an error.
+  pub fn allocate<D: Device<B>>(
+    &mut self, device: &D, requirements: Requirements, properties: Properties, tiling: Tiling,
+    lifetime: Lifetime,
+  ) -> Result<Allocation, HalError> {
+    if requirements.size == 0 {
+      return Err(HalError::EmptyAllocation);
+    }
+    let memory_type = self
+      .memory_types
+      .iter()
+      .enumerate()
+      .position(|(id, memory_type)| {
+        requirements.type_mask & (1 << id) != 0 && memory_type.properties.contains(properties)
+      })
+      .ok_or(HalError::NoSuitableMemoryType)?;
+    // Flushing and invalidating non-coherent memory works in whole atoms, so
+    // give out whole atoms.
+    let (size, alignment) = {
+      let memory_properties = self.memory_types[memory_type].properties;
+      if memory_properties.contains(Properties::CPU_VISIBLE)
+        && !memory_properties.contains(Properties::COHERENT)
+      {
+        let atom = self.non_coherent_atom_size.max(1);
+        (
+          align_up(requirements.size, atom),
+          requirements.alignment.max(atom),
+        )
+      } else {
+        (requirements.size, requirements.alignment)
+      }
+    };
+
+    // Try The Chunks We Already Have
+    for (index, slot) in self.chunks.iter_mut().enumerate() {
+      if let Some(chunk) = slot {
+        if chunk.memory_type != memory_type
+          || chunk.lifetime != lifetime
+          || chunk.tiling != tiling
+          || chunk.dedicated
+        {
+          continue;
+        }
+        if let Some(offset) = chunk.pool.allocate(size, alignment) {
+          return Ok(Allocation {
+            chunk: index,
+            offset,
+            size,
+          });
+        }
+      }
+    }
+
+    // Make A New Chunk
+    let heap_size = self.memory_heaps[self.memory_types[memory_type].heap_index];
+    let chunk_size = match lifetime {
+      Lifetime::Transient => TRANSIENT_CHUNK_SIZE,
+      Lifetime::LongLived => LONG_LIVED_CHUNK_SIZE,
+    }
+    .min(heap_size / 4);
+    let dedicated = size > chunk_size;
+    let chunk_size = if dedicated { size } else { chunk_size };
+    let memory = unsafe { device.allocate_memory(MemoryTypeId(memory_type), chunk_size)? };
+    debug!(
+      "Allocated a {} byte {:?} {:?} chunk of memory type {}",
+      chunk_size, lifetime, tiling, memory_type
+    );
+    let mut chunk = Chunk {
+      memory,
+      memory_type,
+      lifetime,
+      tiling,
+      dedicated,
+      pool: Pool::new(lifetime, chunk_size),
+    };
+    let offset = chunk
+      .pool
+      .allocate(size, alignment)
+      .expect("a fresh chunk always has room");
+    let index = match self.chunks.iter().position(Option::is_none) {
+      Some(index) => {
+        self.chunks[index] = Some(chunk);
+        index
+      }
+      None => {
+        self.chunks.push(Some(chunk));
+        self.chunks.len() - 1
+      }
+    };
+    Ok(Allocation {
+      chunk: index,
+      offset,
+      size,
+    })
+  }
+
+  /// The memory that the allocation is a piece of.
+  pub fn memory(&self, allocation: &Allocation) -> &B::Memory {
+    &self.chunks[allocation.chunk]
+      .as_ref()
+      .expect("the allocation's chunk was already freed")
+      .memory
+  }
+
+  /// Gives the allocation's space back. Dedicated chunks are freed right away,
+  /// the others are kept around to be reused.
+  ///
+  /// # Safety
+  ///
+  /// The buffer or image that used the allocation must already be destroyed.
+  pub unsafe fn free<D: Device<B>>(&mut self, device: &D, allocation: Allocation) {
+    let slot = &mut self.chunks[allocation.chunk];
+    let unused = slot
+      .as_mut()
+      .expect("the allocation's chunk was already freed")
+      .pool
+      .free(allocation.offset);
+    if unused && slot.as_ref().map_or(false, |chunk| chunk.dedicated) {
+      if let Some(chunk) = slot.take() {
+        device.free_memory(chunk.memory);
+      }
+    }
+  }
+
+  /// How many device allocations we're holding on to right now.
+  pub fn allocation_count(&self) -> usize {
+    self.chunks.iter().filter(|slot| slot.is_some()).count()
+  }
+
+  /// Frees every chunk.
+  ///
+  /// # Safety
+  ///
+  /// Everything that was allocated from them must already be destroyed.
+  pub unsafe fn manually_drop<D: Device<B>>(&mut self, device: &D) {
+    for chunk in self.chunks.drain(..).flatten() {
+      device.free_memory(chunk.memory);
+    }
+  }
+}
+
+/// Rounds `value` up to a multiple of `alignment`.
+fn align_up(value: u64, alignment: u64) -> u64 {
+  let alignment = alignment.max(1);
+  (value + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn free_list_aligns_and_reuses_space() {
+    let mut pool = Pool::new(Lifetime::LongLived, 4096);
+    assert_eq!(pool.allocate(100, 256), Some(0));
+    assert_eq!(pool.allocate(100, 256), Some(256));
+    assert_eq!(pool.allocate(4000, 1), None);
+    assert!(!pool.free(0));
+    // the freed space comes first, and is used again
+    assert_eq!(pool.allocate(50, 16), Some(0));
+    assert!(!pool.free(256));
+    // freeing the last one merges everything back into one free block
+    assert!(pool.free(0));
+    assert_eq!(pool.allocate(4096, 1), Some(0));
+  }
+
+  #[test]
+  fn free_list_fills_holes_that_fit() {
+    let mut pool = Pool::new(Lifetime::LongLived, 1024);
+    assert_eq!(pool.allocate(100, 4), Some(0));
+    assert_eq!(pool.allocate(100, 4), Some(100));
+    assert_eq!(pool.allocate(100, 4), Some(200));
+    assert!(!pool.free(100));
+    // too big for the hole, so it goes after the last one
+    assert_eq!(pool.allocate(150, 4), Some(300));
+    assert_eq!(pool.allocate(60, 4), Some(100));
+    assert_eq!(pool.allocate(40, 4), Some(160));
+  }
+
+  #[test]
+  fn linear_arena_starts_over_once_empty() {
+    let mut pool = Pool::new(Lifetime::Transient, 4096);
+    assert_eq!(pool.allocate(10, 16), Some(0));
+    assert_eq!(pool.allocate(10, 16), Some(16));
+    assert_eq!(pool.allocate(4096, 16), None);
+    assert!(!pool.free(0));
+    // the space of freed allocations isn't reused until they're all gone
+    assert_eq!(pool.allocate(10, 16), Some(32));
+    assert!(!pool.free(16));
+    assert!(pool.free(32));
+    assert_eq!(pool.allocate(4096, 16), Some(0));
+  }
+
+  #[test]
+  fn align_up_rounds_to_the_next_multiple() {
+    assert_eq!(align_up(0, 256), 0);
+    assert_eq!(align_up(1, 256), 256);
+    assert_eq!(align_up(256, 256), 256);
+    assert_eq!(align_up(7, 0), 7);
+  }
+}
@@ -0,0 +1,386 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{Allocator, HalError, LoadedImage, SamplerCache, TextureDesc, Vertex};
+use core::fmt;
+use gfx_hal::{
+  adapter::Adapter,
+  device::Device,
+  pool::CommandPool,
+  queue::{
+    capability::{Capability, Supports, Transfer},
+    CommandQueue,
+  },
+  Backend,
+};
+use image::RgbaImage;
+
+/// The entries of an atlas don't fit in the biggest size it's allowed to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasTooSmall {
+  pub max_size: u32,
+}
+impl fmt::Display for AtlasTooSmall {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "The entries don't fit in a {0}x{0} atlas", self.max_size)
+  }
+}
+impl std::error::Error for AtlasTooSmall {}
+
+/// Where an entry ended up in an atlas, in UV coordinates.
+///
+/// `min` is the top left corner and `max` the bottom right, the same way
+/// around as the UVs of a whole texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+  pub min: [f32; 2],
+  pub max: [f32; 2],
+}
+impl UvRect {
+  /// Takes a UV meant for the entry's image by itself to the same spot in the
+  /// atlas. Works for any UV, like the ones in `Quad` vertex attributes.
+  pub fn map(&self, [u, v]: [f32; 2]) -> [f32; 2] {
+    [
+      self.min[0] + u * (self.max[0] - self.min[0]),
+      self.min[1] + v * (self.max[1] - self.min[1]),
+    ]
+  }
+
+  /// Remaps the UVs of every vertex, see `map`.
+  pub fn apply(&self, vertexes: &mut [Vertex]) {
+    for vertex in vertexes {
+      vertex.uv = self.map(vertex.uv);
+    }
+  }
+}
+
+/// Collects images and packs them into one atlas texture.
+///
+/// Each entry gets a border of `padding` pixels copied out from its own edges,
+/// so that filtering near an edge blends with more of the entry instead of
+/// with its neighbor. Mip level `n` is a `2^n` downscale, so a padding of
+/// `2^n` keeps the first `n` mip levels from bleeding.
+///
+/// ```no_run
+/// # use learn_gfx_hal::AtlasBuilder;
+/// # let (grass, stone) = (image::RgbaImage::new(16, 16), image::RgbaImage::new(16, 16));
+/// let mut builder = AtlasBuilder::new().padding(4);
+/// let grass_id = builder.add(grass);
+/// let stone_id = builder.add(stone);
+/// let atlas = builder.pack().unwrap();
+/// let grass_rect = atlas.rects[grass_id];
+/// ```
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+  padding: u32,
+  max_size: u32,
+  entries: Vec<RgbaImage>,
+}
+impl Default for AtlasBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl AtlasBuilder {
+  /// No padding, and at most 4096x4096.
+  pub fn new() -> Self {
+    Self {
+      padding: 0,
+      max_size: 4096,
+      entries: Vec::new(),
+    }
+  }
+
+  pub fn padding(mut self, padding: u32) -> Self {
+    self.padding = padding;
+    self
+  }
+
+  /// The biggest the atlas can be on each side.
+  pub fn max_size(mut self, max_size: u32) -> Self {
+    self.max_size = max_size;
+    self
+  }
+
+  /// Adds an entry, giving back its index in [`PackedAtlas::rects`].
+  pub fn add(&mut self, image: RgbaImage) -> usize {
+    self.entries.push(image);
+    self.entries.len() - 1
+  }
+
+  /// Packs the entries into the smallest power of two square that they fit
+  /// in, and then trims the height down to the smallest power of two that
+  /// still holds them.
+  ///
+  /// Entries are placed tallest first along shelves, which wastes little room
+  /// when the entries are close to the same size, like sprites and block faces
+  /// usually are.
+  pub fn pack(self) -> Result<PackedAtlas, AtlasTooSmall> {
+    let padding = self.padding;
+    let padded: Vec<(u32, u32)> = self
+      .entries
+      .iter()
+      .map(|image| (image.width() + 2 * padding, image.height() + 2 * padding))
+      .collect();
+    let mut order: Vec<usize> = (0..padded.len()).collect();
+    order.sort_by_key(|&i| core::cmp::Reverse(padded[i].1));
+
+    let area: u64 = padded
+      .iter()
+      .map(|&(w, h)| u64::from(w) * u64::from(h))
+      .sum();
+    let widest = padded.iter().map(|&(w, _)| w).max().unwrap_or(1);
+    let mut size = ((area as f64).sqrt().ceil() as u32)
+      .max(widest)
+      .max(1)
+      .next_power_of_two();
+    let (positions, used_height) = loop {
+      if size > self.max_size {
+        return Err(AtlasTooSmall {
+          max_size: self.max_size,
+        });
+      }
+      let (positions, used_height) = shelf_pack(&padded, &order, size);
+      if used_height <= size {
+        break (positions, used_height);
+      }
+      size *= 2;
+    };
+    let (width, height) = (size, used_height.max(1).next_power_of_two());
+    debug!(
+      "Packed {} atlas entries into {}x{}",
+      self.entries.len(),
+      width,
+      height
+    );
+
+    let mut atlas = RgbaImage::new(width, height);
+    let mut rects = Vec::with_capacity(self.entries.len());
+    for (entry, &(x, y)) in self.entries.iter().zip(positions.iter()) {
+      let (x, y) = (x + padding, y + padding);
+      let (w, h) = entry.dimensions();
+      if w > 0 && h > 0 {
+        // the entry itself and then its edges pulled outwards into the padding
+        let padding = padding as i64;
+        for py in -padding..h as i64 + padding {
+          for px in -padding..w as i64 + padding {
+            let sx = px.max(0).min(w as i64 - 1) as u32;
+            let sy = py.max(0).min(h as i64 - 1) as u32;
+            atlas.put_pixel(
+              (x as i64 + px) as u32,
+              (y as i64 + py) as u32,
+              *entry.get_pixel(sx, sy),
+            );
+          }
+        }
+      }
+      rects.push(UvRect {
+        min: [x as f32 / width as f32, y as f32 / height as f32],
+        max: [
+          (x + w) as f32 / width as f32,
+          (y + h) as f32 / height as f32,
+        ],
+      });
+    }
+    Ok(PackedAtlas {
+      image: atlas,
+      rects,
+    })
+  }
+}
+
+/// Puts each padded entry on the current shelf until it's full, and then
+/// starts a new shelf below it. Gives back the top left corner of each entry's
+/// padding, and how far down the shelves went.
+fn shelf_pack(padded: &[(u32, u32)], order: &[usize], width: u32) -> (Vec<(u32, u32)>, u32) {
+  let mut positions = vec![(0, 0); padded.len()];
+  let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+  for &i in order {
+    let (w, h) = padded[i];
+    if x + w > width {
+      x = 0;
+      y += shelf_height;
+      shelf_height = 0;
+    }
+    positions[i] = (x, y);
+    x += w;
+    shelf_height = shelf_height.max(h);
+  }
+  (positions, y + shelf_height)
+}
+
+/// The packed atlas image and where each entry is in it.
+#[derive(Debug, Clone)]
+pub struct PackedAtlas {
+  pub image: RgbaImage,
+  /// One rect per entry, in the order they were added.
+  pub rects: Vec<UvRect>,
+}
+impl PackedAtlas {
+  /// Uploads the atlas with `LoadedImage::new`, giving back the texture and
+  /// the entries' rects. Use an `Uploader` with the `image` to upload it
+  /// without waiting instead.
+  #[allow(clippy::too_many_arguments)]
+  pub fn upload<B: Backend, D: Device<B>, C: Capability + Supports<Transfer>>(
+    self, adapter: &Adapter<B>, device: &D, allocator: &mut Allocator<B>,
+    samplers: &mut SamplerCache<B>, command_pool: &mut CommandPool<B, C>,
+    command_queue: &mut CommandQueue<B, C>, desc: &TextureDesc,
+  ) -> Result<(LoadedImage<B, D>, Vec<UvRect>), HalError> {
+    let texture = LoadedImage::new(
+      adapter,
+      device,
+      allocator,
+      samplers,
+      command_pool,
+      command_queue,
+      self.image,
+      desc,
+    )?;
+    Ok((texture, self.rects))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::Rgba;
+
+  /// The entry's rect in pixels, grown by the padding, as `(x0, y0, x1, y1)`.
+  fn padded_pixels(atlas: &PackedAtlas, rect: UvRect, padding: i64) -> (i64, i64, i64, i64) {
+    let (width, height) = atlas.image.dimensions();
+    let to_x = |u: f32| (u * width as f32).round() as i64;
+    let to_y = |v: f32| (v * height as f32).round() as i64;
+    (
+      to_x(rect.min[0]) - padding,
+      to_y(rect.min[1]) - padding,
+      to_x(rect.max[0]) + padding,
+      to_y(rect.max[1]) + padding,
+    )
+  }
+
+  #[test]
+  fn padded_entries_dont_overlap() {
+    let sizes = [
+      (5, 3),
+      (7, 7),
+      (2, 9),
+      (4, 4),
+      (10, 1),
+      (3, 3),
+      (6, 2),
+      (1, 1),
+    ];
+    let mut builder = AtlasBuilder::new().padding(1);
+    for &(w, h) in sizes.iter() {
+      builder.add(RgbaImage::new(w, h));
+    }
+    let atlas = builder.pack().unwrap();
+    let (width, height) = atlas.image.dimensions();
+    assert!(width.is_power_of_two() && height.is_power_of_two());
+    let pixels: Vec<_> = atlas
+      .rects
+      .iter()
+      .map(|&rect| padded_pixels(&atlas, rect, 1))
+      .collect();
+    for (i, &(x0, y0, x1, y1)) in pixels.iter().enumerate() {
+      let (w, h) = sizes[i];
+      assert_eq!((x1 - x0, y1 - y0), (i64::from(w) + 2, i64::from(h) + 2));
+      assert!(x0 >= 0 && y0 >= 0 && x1 <= i64::from(width) && y1 <= i64::from(height));
+      for &(ox0, oy0, ox1, oy1) in &pixels[i + 1..] {
+        assert!(x1 <= ox0 || ox1 <= x0 || y1 <= oy0 || oy1 <= y0);
+      }
+    }
+  }
+
+  #[test]
+  fn padding_repeats_the_edge_texels() {
+    let colors = [
+      [255, 0, 0, 255],
+      [0, 255, 0, 255],
+      [0, 0, 255, 255],
+      [9, 9, 9, 255],
+    ];
+    let mut builder = AtlasBuilder::new().padding(2);
+    builder.add(RgbaImage::from_fn(2, 2, |x, y| Rgba {
+      data: colors[(y * 2 + x) as usize],
+    }));
+    let atlas = builder.pack().unwrap();
+    assert_eq!(atlas.image.dimensions(), (8, 8));
+    assert_eq!(atlas.rects[0].min, [0.25, 0.25]);
+    assert_eq!(atlas.rects[0].max, [0.5, 0.5]);
+    let at = |x, y| atlas.image.get_pixel(x, y).data;
+    assert_eq!(at(0, 0), colors[0]);
+    assert_eq!(at(2, 0), colors[0]);
+    assert_eq!(at(5, 0), colors[1]);
+    assert_eq!(at(0, 5), colors[2]);
+    assert_eq!(at(5, 5), colors[3]);
+    assert_eq!(at(3, 2), colors[1]);
+    // nothing past the padding
+    assert_eq!(at(6, 6), [0, 0, 0, 0]);
+  }
+
+  #[test]
+  fn entries_keep_their_own_texels() {
+    let mut builder = AtlasBuilder::new().padding(2);
+    let ids: Vec<usize> = (0..10u8)
+      .map(|i| {
+        let size = 8 + u32::from(i % 3) * 4;
+        builder.add(RgbaImage::from_pixel(
+          size,
+          size,
+          Rgba {
+            data: [i, 0, 0, 255],
+          },
+        ))
+      })
+      .collect();
+    let atlas = builder.pack().unwrap();
+    for (i, &id) in ids.iter().enumerate() {
+      // every texel of the entry and its padding has the entry's color
+      let (x0, y0, x1, y1) = padded_pixels(&atlas, atlas.rects[id], 2);
+      for y in y0..y1 {
+        for x in x0..x1 {
+          assert_eq!(
+            atlas.image.get_pixel(x as u32, y as u32).data,
+            [i as u8, 0, 0, 255]
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn uv_rect_map() {
+    let rect = UvRect {
+      min: [0.25, 0.5],
+      max: [0.75, 1.0],
+    };
+    assert_eq!(rect.map([0.0, 0.0]), [0.25, 0.5]);
+    assert_eq!(rect.map([1.0, 1.0]), [0.75, 1.0]);
+    assert_eq!(rect.map([0.5, 0.5]), [0.5, 0.75]);
+    assert_eq!(rect.map([2.0, -1.0]), [1.25, 0.0]);
+    let mut vertexes = [
+      Vertex {
+        xyz: [0.0; 3],
+        uv: [0.0, 1.0],
+      },
+      Vertex {
+        xyz: [0.0; 3],
+        uv: [1.0, 0.0],
+      },
+    ];
+    rect.apply(&mut vertexes);
+    assert_eq!(vertexes[0].uv, [0.25, 1.0]);
+    assert_eq!(vertexes[1].uv, [0.75, 0.5]);
+  }
+
+  #[test]
+  fn empty_and_too_small_atlases() {
+    let atlas = AtlasBuilder::new().pack().unwrap();
+    assert_eq!(atlas.image.dimensions(), (1, 1));
+    assert!(atlas.rects.is_empty());
+    let mut builder = AtlasBuilder::new().max_size(16);
+    builder.add(RgbaImage::new(20, 4));
+    assert_eq!(builder.pack().unwrap_err(), AtlasTooSmall { max_size: 16 });
+  }
+}
@@ -0,0 +1,562 @@
+//! Decoding BC1 to BC5 and BC7 blocks on the CPU, for adapters that can't
+//! sample them. Every block is 4x4 texels, given back in row order as RGBA.
+
+/// The texels of one decoded block.
+pub(crate) type Texels = [[u8; 4]; 16];
+
+/// BC1, with or without its 1-bit alpha. Without alpha the "transparent"
+/// color is opaque black.
+pub(crate) fn bc1(block: &[u8], alpha: bool) -> Texels {
+  let mut out = [[0; 4]; 16];
+  color_block(block, true, &mut out);
+  if !alpha {
+    for texel in out.iter_mut() {
+      texel[3] = 255;
+    }
+  }
+  out
+}
+
+/// BC2, which is explicit 4-bit alpha followed by a BC1 color block.
+pub(crate) fn bc2(block: &[u8]) -> Texels {
+  let mut out = [[0; 4]; 16];
+  color_block(&block[8..16], false, &mut out);
+  for (i, texel) in out.iter_mut().enumerate() {
+    let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0xF;
+    texel[3] = nibble * 17;
+  }
+  out
+}
+
+/// BC3, which is a BC4 style alpha block followed by a BC1 color block.
+pub(crate) fn bc3(block: &[u8]) -> Texels {
+  let mut out = [[0; 4]; 16];
+  color_block(&block[8..16], false, &mut out);
+  let alpha = channel_block(&block[0..8]);
+  for (texel, &a) in out.iter_mut().zip(alpha.iter()) {
+    texel[3] = a;
+  }
+  out
+}
+
+/// BC4, a single channel. It goes in red, the way an `R8` texture samples.
+pub(crate) fn bc4(block: &[u8]) -> Texels {
+  let mut out = [[0, 0, 0, 255]; 16];
+  for (texel, &r) in out.iter_mut().zip(channel_block(block).iter()) {
+    texel[0] = r;
+  }
+  out
+}
+
+/// BC5, two channels. They go in red and green, the way an `RG8` texture
+/// samples.
+pub(crate) fn bc5(block: &[u8]) -> Texels {
+  let mut out = [[0, 0, 0, 255]; 16];
+  let red = channel_block(&block[0..8]);
+  let green = channel_block(&block[8..16]);
+  for (i, texel) in out.iter_mut().enumerate() {
+    texel[0] = red[i];
+    texel[1] = green[i];
+  }
+  out
+}
+
+/// The 8 byte color part of BC1, BC2, and BC3. Only BC1 has the 3 color mode
+/// with transparent black, the others always use 4 colors.
+fn color_block(block: &[u8], allow_three_colors: bool, out: &mut Texels) {
+  let c0 = u16::from(block[0]) | u16::from(block[1]) << 8;
+  let c1 = u16::from(block[2]) | u16::from(block[3]) << 8;
+  let e0 = rgb565(c0);
+  let e1 = rgb565(c1);
+  let mut palette = [[0u8; 4]; 4];
+  palette[0] = [e0[0], e0[1], e0[2], 255];
+  palette[1] = [e1[0], e1[1], e1[2], 255];
+  if c0 > c1 || !allow_three_colors {
+    for ch in 0..3 {
+      let (a, b) = (u32::from(e0[ch]), u32::from(e1[ch]));
+      palette[2][ch] = ((2 * a + b) / 3) as u8;
+      palette[3][ch] = ((a + 2 * b) / 3) as u8;
+    }
+    palette[2][3] = 255;
+    palette[3][3] = 255;
+  } else {
+    for ch in 0..3 {
+      let (a, b) = (u32::from(e0[ch]), u32::from(e1[ch]));
+      palette[2][ch] = ((a + b) / 2) as u8;
+    }
+    palette[2][3] = 255;
+    palette[3] = [0, 0, 0, 0];
+  }
+  let indexes = u32::from(block[4])
+    | u32::from(block[5]) << 8
+    | u32::from(block[6]) << 16
+    | u32::from(block[7]) << 24;
+  for (i, texel) in out.iter_mut().enumerate() {
+    *texel = palette[((indexes >> (2 * i)) & 0b11) as usize];
+  }
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+  let r = ((c >> 11) & 0x1F) as u8;
+  let g = ((c >> 5) & 0x3F) as u8;
+  let b = (c & 0x1F) as u8;
+  [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+/// The 8 byte single channel block of BC3 alpha, BC4, and BC5.
+fn channel_block(block: &[u8]) -> [u8; 16] {
+  let (a, b) = (u32::from(block[0]), u32::from(block[1]));
+  let mut palette = [0u8; 8];
+  palette[0] = block[0];
+  palette[1] = block[1];
+  if a > b {
+    for i in 1..7 {
+      palette[i + 1] = (((7 - i as u32) * a + i as u32 * b) / 7) as u8;
+    }
+  } else {
+    for i in 1..5 {
+      palette[i + 1] = (((5 - i as u32) * a + i as u32 * b) / 5) as u8;
+    }
+    palette[6] = 0;
+    palette[7] = 255;
+  }
+  let mut indexes = 0u64;
+  for (i, &byte) in block[2..8].iter().enumerate() {
+    indexes |= u64::from(byte) << (8 * i);
+  }
+  let mut out = [0; 16];
+  for (i, value) in out.iter_mut().enumerate() {
+    *value = palette[((indexes >> (3 * i)) & 0b111) as usize];
+  }
+  out
+}
+
+/// How each BC7 mode lays out its bits.
+struct Bc7Mode {
+  subsets: usize,
+  partition_bits: u32,
+  rotation_bits: u32,
+  index_selection_bits: u32,
+  color_bits: u32,
+  alpha_bits: u32,
+  endpoint_pbits: bool,
+  shared_pbits: bool,
+  index_bits: u32,
+  index2_bits: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn bc7_mode(
+  subsets: usize, partition_bits: u32, rotation_bits: u32, index_selection_bits: u32,
+  color_bits: u32, alpha_bits: u32, endpoint_pbits: bool, shared_pbits: bool, index_bits: u32,
+  index2_bits: u32,
+) -> Bc7Mode {
+  Bc7Mode {
+    subsets,
+    partition_bits,
+    rotation_bits,
+    index_selection_bits,
+    color_bits,
+    alpha_bits,
+    endpoint_pbits,
+    shared_pbits,
+    index_bits,
+    index2_bits,
+  }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+  bc7_mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+  bc7_mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+  bc7_mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+  bc7_mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+  bc7_mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+  bc7_mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+  bc7_mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+  bc7_mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+/// Reads bits from the start of a block, lowest bit first.
+struct Bits<'a> {
+  block: &'a [u8],
+  position: usize,
+}
+impl<'a> Bits<'a> {
+  fn read(&mut self, count: u32) -> u32 {
+    let mut value = 0;
+    for i in 0..count {
+      let bit = (self.block[self.position / 8] >> (self.position % 8)) & 1;
+      value |= u32::from(bit) << i;
+      self.position += 1;
+    }
+    value
+  }
+}
+
+/// BC7. Reserved blocks (mode 8) decode to transparent black.
+pub(crate) fn bc7(block: &[u8]) -> Texels {
+  let mut out = [[0; 4]; 16];
+  let mode_index = block[0].trailing_zeros() as usize;
+  if mode_index >= 8 {
+    return out;
+  }
+  let mode = &BC7_MODES[mode_index];
+  let mut bits = Bits {
+    block,
+    position: mode_index + 1,
+  };
+  let partition = bits.read(mode.partition_bits) as usize;
+  let rotation = bits.read(mode.rotation_bits);
+  let index_selection = bits.read(mode.index_selection_bits);
+
+  // Endpoints, as [subset][0 or 1][channel], still at their stored precision
+  let mut endpoints = [[[0u32; 4]; 2]; 3];
+  for ch in 0..3 {
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+      for endpoint in subset.iter_mut() {
+        endpoint[ch] = bits.read(mode.color_bits);
+      }
+    }
+  }
+  if mode.alpha_bits > 0 {
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+      for endpoint in subset.iter_mut() {
+        endpoint[3] = bits.read(mode.alpha_bits);
+      }
+    }
+  }
+  let mut pbits = [[0u32; 2]; 3];
+  if mode.endpoint_pbits {
+    for subset in pbits.iter_mut().take(mode.subsets) {
+      for pbit in subset.iter_mut() {
+        *pbit = bits.read(1);
+      }
+    }
+  } else if mode.shared_pbits {
+    for subset in pbits.iter_mut().take(mode.subsets) {
+      let pbit = bits.read(1);
+      *subset = [pbit, pbit];
+    }
+  }
+  let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+
+  // Expand the endpoints to 8 bits
+  let mut colors = [[[0u32; 4]; 2]; 3];
+  for subset in 0..mode.subsets {
+    for e in 0..2 {
+      for ch in 0..4 {
+        let stored_bits = if ch < 3 {
+          mode.color_bits
+        } else {
+          mode.alpha_bits
+        };
+        colors[subset][e][ch] = if stored_bits == 0 {
+          255
+        } else if has_pbits {
+          expand(
+            endpoints[subset][e][ch] << 1 | pbits[subset][e],
+            stored_bits + 1,
+          )
+        } else {
+          expand(endpoints[subset][e][ch], stored_bits)
+        };
+      }
+    }
+  }
+
+  // Which subset each texel is in, and which texels are anchors (their index
+  // has an implied leading 0 bit)
+  let subset_of = |i: usize| -> usize {
+    match mode.subsets {
+      2 => ((BC7_PARTITIONS_2[partition] >> i) & 1) as usize,
+      3 => BC7_PARTITIONS_3[partition][i] as usize,
+      _ => 0,
+    }
+  };
+  let is_anchor = |i: usize| -> bool {
+    i == 0
+      || match mode.subsets {
+        2 => i == BC7_ANCHORS_2[partition] as usize,
+        3 => i == BC7_ANCHORS_3A[partition] as usize || i == BC7_ANCHORS_3B[partition] as usize,
+        _ => false,
+      }
+  };
+  let mut indexes = [0u32; 16];
+  for (i, index) in indexes.iter_mut().enumerate() {
+    let count = if is_anchor(i) {
+      mode.index_bits - 1
+    } else {
+      mode.index_bits
+    };
+    *index = bits.read(count);
+  }
+  let mut indexes2 = [0u32; 16];
+  if mode.index2_bits > 0 {
+    for (i, index) in indexes2.iter_mut().enumerate() {
+      let count = if i == 0 {
+        mode.index2_bits - 1
+      } else {
+        mode.index2_bits
+      };
+      *index = bits.read(count);
+    }
+  }
+
+  for (i, texel) in out.iter_mut().enumerate() {
+    let c = &colors[subset_of(i)];
+    let (color_index, color_bits, alpha_index, alpha_bits) = if mode.index2_bits == 0 {
+      (indexes[i], mode.index_bits, indexes[i], mode.index_bits)
+    } else if index_selection == 0 {
+      (indexes[i], mode.index_bits, indexes2[i], mode.index2_bits)
+    } else {
+      (indexes2[i], mode.index2_bits, indexes[i], mode.index_bits)
+    };
+    for ch in 0..3 {
+      texel[ch] = interpolate(c[0][ch], c[1][ch], color_index, color_bits);
+    }
+    texel[3] = interpolate(c[0][3], c[1][3], alpha_index, alpha_bits);
+    match rotation {
+      1 => texel.swap(0, 3),
+      2 => texel.swap(1, 3),
+      3 => texel.swap(2, 3),
+      _ => (),
+    }
+  }
+  out
+}
+
+/// Spreads a value with `bits` bits out to 8, copying the high bits down.
+fn expand(value: u32, bits: u32) -> u32 {
+  let value = value << (8 - bits);
+  value | value >> bits
+}
+
+fn interpolate(e0: u32, e1: u32, index: u32, index_bits: u32) -> u8 {
+  const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+  const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+  const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+  let weight = match index_bits {
+    2 => WEIGHTS_2[index as usize],
+    3 => WEIGHTS_3[index as usize],
+    _ => WEIGHTS_4[index as usize],
+  };
+  (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+}
+
+/// Two subset partitions, one bit per texel saying if it's in subset 1.
+const BC7_PARTITIONS_2: [u16; 64] = [
+  0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+  0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+  0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+  0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+  0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+  0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Three subset partitions, the subset of each texel.
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+  [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+  [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+  [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+  [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+  [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+  [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+  [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+  [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+  [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+  [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+  [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+  [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+  [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+  [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+  [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+  [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+  [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+  [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+  [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+  [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+  [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// The anchor texel of subset 1 in each two subset partition.
+const BC7_ANCHORS_2: [u8; 64] = [
+  15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+  2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2,
+  15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor texel of subset 1 in each three subset partition.
+const BC7_ANCHORS_3A: [u8; 64] = [
+  3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+  15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5, 10,
+  8, 13, 15, 12, 3, 3,
+];
+
+/// The anchor texel of subset 2 in each three subset partition.
+const BC7_ANCHORS_3B: [u8; 64] = [
+  15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+  10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15, 15,
+  15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Packs `(value, bit count)` fields into a block, lowest bit first, the
+  /// way BC7 reads them.
+  fn pack_bits(fields: &[(u32, u32)]) -> [u8; 16] {
+    let mut block = [0; 16];
+    let mut position = 0;
+    for &(value, count) in fields {
+      for i in 0..count {
+        block[position / 8] |= (((value >> i) & 1) as u8) << (position % 8);
+        position += 1;
+      }
+    }
+    block
+  }
+
+  #[test]
+  fn bc1_four_colors() {
+    // red and blue, with texels 0 to 3 using indexes 0 to 3
+    let texels = bc1(&[0x00, 0xF8, 0x1F, 0x00, 0xE4, 0, 0, 0], true);
+    assert_eq!(texels[0], [255, 0, 0, 255]);
+    assert_eq!(texels[1], [0, 0, 255, 255]);
+    assert_eq!(texels[2], [170, 0, 85, 255]);
+    assert_eq!(texels[3], [85, 0, 170, 255]);
+    assert_eq!(texels[15], [255, 0, 0, 255]);
+  }
+
+  #[test]
+  fn bc1_three_colors_and_transparent_black() {
+    // blue and red, so the first color is smaller and index 3 is transparent
+    let block = [0x1F, 0x00, 0x00, 0xF8, 0xE4, 0, 0, 0];
+    let texels = bc1(&block, true);
+    assert_eq!(texels[2], [127, 0, 127, 255]);
+    assert_eq!(texels[3], [0, 0, 0, 0]);
+    assert_eq!(bc1(&block, false)[3], [0, 0, 0, 255]);
+  }
+
+  #[test]
+  fn bc2_explicit_alpha() {
+    let mut block = [0; 16];
+    block[0] = 0xF0;
+    block[1] = 0x08;
+    block[8..12].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+    let texels = bc2(&block);
+    assert_eq!(texels[0], [255, 255, 255, 0]);
+    assert_eq!(texels[1], [255, 255, 255, 255]);
+    assert_eq!(texels[2], [255, 255, 255, 136]);
+    assert_eq!(texels[15], [255, 255, 255, 0]);
+  }
+
+  #[test]
+  fn bc3_interpolated_alpha() {
+    // alpha 255 and 0 with indexes 0, 1, and 2, over solid green
+    let mut block = [0; 16];
+    block[0..3].copy_from_slice(&[255, 0, 0x88]);
+    block[8..10].copy_from_slice(&[0xE0, 0x07]);
+    let texels = bc3(&block);
+    assert_eq!(texels[0], [0, 255, 0, 255]);
+    assert_eq!(texels[1], [0, 255, 0, 0]);
+    assert_eq!(texels[2], [0, 255, 0, 218]);
+    assert_eq!(texels[3], [0, 255, 0, 255]);
+  }
+
+  #[test]
+  fn bc4_six_values_with_zero_and_one() {
+    // 0 and 255 in the six value mode, with indexes 6, 7, and 2
+    let block = [0, 255, 0xBE, 0, 0, 0, 0, 0];
+    let texels = bc4(&block);
+    assert_eq!(texels[0], [0, 0, 0, 255]);
+    assert_eq!(texels[1], [255, 0, 0, 255]);
+    assert_eq!(texels[2], [51, 0, 0, 255]);
+    assert_eq!(texels[3], [0, 0, 0, 255]);
+  }
+
+  #[test]
+  fn bc5_red_and_green() {
+    let mut block = [0; 16];
+    block[0..3].copy_from_slice(&[0, 255, 0xBE]);
+    block[8..10].copy_from_slice(&[200, 200]);
+    let texels = bc5(&block);
+    assert_eq!(texels[0], [0, 200, 0, 255]);
+    assert_eq!(texels[1], [255, 200, 0, 255]);
+    assert_eq!(texels[2], [51, 200, 0, 255]);
+  }
+
+  #[test]
+  fn bc7_mode_6() {
+    let block = pack_bits(&[
+      (1 << 6, 7),
+      // red, green, and blue endpoint pairs, then alpha
+      (127, 7),
+      (0, 7),
+      (0, 7),
+      (127, 7),
+      (0, 7),
+      (0, 7),
+      (127, 7),
+      (127, 7),
+      // p-bits
+      (1, 1),
+      (0, 1),
+      // texel 0 is an anchor with one bit less
+      (0, 3),
+      (15, 4),
+    ]);
+    let texels = bc7(&block);
+    assert_eq!(texels[0], [255, 1, 1, 255]);
+    assert_eq!(texels[1], [0, 254, 0, 254]);
+    assert_eq!(texels[2], [255, 1, 1, 255]);
+  }
+
+  #[test]
+  fn bc7_reserved_mode_is_transparent_black() {
+    assert_eq!(bc7(&[0; 16]), [[0; 4]; 16]);
+  }
+}
@@ -0,0 +1,97 @@
+use crate::{Allocation, Allocator, HalError, Lifetime};
+use core::{marker::PhantomData, mem::ManuallyDrop};
+use gfx_hal::{
+  buffer::Usage as BufferUsage,
+  device::Device,
+  image::Tiling,
+  memory::{Properties, Requirements},
+  Backend,
+};
+
+/// A buffer along with the memory that backs it.
+pub struct BufferBundle<B: Backend, D: Device<B>> {
+  pub buffer: ManuallyDrop<B::Buffer>,
+  pub requirements: Requirements,
+  pub allocation: ManuallyDrop<Allocation>,
+  pub phantom: PhantomData<D>,
+}
+impl<B: Backend, D: Device<B>> BufferBundle<B, D> {
+  /// A CPU visible buffer that's kept around for a while.
+  pub fn new(
+    device: &D, allocator: &mut Allocator<B>, size: usize, usage: BufferUsage,
+  ) -> Result<Self, HalError> {
+    Self::with_properties(
+      device,
+      allocator,
+      size,
+      usage,
+      Properties::CPU_VISIBLE,
+      Lifetime::LongLived,
+    )
+  }
+
+  /// A CPU visible buffer that's destroyed again soon, like a staging buffer.
+  pub fn new_transient(
+    device: &D, allocator: &mut Allocator<B>, size: usize, usage: BufferUsage,
+  ) -> Result<Self, HalError> {
+    Self::with_properties(
+      device,
+      allocator,
+      size,
+      usage,
+      Properties::CPU_VISIBLE,
+      Lifetime::Transient,
+    )
+  }
+
+  pub(crate) fn with_properties(
+    device: &D, allocator: &mut Allocator<B>, size: usize, usage: BufferUsage,
+    properties: Properties, lifetime: Lifetime,
+  ) -> Result<Self, HalError> {
+    unsafe {
+      let mut buffer = device.create_buffer(size as u64, usage)?;
+      let requirements = device.get_buffer_requirements(&buffer);
+      let allocation =
+        match allocator.allocate(device, requirements, properties, Tiling::Linear, lifetime) {
+          Ok(allocation) => allocation,
+          Err(e) => {
+            // not finding a memory type is expected sometimes, so don't leak
+            device.destroy_buffer(buffer);
+            return Err(e);
+          }
+        };
+      device.bind_buffer_memory(
+        allocator.memory(&allocation),
+        allocation.offset,
+        &mut buffer,
+      )?;
+      Ok(Self {
+        buffer: ManuallyDrop::new(buffer),
+        requirements,
+        allocation: ManuallyDrop::new(allocation),
+        phantom: PhantomData,
+      })
+    }
+  }
+
+  /// Copies the data into the start of the buffer, which must be CPU visible.
+  pub(crate) unsafe fn write<T: Copy>(
+    &self, device: &D, allocator: &Allocator<B>, data: &[T],
+  ) -> Result<(), HalError> {
+    let mut writer = device
+      .acquire_mapping_writer::<T>(allocator.memory(&self.allocation), self.allocation.range())?;
+    writer[..data.len()].copy_from_slice(data);
+    device.release_mapping_writer(writer)?;
+    Ok(())
+  }
+
+  /// # Safety
+  ///
+  /// The GPU can't be using the buffer any more, and `device` and `allocator` have
+  /// to be the ones that made it.
+  pub unsafe fn manually_drop(&self, device: &D, allocator: &mut Allocator<B>) {
+    use core::ptr::read;
+    device.destroy_buffer(ManuallyDrop::into_inner(read(&self.buffer)));
+    allocator.free(device, ManuallyDrop::into_inner(read(&self.allocation)));
+  }
+}
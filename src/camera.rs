@@ -0,0 +1,139 @@
+use nalgebra_glm as glm;
+use std::collections::HashSet;
+use winit::VirtualKeyCode;
+
+/// Acts like a normal "FPS" camera, capped at +/- 89 degrees, no roll.
+#[derive(Debug, Clone, Copy)]
+pub struct EulerFPSCamera {
+  /// Camera position, free free to directly update at any time.
+  pub position: glm::TVec3<f32>,
+  pitch_deg: f32,
+  yaw_deg: f32,
+}
+impl EulerFPSCamera {
+  const UP: [f32; 3] = [0.0, 1.0, 0.0];
+
+  fn make_front(&self) -> glm::TVec3<f32> {
+    let pitch_rad = f32::to_radians(self.pitch_deg);
+    let yaw_rad = f32::to_radians(self.yaw_deg);
+    glm::make_vec3(&[
+      yaw_rad.sin() * pitch_rad.cos(),
+      pitch_rad.sin(),
+      yaw_rad.cos() * pitch_rad.cos(),
+    ])
+  }
+
+  /// Adjusts the camera's orientation.
+  ///
+  /// Input deltas should be in _degrees_, pitch is capped at +/- 89 degrees.
+  pub fn update_orientation(&mut self, d_pitch_deg: f32, d_yaw_deg: f32) {
+    self.pitch_deg = (self.pitch_deg + d_pitch_deg).max(-89.0).min(89.0);
+    self.yaw_deg = (self.yaw_deg + d_yaw_deg) % 360.0;
+  }
+
+  /// Updates the position using WASDQE controls.
+  ///
+  /// The "forward" vector is relative to the current orientation.
+  pub fn update_position(&mut self, keys: &HashSet<VirtualKeyCode>, distance: f32) {
+    let up = glm::make_vec3(&Self::UP);
+    let forward = self.make_front();
+    let cross_normalized = glm::cross::<f32, glm::U3>(&forward, &up).normalize();
+    let mut move_vector =
+      keys
+        .iter()
+        .fold(glm::make_vec3(&[0.0, 0.0, 0.0]), |vec, key| match *key {
+          VirtualKeyCode::W => vec + forward,
+          VirtualKeyCode::S => vec - forward,
+          VirtualKeyCode::A => vec + cross_normalized,
+          VirtualKeyCode::D => vec - cross_normalized,
+          VirtualKeyCode::E => vec + up,
+          VirtualKeyCode::Q => vec - up,
+          _ => vec,
+        });
+    if move_vector != glm::zero() {
+      move_vector = move_vector.normalize();
+      self.position += move_vector * distance;
+    }
+  }
+
+  /// Generates the current view matrix for this camera.
+  pub fn make_view_matrix(&self) -> glm::TMat4<f32> {
+    glm::look_at_lh(
+      &self.position,
+      &(self.position + self.make_front()),
+      &glm::make_vec3(&Self::UP),
+    )
+  }
+
+  /// Makes a new camera at the position specified and Pitch/Yaw of `0.0`.
+  pub const fn at_position(position: glm::TVec3<f32>) -> Self {
+    Self {
+      position,
+      pitch_deg: 0.0,
+      yaw_deg: 0.0,
+    }
+  }
+}
+
+/// Acts like a space flight camera.
+///
+/// Neat, but the fact that the user can disorient themselves means that it
+/// might be too much power for the common use.
+#[derive(Debug, Clone, Copy)]
+pub struct QuaternionFreeCamera {
+  /// Camera position, free free to update directly at any time.
+  pub position: glm::TVec3<f32>,
+  quat: glm::Qua<f32>,
+}
+impl QuaternionFreeCamera {
+  /// Updates the orientation of the camera.
+  ///
+  /// Inputs should be in double radians, and also limited to being less than 10
+  /// degrees at a time to keep approximation error minimal.
+  pub fn update_orientation(&mut self, d_pitch_2rad: f32, d_yaw_2rad: f32, d_roll_2rad: f32) {
+    // This gives a non-unit quaternion! That's okay because of the normalization step.
+    let delta_quat = glm::quat(d_pitch_2rad, d_yaw_2rad, d_roll_2rad, 1.0);
+    self.quat = glm::quat_normalize(&(self.quat * delta_quat));
+  }
+
+  /// Updates the position of the camera with WASDQE controls.
+  ///
+  /// All motion is relative to the current orientation.
+  pub fn update_position(&mut self, keys: &HashSet<VirtualKeyCode>, distance: f32) {
+    let up = glm::make_vec3(&[0.0, 1.0, 0.0]);
+    let forward = glm::make_vec3(&[0.0, 0.0, 1.0]);
+    let cross_normalized = glm::cross::<f32, glm::U3>(&forward, &up).normalize();
+    let mut move_vector =
+      keys
+        .iter()
+        .fold(glm::make_vec3(&[0.0, 0.0, 0.0]), |vec, key| match *key {
+          VirtualKeyCode::W => vec + forward,
+          VirtualKeyCode::S => vec - forward,
+          VirtualKeyCode::A => vec + cross_normalized,
+          VirtualKeyCode::D => vec - cross_normalized,
+          VirtualKeyCode::E => vec + up,
+          VirtualKeyCode::Q => vec - up,
+          _ => vec,
+        });
+    if move_vector != glm::zero() {
+      move_vector = move_vector.normalize();
+      let rotated_move_vector = glm::quat_rotate_vec3(&self.quat, &move_vector);
+      self.position += rotated_move_vector * distance;
+    }
+  }
+
+  /// Generates the current view matrix for this camera.
+  pub fn make_view_matrix(&self) -> glm::TMat4<f32> {
+    let rotation = glm::quat_to_mat4(&self.quat);
+    let translation = glm::translation(&self.position);
+    glm::inverse(&(translation * rotation))
+  }
+
+  /// Makes a new camera at the position specified and an identity orientation.
+  pub fn at_position(position: glm::TVec3<f32>) -> Self {
+    Self {
+      position,
+      quat: glm::quat_identity(),
+    }
+  }
+}
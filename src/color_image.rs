@@ -0,0 +1,131 @@
+use crate::{Allocation, Allocator, HalError, Lifetime};
+use core::{marker::PhantomData, mem::ManuallyDrop};
+use gfx_hal::{
+  device::Device,
+  format::{Aspects, Format},
+  image::{NumSamples, SubresourceRange, Usage},
+  memory::{Properties, Requirements},
+  window::Extent2D,
+  Backend,
+};
+
+/// Parts for a color image that we render into without any swapchain.
+///
+/// Made with `new` it can be used as a color attachment and as a transfer
+/// source, so the pixels can be copied back out once a frame is done. Made with
+/// `new_multisampled` it's only for drawing into before the samples get
+/// resolved into some other image.
+pub struct ColorImage<B: Backend, D: Device<B>> {
+  pub image: ManuallyDrop<B::Image>,
+  pub requirements: Requirements,
+  pub allocation: ManuallyDrop<Allocation>,
+  pub image_view: ManuallyDrop<B::ImageView>,
+  pub format: Format,
+  pub phantom: PhantomData<D>,
+}
+impl<B: Backend, D: Device<B>> ColorImage<B, D> {
+  pub fn new(
+    device: &D, allocator: &mut Allocator<B>, extent: Extent2D, format: Format,
+  ) -> Result<Self, HalError> {
+    Self::with_usage(
+      device,
+      allocator,
+      extent,
+      format,
+      1,
+      Usage::COLOR_ATTACHMENT | Usage::TRANSFER_SRC,
+    )
+  }
+
+  pub fn new_multisampled(
+    device: &D, allocator: &mut Allocator<B>, extent: Extent2D, format: Format, samples: NumSamples,
+  ) -> Result<Self, HalError> {
+    Self::with_usage(
+      device,
+      allocator,
+      extent,
+      format,
+      samples,
+      Usage::COLOR_ATTACHMENT | Usage::TRANSIENT_ATTACHMENT,
+    )
+  }
+
+  fn with_usage(
+    device: &D, allocator: &mut Allocator<B>, extent: Extent2D, format: Format,
+    samples: NumSamples, usage: Usage,
+  ) -> Result<Self, HalError> {
+    unsafe {
+      let mut the_image = device.create_image(
+        gfx_hal::image::Kind::D2(extent.width, extent.height, 1, samples),
+        1,
+        format,
+        gfx_hal::image::Tiling::Optimal,
+        usage,
+        gfx_hal::image::ViewCapabilities::empty(),
+      )?;
+      let requirements = device.get_image_requirements(&the_image);
+      let allocation = match allocator.allocate(
+        device,
+        requirements,
+        Properties::DEVICE_LOCAL,
+        gfx_hal::image::Tiling::Optimal,
+        Lifetime::LongLived,
+      ) {
+        Ok(allocation) => allocation,
+        Err(e) => {
+          device.destroy_image(the_image);
+          return Err(e);
+        }
+      };
+      // from here on a failure has to give back the memory as well
+      let cleanup = |device: &D, allocator: &mut Allocator<B>, the_image: B::Image, allocation| {
+        device.destroy_image(the_image);
+        allocator.free(device, allocation);
+      };
+      if let Err(e) = device.bind_image_memory(
+        allocator.memory(&allocation),
+        allocation.offset,
+        &mut the_image,
+      ) {
+        cleanup(device, allocator, the_image, allocation);
+        return Err(e.into());
+      }
+      let image_view = match device.create_image_view(
+        &the_image,
+        gfx_hal::image::ViewKind::D2,
+        format,
+        gfx_hal::format::Swizzle::NO,
+        SubresourceRange {
+          aspects: Aspects::COLOR,
+          levels: 0..1,
+          layers: 0..1,
+        },
+      ) {
+        Ok(image_view) => image_view,
+        Err(e) => {
+          cleanup(device, allocator, the_image, allocation);
+          return Err(e.into());
+        }
+      };
+      Ok(Self {
+        image: ManuallyDrop::new(the_image),
+        requirements,
+        allocation: ManuallyDrop::new(allocation),
+        image_view: ManuallyDrop::new(image_view),
+        format,
+        phantom: PhantomData,
+      })
+    }
+  }
+
+  /// # Safety
+  ///
+  /// Nothing can render into the image after this, and `device` and
+  /// `allocator` have to be the ones that made it.
+  pub unsafe fn manually_drop(&self, device: &D, allocator: &mut Allocator<B>) {
+    use core::ptr::read;
+    device.destroy_image_view(ManuallyDrop::into_inner(read(&self.image_view)));
+    device.destroy_image(ManuallyDrop::into_inner(read(&self.image)));
+    allocator.free(device, ManuallyDrop::into_inner(read(&self.allocation)));
+  }
+}
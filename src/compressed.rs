@@ -0,0 +1,583 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::bc_decode;
+use core::fmt;
+use gfx_hal::format::Format;
+use image::{Rgba, RgbaImage};
+
+/// The first 12 bytes of a KTX file.
+const KTX_IDENTIFIER: [u8; 12] = [
+  0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// The first 12 bytes of a KTX2 file.
+const KTX2_IDENTIFIER: [u8; 12] = [
+  0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Why a DDS, KTX, or KTX2 file couldn't be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerError {
+  /// The bytes don't start like any of the containers we know.
+  UnknownContainer,
+  /// The file ends before all of the data it says it has.
+  Truncated,
+  /// The texels aren't in one of the BC formats. The number is the format
+  /// code from the file (a DXGI format, FourCC, GL internal format, or Vulkan
+  /// format).
+  UnsupportedFormat(u32),
+  /// The file is a 3D texture, which we don't load.
+  VolumeTexture,
+  /// A KTX2 file with supercompression (eg: Basis or zstd), which we can't
+  /// undo.
+  Supercompressed(u32),
+  /// The file says it has more mip levels than its size allows.
+  TooManyLevels(u32),
+}
+impl fmt::Display for ContainerError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ContainerError::UnknownContainer => write!(f, "Not a DDS, KTX, or KTX2 file"),
+      ContainerError::Truncated => write!(f, "The file is cut short"),
+      ContainerError::UnsupportedFormat(code) => {
+        write!(f, "Format {} (0x{:X}) isn't a BC format", code, code)
+      }
+      ContainerError::VolumeTexture => write!(f, "3D textures aren't supported"),
+      ContainerError::Supercompressed(scheme) => {
+        write!(f, "KTX2 supercompression scheme {} isn't supported", scheme)
+      }
+      ContainerError::TooManyLevels(count) => {
+        write!(f, "{} mip levels is too many for the texture's size", count)
+      }
+    }
+  }
+}
+impl std::error::Error for ContainerError {}
+
+/// Block compressed texels straight out of a DDS, KTX, or KTX2 file, along
+/// with whatever mip levels the file had.
+///
+/// Files with more than one array layer or cube face only have their first
+/// one loaded.
+#[derive(Debug, Clone)]
+pub struct CompressedTexture {
+  pub format: Format,
+  pub width: u32,
+  pub height: u32,
+  /// Level 0 first. Each one is that level's 4x4 blocks, row by row.
+  pub levels: Vec<Vec<u8>>,
+}
+impl CompressedTexture {
+  /// Loads whichever kind of container the bytes are.
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, ContainerError> {
+    if bytes.starts_with(b"DDS ") {
+      Self::from_dds(bytes)
+    } else if bytes.starts_with(&KTX_IDENTIFIER) {
+      Self::from_ktx(bytes)
+    } else if bytes.starts_with(&KTX2_IDENTIFIER) {
+      Self::from_ktx2(bytes)
+    } else {
+      Err(ContainerError::UnknownContainer)
+    }
+  }
+
+  pub fn from_dds(bytes: &[u8]) -> Result<Self, ContainerError> {
+    const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+    const DDSD_DEPTH: u32 = 0x80_0000;
+    if !bytes.starts_with(b"DDS ") {
+      return Err(ContainerError::UnknownContainer);
+    }
+    let flags = read_u32(bytes, 8, false)?;
+    let height = read_u32(bytes, 12, false)?;
+    let width = read_u32(bytes, 16, false)?;
+    let depth = read_u32(bytes, 24, false)?;
+    let mip_count = read_u32(bytes, 28, false)?;
+    let four_cc = read_u32(bytes, 84, false)?;
+    if flags & DDSD_DEPTH != 0 && depth > 1 {
+      return Err(ContainerError::VolumeTexture);
+    }
+    let (format, data_start) = if four_cc == u32::from_le_bytes(*b"DX10") {
+      let dxgi_format = read_u32(bytes, 128, false)?;
+      (dxgi_to_format(dxgi_format)?, 148)
+    } else {
+      (four_cc_to_format(four_cc)?, 128)
+    };
+    let level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+      mip_count.max(1)
+    } else {
+      1
+    };
+    check_level_count(width, height, level_count)?;
+    // The levels of the first layer (or face) come one after the other
+    let mut offset = data_start;
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+      let size = level_size(format, width >> level, height >> level);
+      levels.push(slice(bytes, offset, size)?.to_vec());
+      offset += size;
+    }
+    Ok(Self {
+      format,
+      width,
+      height,
+      levels,
+    })
+  }
+
+  pub fn from_ktx(bytes: &[u8]) -> Result<Self, ContainerError> {
+    if !bytes.starts_with(&KTX_IDENTIFIER) {
+      return Err(ContainerError::UnknownContainer);
+    }
+    // The writer's endianness, which the rest of the header is in
+    let big_endian = read_u32(bytes, 12, false)? != 0x0403_0201;
+    let gl_internal_format = read_u32(bytes, 28, big_endian)?;
+    let width = read_u32(bytes, 36, big_endian)?;
+    let height = read_u32(bytes, 40, big_endian)?.max(1);
+    let depth = read_u32(bytes, 44, big_endian)?;
+    let array_elements = read_u32(bytes, 48, big_endian)?;
+    let faces = read_u32(bytes, 52, big_endian)?;
+    let mip_count = read_u32(bytes, 56, big_endian)?;
+    let key_value_bytes = read_u32(bytes, 60, big_endian)? as usize;
+    if depth > 1 {
+      return Err(ContainerError::VolumeTexture);
+    }
+    let format = gl_to_format(gl_internal_format)?;
+    let level_count = mip_count.max(1);
+    check_level_count(width, height, level_count)?;
+    // Each level is its size, then every layer and face of it, then padding
+    // to 4 bytes (which blocks never need). Only for a cubemap that isn't an
+    // array is the size just one face's, with each of the six faces padded.
+    let faces_after_size = if faces == 6 && array_elements == 0 {
+      6
+    } else {
+      1
+    };
+    let mut offset = 64 + key_value_bytes;
+    let mut levels = Vec::new();
+    for level in 0..level_count {
+      let image_size = read_u32(bytes, offset, big_endian)? as usize;
+      let size = level_size(format, width >> level, height >> level);
+      levels.push(slice(bytes, offset + 4, size)?.to_vec());
+      offset += 4 + faces_after_size * ((image_size + 3) & !3);
+    }
+    Ok(Self {
+      format,
+      width,
+      height,
+      levels,
+    })
+  }
+
+  pub fn from_ktx2(bytes: &[u8]) -> Result<Self, ContainerError> {
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+      return Err(ContainerError::UnknownContainer);
+    }
+    let vk_format = read_u32(bytes, 12, false)?;
+    let width = read_u32(bytes, 20, false)?;
+    let height = read_u32(bytes, 24, false)?.max(1);
+    let depth = read_u32(bytes, 28, false)?;
+    let level_count = read_u32(bytes, 40, false)?.max(1);
+    let supercompression = read_u32(bytes, 44, false)?;
+    if depth > 1 {
+      return Err(ContainerError::VolumeTexture);
+    }
+    if supercompression != 0 {
+      return Err(ContainerError::Supercompressed(supercompression));
+    }
+    let format = vk_to_format(vk_format)?;
+    check_level_count(width, height, level_count)?;
+    // The level index comes right after the header, level 0 first, as a byte
+    // offset, a byte length, and an uncompressed byte length
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+      let entry = 80 + 24 * level as usize;
+      let offset = read_u64(bytes, entry)? as usize;
+      let size = level_size(format, width >> level, height >> level);
+      levels.push(slice(bytes, offset, size)?.to_vec());
+    }
+    Ok(Self {
+      format,
+      width,
+      height,
+      levels,
+    })
+  }
+
+  /// Decodes every level on the CPU, for adapters that can't sample the
+  /// format. Single and two channel formats end up in red and green, the way
+  /// they'd be sampled.
+  ///
+  /// This is `None` for the formats we can't decode, which are BC6H and the
+  /// signed versions of BC4 and BC5.
+  pub fn decompress(&self) -> Option<Vec<RgbaImage>> {
+    let decode_block: fn(&[u8]) -> bc_decode::Texels = match self.format {
+      Format::Bc1RgbUnorm | Format::Bc1RgbSrgb => |block| bc_decode::bc1(block, false),
+      Format::Bc1RgbaUnorm | Format::Bc1RgbaSrgb => |block| bc_decode::bc1(block, true),
+      Format::Bc2Unorm | Format::Bc2Srgb => bc_decode::bc2,
+      Format::Bc3Unorm | Format::Bc3Srgb => bc_decode::bc3,
+      Format::Bc4Unorm => bc_decode::bc4,
+      Format::Bc5Unorm => bc_decode::bc5,
+      Format::Bc7Unorm | Format::Bc7Srgb => bc_decode::bc7,
+      _ => return None,
+    };
+    let block_size = block_bytes(self.format);
+    let decoded = self
+      .levels
+      .iter()
+      .enumerate()
+      .map(|(level, blocks)| {
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+        let blocks_wide = (width as usize + 3) / 4;
+        let mut img = RgbaImage::new(width, height);
+        for (index, block) in blocks.chunks(block_size).enumerate() {
+          let texels = decode_block(block);
+          let (bx, by) = (
+            (index % blocks_wide) as u32 * 4,
+            (index / blocks_wide) as u32 * 4,
+          );
+          for (i, &texel) in texels.iter().enumerate() {
+            let (x, y) = (bx + i as u32 % 4, by + i as u32 / 4);
+            // edge blocks hang off the side of levels that aren't a multiple
+            // of 4 in size
+            if x < width && y < height {
+              img.put_pixel(x, y, Rgba { data: texel });
+            }
+          }
+        }
+        img
+      })
+      .collect();
+    Some(decoded)
+  }
+
+  /// The uncompressed format that `decompress` gives texels for.
+  pub fn decompressed_format(&self) -> Format {
+    match self.format {
+      Format::Bc1RgbSrgb
+      | Format::Bc1RgbaSrgb
+      | Format::Bc2Srgb
+      | Format::Bc3Srgb
+      | Format::Bc7Srgb => Format::Rgba8Srgb,
+      _ => Format::Rgba8Unorm,
+    }
+  }
+}
+
+/// How many bytes one 4x4 block of a BC format takes.
+pub(crate) fn block_bytes(format: Format) -> usize {
+  match format {
+    Format::Bc1RgbUnorm
+    | Format::Bc1RgbSrgb
+    | Format::Bc1RgbaUnorm
+    | Format::Bc1RgbaSrgb
+    | Format::Bc4Unorm
+    | Format::Bc4Inorm => 8,
+    _ => 16,
+  }
+}
+
+/// How many bytes a level of the given size takes, counting partial blocks
+/// along the edges as whole ones.
+fn level_size(format: Format, width: u32, height: u32) -> usize {
+  let blocks_wide = (width.max(1) as usize + 3) / 4;
+  let blocks_high = (height.max(1) as usize + 3) / 4;
+  blocks_wide * blocks_high * block_bytes(format)
+}
+
+/// Halving the size can only go on until it's 1x1, so more levels than that
+/// means the header is damaged, and we don't allocate room for them.
+fn check_level_count(width: u32, height: u32, level_count: u32) -> Result<(), ContainerError> {
+  if level_count > 32 - width.max(height).leading_zeros() {
+    return Err(ContainerError::TooManyLevels(level_count));
+  }
+  Ok(())
+}
+
+fn slice(bytes: &[u8], offset: usize, size: usize) -> Result<&[u8], ContainerError> {
+  offset
+    .checked_add(size)
+    .and_then(|end| bytes.get(offset..end))
+    .ok_or(ContainerError::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u32, ContainerError> {
+  let mut word = [0; 4];
+  word.copy_from_slice(slice(bytes, offset, 4)?);
+  Ok(if big_endian {
+    u32::from_be_bytes(word)
+  } else {
+    u32::from_le_bytes(word)
+  })
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ContainerError> {
+  let mut word = [0; 8];
+  word.copy_from_slice(slice(bytes, offset, 8)?);
+  Ok(u64::from_le_bytes(word))
+}
+
+fn dxgi_to_format(dxgi_format: u32) -> Result<Format, ContainerError> {
+  Ok(match dxgi_format {
+    71 => Format::Bc1RgbaUnorm,
+    72 => Format::Bc1RgbaSrgb,
+    74 => Format::Bc2Unorm,
+    75 => Format::Bc2Srgb,
+    77 => Format::Bc3Unorm,
+    78 => Format::Bc3Srgb,
+    80 => Format::Bc4Unorm,
+    81 => Format::Bc4Inorm,
+    83 => Format::Bc5Unorm,
+    84 => Format::Bc5Inorm,
+    95 => Format::Bc6hUfloat,
+    96 => Format::Bc6hFloat,
+    98 => Format::Bc7Unorm,
+    99 => Format::Bc7Srgb,
+    _ => return Err(ContainerError::UnsupportedFormat(dxgi_format)),
+  })
+}
+
+fn four_cc_to_format(four_cc: u32) -> Result<Format, ContainerError> {
+  Ok(match &four_cc.to_le_bytes() {
+    b"DXT1" => Format::Bc1RgbaUnorm,
+    b"DXT2" | b"DXT3" => Format::Bc2Unorm,
+    b"DXT4" | b"DXT5" => Format::Bc3Unorm,
+    b"ATI1" | b"BC4U" => Format::Bc4Unorm,
+    b"BC4S" => Format::Bc4Inorm,
+    b"ATI2" | b"BC5U" => Format::Bc5Unorm,
+    b"BC5S" => Format::Bc5Inorm,
+    _ => return Err(ContainerError::UnsupportedFormat(four_cc)),
+  })
+}
+
+fn gl_to_format(gl_internal_format: u32) -> Result<Format, ContainerError> {
+  Ok(match gl_internal_format {
+    0x83F0 => Format::Bc1RgbUnorm,
+    0x83F1 => Format::Bc1RgbaUnorm,
+    0x83F2 => Format::Bc2Unorm,
+    0x83F3 => Format::Bc3Unorm,
+    0x8C4C => Format::Bc1RgbSrgb,
+    0x8C4D => Format::Bc1RgbaSrgb,
+    0x8C4E => Format::Bc2Srgb,
+    0x8C4F => Format::Bc3Srgb,
+    0x8DBB => Format::Bc4Unorm,
+    0x8DBC => Format::Bc4Inorm,
+    0x8DBD => Format::Bc5Unorm,
+    0x8DBE => Format::Bc5Inorm,
+    0x8E8C => Format::Bc7Unorm,
+    0x8E8D => Format::Bc7Srgb,
+    0x8E8E => Format::Bc6hFloat,
+    0x8E8F => Format::Bc6hUfloat,
+    _ => return Err(ContainerError::UnsupportedFormat(gl_internal_format)),
+  })
+}
+
+fn vk_to_format(vk_format: u32) -> Result<Format, ContainerError> {
+  Ok(match vk_format {
+    131 => Format::Bc1RgbUnorm,
+    132 => Format::Bc1RgbSrgb,
+    133 => Format::Bc1RgbaUnorm,
+    134 => Format::Bc1RgbaSrgb,
+    135 => Format::Bc2Unorm,
+    136 => Format::Bc2Srgb,
+    137 => Format::Bc3Unorm,
+    138 => Format::Bc3Srgb,
+    139 => Format::Bc4Unorm,
+    140 => Format::Bc4Inorm,
+    141 => Format::Bc5Unorm,
+    142 => Format::Bc5Inorm,
+    143 => Format::Bc6hUfloat,
+    144 => Format::Bc6hFloat,
+    145 => Format::Bc7Unorm,
+    146 => Format::Bc7Srgb,
+    _ => return Err(ContainerError::UnsupportedFormat(vk_format)),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn put_u32(bytes: &mut Vec<u8>, offset: usize, value: u32) {
+    if bytes.len() < offset + 4 {
+      bytes.resize(offset + 4, 0);
+    }
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+  }
+
+  /// A DXT1 file of the size given, with `mip_count` in the header and room
+  /// for `blocks` blocks of data.
+  fn dds(width: u32, height: u32, mip_count: u32, blocks: usize) -> Vec<u8> {
+    let mut bytes = b"DDS ".to_vec();
+    put_u32(&mut bytes, 4, 124);
+    put_u32(&mut bytes, 8, 0x2_1007);
+    put_u32(&mut bytes, 12, height);
+    put_u32(&mut bytes, 16, width);
+    put_u32(&mut bytes, 28, mip_count);
+    put_u32(&mut bytes, 84, u32::from_le_bytes(*b"DXT1"));
+    bytes.resize(128 + 8 * blocks, 0);
+    bytes
+  }
+
+  /// A little endian KTX file with a single level of `internal_format` blocks.
+  fn ktx(internal_format: u32, width: u32, height: u32, mip_count: u32) -> Vec<u8> {
+    let mut bytes = KTX_IDENTIFIER.to_vec();
+    put_u32(&mut bytes, 12, 0x0403_0201);
+    put_u32(&mut bytes, 28, internal_format);
+    put_u32(&mut bytes, 36, width);
+    put_u32(&mut bytes, 40, height);
+    put_u32(&mut bytes, 56, mip_count);
+    put_u32(&mut bytes, 60, 0);
+    let size = level_size(gl_to_format(internal_format).unwrap(), width, height);
+    put_u32(&mut bytes, 64, size as u32);
+    bytes.resize(68 + size, 0);
+    bytes
+  }
+
+  /// A KTX2 file header for BC7 whose level index has the given offsets.
+  fn ktx2(width: u32, height: u32, level_offsets: &[u64]) -> Vec<u8> {
+    let mut bytes = KTX2_IDENTIFIER.to_vec();
+    put_u32(&mut bytes, 12, 145);
+    put_u32(&mut bytes, 20, width);
+    put_u32(&mut bytes, 24, height);
+    put_u32(&mut bytes, 40, level_offsets.len() as u32);
+    put_u32(&mut bytes, 44, 0);
+    bytes.resize(80, 0);
+    for &offset in level_offsets {
+      bytes.extend_from_slice(&offset.to_le_bytes());
+      bytes.extend_from_slice(&[0; 16]);
+    }
+    bytes
+  }
+
+  #[test]
+  fn dds_levels_are_read_one_after_the_other() {
+    // 8x8 is 4 blocks, then 4x4, 2x2, and 1x1 are one block each
+    let texture = CompressedTexture::from_bytes(&dds(8, 8, 4, 7)).unwrap();
+    assert_eq!(texture.format, Format::Bc1RgbaUnorm);
+    let sizes: Vec<usize> = texture.levels.iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![32, 8, 8, 8]);
+  }
+
+  #[test]
+  fn too_many_levels() {
+    assert_eq!(
+      CompressedTexture::from_bytes(&dds(8, 8, 5, 8)).unwrap_err(),
+      ContainerError::TooManyLevels(5)
+    );
+    assert_eq!(
+      CompressedTexture::from_bytes(&dds(8, 8, u32::MAX, 8)).unwrap_err(),
+      ContainerError::TooManyLevels(u32::MAX)
+    );
+    assert_eq!(
+      CompressedTexture::from_bytes(&ktx(0x83F1, 4, 4, 4)).unwrap_err(),
+      ContainerError::TooManyLevels(4)
+    );
+    assert_eq!(
+      CompressedTexture::from_bytes(&ktx2(1, 1, &[0; 2])).unwrap_err(),
+      ContainerError::TooManyLevels(2)
+    );
+  }
+
+  #[test]
+  fn truncated_files() {
+    let dds_bytes = dds(8, 8, 4, 7);
+    for len in &[4, 100, 128, dds_bytes.len() - 1] {
+      assert_eq!(
+        CompressedTexture::from_bytes(&dds_bytes[..*len]).unwrap_err(),
+        ContainerError::Truncated,
+        "cut at {}",
+        len
+      );
+    }
+    let ktx_bytes = ktx(0x8E8C, 8, 8, 1);
+    assert_eq!(
+      CompressedTexture::from_bytes(&ktx_bytes[..ktx_bytes.len() - 1]).unwrap_err(),
+      ContainerError::Truncated
+    );
+    assert_eq!(
+      CompressedTexture::from_bytes(&ktx2(4, 4, &[200])).unwrap_err(),
+      ContainerError::Truncated
+    );
+    // an offset that would overflow when the level's size is added to it
+    assert_eq!(
+      CompressedTexture::from_bytes(&ktx2(4, 4, &[u64::MAX])).unwrap_err(),
+      ContainerError::Truncated
+    );
+  }
+
+  #[test]
+  fn ktx_cubemaps_skip_the_other_faces() {
+    // each level's size is just one face's, then all six faces follow
+    let mut bytes = ktx(0x83F1, 8, 8, 2);
+    bytes.truncate(64);
+    put_u32(&mut bytes, 52, 6);
+    for &(level, size) in [(0u8, 32usize), (1, 8)].iter() {
+      bytes.extend_from_slice(&(size as u32).to_le_bytes());
+      for face in 0..6 {
+        bytes.extend(core::iter::repeat(level * 16 + face).take(size));
+      }
+    }
+    let texture = CompressedTexture::from_bytes(&bytes).unwrap();
+    assert_eq!(texture.levels, vec![vec![0; 32], vec![16; 8]]);
+    // in a cubemap array the size covers every face of every layer
+    put_u32(&mut bytes, 48, 1);
+    put_u32(&mut bytes, 64, 6 * 32);
+    put_u32(&mut bytes, 64 + 4 + 6 * 32, 6 * 8);
+    let texture = CompressedTexture::from_bytes(&bytes).unwrap();
+    assert_eq!(texture.levels, vec![vec![0; 32], vec![16; 8]]);
+    assert_eq!(
+      CompressedTexture::from_bytes(&bytes[..bytes.len() - 8 * 6]).unwrap_err(),
+      ContainerError::Truncated
+    );
+  }
+
+  #[test]
+  fn ktx2_levels_come_from_the_index() {
+    let mut bytes = ktx2(4, 4, &[104]);
+    bytes.extend(0..16u8);
+    let texture = CompressedTexture::from_bytes(&bytes).unwrap();
+    assert_eq!(texture.format, Format::Bc7Unorm);
+    assert_eq!(texture.levels, vec![(0..16u8).collect::<Vec<_>>()]);
+  }
+
+  #[test]
+  fn unknown_containers_and_formats() {
+    assert_eq!(
+      CompressedTexture::from_bytes(b"\x89PNG\r\n\x1a\n").unwrap_err(),
+      ContainerError::UnknownContainer
+    );
+    let mut bytes = dds(4, 4, 1, 1);
+    put_u32(&mut bytes, 84, u32::from_le_bytes(*b"RGBG"));
+    assert_eq!(
+      CompressedTexture::from_bytes(&bytes).unwrap_err(),
+      ContainerError::UnsupportedFormat(u32::from_le_bytes(*b"RGBG"))
+    );
+  }
+
+  #[test]
+  fn decompress_does_every_level() {
+    // solid red at 8x8 and solid blue at 4x4, every index 0 so each texel is
+    // the block's first color
+    let mut bytes = dds(8, 8, 2, 0);
+    for _ in 0..4 {
+      bytes.extend_from_slice(&[0x00, 0xF8, 0, 0, 0, 0, 0, 0]);
+    }
+    bytes.extend_from_slice(&[0x1F, 0x00, 0, 0, 0, 0, 0, 0]);
+    let texture = CompressedTexture::from_bytes(&bytes).unwrap();
+    assert_eq!((texture.width, texture.height), (8, 8));
+    let levels = texture.decompress().unwrap();
+    assert_eq!(levels.len(), 2);
+    assert!(levels[0].pixels().all(|p| p.data == [255, 0, 0, 255]));
+    assert_eq!(levels[1].dimensions(), (4, 4));
+    assert!(levels[1].pixels().all(|p| p.data == [0, 0, 255, 255]));
+  }
+
+  #[test]
+  fn bc6h_isnt_decompressed() {
+    let texture = CompressedTexture::from_bytes(&ktx(0x8E8F, 4, 4, 1)).unwrap();
+    assert_eq!(texture.format, Format::Bc6hUfloat);
+    assert!(texture.decompress().is_none());
+  }
+}
@@ -0,0 +1,396 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  AdapterPreference, ColorSpace, HalError, SamplerDesc, ShaderSources, ShaderVariant, SpirvCache,
+  TextureDesc,
+};
+use gfx_hal::{
+  adapter::{Adapter, AdapterInfo, PhysicalDevice},
+  format::{Format, ImageFeature},
+  image::{NumSamples, WrapMode},
+  window::{CompositeAlpha, PresentMode},
+  Backend,
+};
+use std::path::PathBuf;
+
+/// The present modes that get tried after the requested ones, best first.
+const DEFAULT_PRESENT_MODES: [PresentMode; 4] = [
+  PresentMode::Mailbox,
+  PresentMode::Fifo,
+  PresentMode::Relaxed,
+  PresentMode::Immediate,
+];
+
+/// The composite alphas that get tried after the requested ones.
+const DEFAULT_COMPOSITE_ALPHAS: [CompositeAlpha; 4] = [
+  CompositeAlpha::Opaque,
+  CompositeAlpha::Inherit,
+  CompositeAlpha::PreMultiplied,
+  CompositeAlpha::PostMultiplied,
+];
+
+/// The offscreen color formats that get tried after the requested ones.
+const DEFAULT_OFFSCREEN_FORMATS: [Format; 3] =
+  [Format::Rgba8Srgb, Format::Bgra8Srgb, Format::Rgba8Unorm];
+
+/// The depth formats that get tried after the requested ones.
+const DEFAULT_DEPTH_FORMATS: [Format; 4] = [
+  Format::D32Float,
+  Format::D32FloatS8Uint,
+  Format::D24UnormS8Uint,
+  Format::D16Unorm,
+];
+
+/// How a `HalState` should be set up.
+///
+/// Everything in here is a request. When the adapter or surface can't do what
+/// was asked for, the next option in the list is tried, then the defaults. The
+/// [`ChosenConfig`] of a `HalState` tells you what was actually picked.
+///
+/// ```no_run
+/// # use learn_gfx_hal::{AdapterPreference, HalConfig};
+/// let config = HalConfig::default()
+///   .adapter(AdapterPreference::PreferDiscrete)
+///   .vsync(false)
+///   .frames_in_flight(3)
+///   .samples(4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HalConfig {
+  pub(crate) adapter: AdapterPreference,
+  pub(crate) present_modes: Vec<PresentMode>,
+  pub(crate) composite_alphas: Vec<CompositeAlpha>,
+  pub(crate) formats: Vec<Format>,
+  pub(crate) frames_in_flight: Option<usize>,
+  pub(crate) use_depth: bool,
+  pub(crate) depth_formats: Vec<Format>,
+  pub(crate) samples: NumSamples,
+  pub(crate) transfer_queue: bool,
+  pub(crate) mipmaps: bool,
+  pub(crate) texture_sampler: Option<SamplerDesc>,
+  pub(crate) shaders: ShaderSources,
+  pub(crate) spirv_cache: Option<SpirvCache>,
+  pub(crate) shader_variant: ShaderVariant,
+}
+impl Default for HalConfig {
+  fn default() -> Self {
+    Self {
+      adapter: AdapterPreference::default(),
+      present_modes: Vec::new(),
+      composite_alphas: Vec::new(),
+      formats: Vec::new(),
+      frames_in_flight: None,
+      use_depth: true,
+      depth_formats: Vec::new(),
+      samples: 1,
+      transfer_queue: false,
+      mipmaps: false,
+      texture_sampler: None,
+      shaders: ShaderSources::default(),
+      spirv_cache: None,
+      shader_variant: ShaderVariant::default(),
+    }
+  }
+}
+impl HalConfig {
+  /// Which adapter to use.
+  pub fn adapter(mut self, preference: AdapterPreference) -> Self {
+    self.adapter = preference;
+    self
+  }
+
+  /// With vsync on we wait for the display (`Fifo` first), with it off we
+  /// don't (`Immediate` first, then `Mailbox`).
+  pub fn vsync(mut self, vsync: bool) -> Self {
+    self.present_modes = if vsync {
+      vec![
+        PresentMode::Fifo,
+        PresentMode::Mailbox,
+        PresentMode::Relaxed,
+      ]
+    } else {
+      vec![PresentMode::Immediate, PresentMode::Mailbox]
+    };
+    self
+  }
+
+  /// Asks for one particular present mode.
+  pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+    self.present_modes = vec![present_mode];
+    self
+  }
+
+  /// The present modes to try, best first.
+  pub fn present_modes(mut self, present_modes: Vec<PresentMode>) -> Self {
+    self.present_modes = present_modes;
+    self
+  }
+
+  /// The composite alphas to try, best first.
+  pub fn composite_alphas(mut self, composite_alphas: Vec<CompositeAlpha>) -> Self {
+    self.composite_alphas = composite_alphas;
+    self
+  }
+
+  /// The color formats to try, best first. If none of them work we take an
+  /// sRGB format if there is one.
+  pub fn formats(mut self, formats: Vec<Format>) -> Self {
+    self.formats = formats;
+    self
+  }
+
+  /// How many frames can be worked on at once. With a swapchain this is the
+  /// number of images we ask for, which the surface might not allow.
+  pub fn frames_in_flight(mut self, frames_in_flight: usize) -> Self {
+    self.frames_in_flight = Some(frames_in_flight.max(1));
+    self
+  }
+
+  /// If there should be a depth buffer at all.
+  pub fn depth(mut self, use_depth: bool) -> Self {
+    self.use_depth = use_depth;
+    self
+  }
+
+  /// The depth formats to try, best first. This also turns the depth buffer on.
+  pub fn depth_formats(mut self, depth_formats: Vec<Format>) -> Self {
+    self.use_depth = true;
+    self.depth_formats = depth_formats;
+    self
+  }
+
+  /// How many samples per pixel to use for MSAA. If the adapter can't do that
+  /// many we use the most it can do below that, and 1 turns MSAA off.
+  pub fn samples(mut self, samples: NumSamples) -> Self {
+    self.samples = samples.max(1);
+    self
+  }
+
+  /// If uploads should use a queue family that only does transfers, when the
+  /// adapter has one. Resources then have their ownership handed over to the
+  /// graphics queue family once they're uploaded.
+  pub fn transfer_queue(mut self, transfer_queue: bool) -> Self {
+    self.transfer_queue = transfer_queue;
+    self
+  }
+
+  /// If textures should get a full mip chain and trilinear filtering. The
+  /// smaller levels are made on the GPU when the adapter can blit the texture
+  /// format, and on the CPU otherwise.
+  pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+    self.mipmaps = mipmaps;
+    self
+  }
+
+  /// How the cube texture gets sampled. Without this it's nearest filtering,
+  /// or trilinear filtering when there are mipmaps.
+  pub fn texture_sampler(mut self, texture_sampler: SamplerDesc) -> Self {
+    self.texture_sampler = Some(texture_sampler);
+    self
+  }
+
+  /// Where the cube shaders come from. Shaders in files get reloaded when the
+  /// files change, see `HalState::reload_changed_shaders`. GLSL needs the
+  /// `runtime-shaders` feature, unless it's already in the `spirv_cache`.
+  pub fn shaders(mut self, shaders: ShaderSources) -> Self {
+    self.shaders = shaders;
+    self
+  }
+
+  /// Keeps the SPIR-V of any GLSL shaders in this directory, so that they're
+  /// only compiled the first time they're used. The default shaders are
+  /// compiled ahead of time and don't need it.
+  pub fn spirv_cache<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+    self.spirv_cache = Some(SpirvCache::new(dir));
+    self
+  }
+
+  /// The variant of the shaders to start with, see
+  /// `HalState::set_shader_variant`.
+  pub fn shader_variant(mut self, variant: ShaderVariant) -> Self {
+    self.shader_variant = variant;
+    self
+  }
+
+  /// How the cube texture should be made.
+  pub(crate) fn texture_desc(&self) -> TextureDesc {
+    TextureDesc {
+      color_space: ColorSpace::Srgb,
+      mipmaps: self.mipmaps,
+      sampler: match &self.texture_sampler {
+        Some(desc) => desc.clone(),
+        None if self.mipmaps => SamplerDesc::trilinear(WrapMode::Tile),
+        None => SamplerDesc::default(),
+      },
+    }
+  }
+
+  /// The first present mode that's supported, going through the requested
+  /// ones and then the defaults.
+  pub(crate) fn pick_present_mode(&self, supported: &[PresentMode]) -> Option<PresentMode> {
+    pick(&self.present_modes, &DEFAULT_PRESENT_MODES, |pm| {
+      supported.contains(&pm)
+    })
+  }
+
+  /// The first composite alpha that's supported, going through the requested
+  /// ones and then the defaults.
+  pub(crate) fn pick_composite_alpha(
+    &self, supported: &[CompositeAlpha],
+  ) -> Option<CompositeAlpha> {
+    pick(&self.composite_alphas, &DEFAULT_COMPOSITE_ALPHAS, |ca| {
+      supported.contains(&ca)
+    })
+  }
+
+  /// The first requested format that the surface can use, or `None` if it
+  /// can't use any of them.
+  ///
+  /// A surface that doesn't list any formats can use anything.
+  pub(crate) fn pick_surface_format(&self, supported: Option<&[Format]>) -> Option<Format> {
+    pick(&self.formats, &[], |format| {
+      supported.map_or(true, |formats| formats.contains(&format))
+    })
+  }
+
+  /// The first color format that we can render into offscreen.
+  pub(crate) fn pick_offscreen_format<B: Backend>(
+    &self, adapter: &Adapter<B>,
+  ) -> Result<Format, HalError> {
+    pick(&self.formats, &DEFAULT_OFFSCREEN_FORMATS, |format| {
+      supports(adapter, format, ImageFeature::COLOR_ATTACHMENT)
+    })
+    .ok_or(HalError::NoSuitableFormat("offscreen color"))
+  }
+
+  /// The first depth format that can be used as a depth attachment, or `None`
+  /// if we're not using depth.
+  pub(crate) fn pick_depth_format<B: Backend>(
+    &self, adapter: &Adapter<B>,
+  ) -> Result<Option<Format>, HalError> {
+    if !self.use_depth {
+      return Ok(None);
+    }
+    pick(&self.depth_formats, &DEFAULT_DEPTH_FORMATS, |format| {
+      supports(adapter, format, ImageFeature::DEPTH_STENCIL_ATTACHMENT)
+    })
+    .map(Some)
+    .ok_or(HalError::NoSuitableFormat("depth"))
+  }
+
+  /// The most samples per pixel, no more than requested, that the color (and
+  /// depth, if we're using it) attachments can have.
+  pub(crate) fn pick_samples<B: Backend>(&self, adapter: &Adapter<B>) -> NumSamples {
+    let limits = adapter.physical_device.limits();
+    // These are bit masks of the supported sample counts.
+    let mut supported = limits.framebuffer_color_samples_count;
+    if self.use_depth {
+      supported &= limits.framebuffer_depth_samples_count;
+    }
+    most_samples(self.samples, supported)
+  }
+}
+
+/// What a `HalState` actually ended up using, after all the fallbacks.
+#[derive(Debug, Clone)]
+pub struct ChosenConfig {
+  pub adapter: AdapterInfo,
+  /// This is `None` when drawing offscreen, since nothing gets presented.
+  pub present_mode: Option<PresentMode>,
+  /// This is `None` when drawing offscreen, since nothing gets presented.
+  pub composite_alpha: Option<CompositeAlpha>,
+  pub format: Format,
+  pub frames_in_flight: usize,
+  pub depth_format: Option<Format>,
+  pub samples: NumSamples,
+  /// If uploads run on a dedicated transfer queue.
+  pub transfer_queue: bool,
+}
+
+/// The first of the requested values, then the defaults, that passes the test.
+fn pick<T: Copy, F: Fn(T) -> bool>(requested: &[T], defaults: &[T], test: F) -> Option<T> {
+  requested
+    .iter()
+    .chain(defaults.iter())
+    .cloned()
+    .find(|&t| test(t))
+}
+
+/// The biggest power of two, no more than `requested`, that's in the bit mask
+/// of `supported` sample counts. Single sampling always works.
+fn most_samples(requested: NumSamples, supported: NumSamples) -> NumSamples {
+  let mut samples = requested.next_power_of_two();
+  if samples > requested {
+    samples /= 2;
+  }
+  while samples > 1 && supported & samples == 0 {
+    samples /= 2;
+  }
+  samples
+}
+
+/// If images of the format can have the feature with optimal tiling.
+fn supports<B: Backend>(adapter: &Adapter<B>, format: Format, feature: ImageFeature) -> bool {
+  adapter
+    .physical_device
+    .format_properties(Some(format))
+    .optimal_tiling
+    .contains(feature)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn requested_values_come_before_the_defaults() {
+    let config =
+      HalConfig::default().present_modes(vec![PresentMode::Immediate, PresentMode::Fifo]);
+    let all = &DEFAULT_PRESENT_MODES;
+    assert_eq!(config.pick_present_mode(all), Some(PresentMode::Immediate));
+    assert_eq!(
+      config.pick_present_mode(&[PresentMode::Fifo, PresentMode::Relaxed]),
+      Some(PresentMode::Fifo)
+    );
+    // none of the requested ones, so it's the best of the defaults
+    assert_eq!(
+      config.pick_present_mode(&[PresentMode::Relaxed, PresentMode::Mailbox]),
+      Some(PresentMode::Mailbox)
+    );
+    assert_eq!(config.pick_present_mode(&[]), None);
+    assert_eq!(
+      HalConfig::default().pick_composite_alpha(&[CompositeAlpha::PostMultiplied]),
+      Some(CompositeAlpha::PostMultiplied)
+    );
+  }
+
+  #[test]
+  fn surface_formats_have_no_defaults() {
+    let config = HalConfig::default().formats(vec![Format::Bgra8Unorm, Format::Rgba8Srgb]);
+    assert_eq!(config.pick_surface_format(None), Some(Format::Bgra8Unorm));
+    assert_eq!(
+      config.pick_surface_format(Some(&[Format::Rgba8Srgb, Format::Bgra8Unorm])),
+      Some(Format::Bgra8Unorm)
+    );
+    assert_eq!(
+      config.pick_surface_format(Some(&[Format::Rgba8Srgb])),
+      Some(Format::Rgba8Srgb)
+    );
+    assert_eq!(
+      config.pick_surface_format(Some(&[Format::Rgba16Unorm])),
+      None
+    );
+    assert_eq!(HalConfig::default().pick_surface_format(None), None);
+  }
+
+  #[test]
+  fn samples_round_down_to_a_supported_count() {
+    assert_eq!(most_samples(4, 0b1111), 4);
+    assert_eq!(most_samples(6, 0b1111), 4);
+    assert_eq!(most_samples(8, 0b0011), 2);
+    assert_eq!(most_samples(16, 0b0101), 4);
+    assert_eq!(most_samples(4, 0), 1);
+    assert_eq!(most_samples(1, 0b1111), 1);
+  }
+}
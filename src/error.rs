@@ -0,0 +1,291 @@
+use core::fmt;
+use gfx_hal::{
+  buffer, device,
+  error::{DeviceCreationError, HostExecutionError},
+  image::{CreationError as ImageCreationError, ViewError},
+  mapping, pso, window,
+};
+
+/// Everything that can go wrong while setting up or using a `HalState`.
+///
+/// Whenever gfx-hal, shaderc, or the OS gave us an error value it's kept
+/// inside the variant, so you can match on exactly what happened. The gfx-hal
+/// errors are `failure::Fail` types rather than `std::error::Error` types, so
+/// only the shaderc and IO errors show up as a `source`, but all of them are in
+/// the `Display` output.
+#[derive(Debug)]
+pub enum HalError {
+  /// None of the adapters could do graphics (and present to the surface, if
+  /// there is one).
+  NoSuitableAdapter,
+  /// The adapter doesn't have a queue family that passes our filter.
+  NoSuitableQueueFamily,
+  /// Opening the physical device failed.
+  DeviceCreation(DeviceCreationError),
+  /// The device was opened but didn't give us any usable queues.
+  NoQueues,
+  /// The surface can't be used the way we need, for the reason given.
+  SurfaceIncompatible(&'static str),
+  /// The window we were asked to draw to doesn't exist any more.
+  WindowClosed,
+  /// Creating the swapchain failed.
+  SwapchainCreation(window::CreationError),
+  /// The swapchain gave us a framebuffer instead of images, and we can only
+  /// draw into images.
+  UnsupportedBackbuffer,
+  /// The host or the device ran out of memory.
+  OutOfMemory(device::OutOfMemory),
+  /// None of the memory types fit a buffer or image with the properties we
+  /// wanted.
+  NoSuitableMemoryType,
+  /// A buffer or image asked for 0 bytes of memory.
+  EmptyAllocation,
+  /// Allocating memory (or an object that needs memory) failed.
+  Allocation(device::AllocationError),
+  /// Binding memory to a buffer or image failed.
+  Bind(device::BindError),
+  /// Creating a buffer failed.
+  BufferCreation(buffer::CreationError),
+  /// Creating an image failed.
+  ImageCreation(ImageCreationError),
+  /// Creating an image view failed.
+  ImageViewCreation(ViewError),
+  /// Mapping memory so the CPU can see it failed.
+  Mapping(mapping::Error),
+  /// A shader has to be compiled, but shaderc isn't available. Either the
+  /// `runtime-shaders` feature is off or shaderc couldn't be started.
+  ShaderCompilerMissing,
+  /// A shader file couldn't be read.
+  ShaderRead {
+    path: std::path::PathBuf,
+    source: std::io::Error,
+  },
+  /// shaderc couldn't compile a shader. The name is the one we gave shaderc,
+  /// and the error has shaderc's message.
+  #[cfg(feature = "runtime-shaders")]
+  ShaderCompilation {
+    name: String,
+    source: shaderc::Error,
+  },
+  /// The compiled SPIR-V couldn't be made into a shader module.
+  ShaderModule(device::ShaderError),
+  /// Allocating a descriptor set from the pool failed.
+  DescriptorAllocation(pso::AllocationError),
+  /// The shaders can't be reflected, or don't fit the vertex layout or the
+  /// pipeline layout.
+  Reflection(crate::ReflectError),
+  /// Creating the graphics pipeline failed.
+  PipelineCreation(pso::CreationError),
+  /// The swapchain doesn't match the surface any more and has to be recreated.
+  OutOfDate,
+  /// Acquiring a swapchain image failed for some other reason.
+  Acquire(window::AcquireError),
+  /// Presenting to the swapchain failed. gfx-hal doesn't tell us why.
+  PresentFailed,
+  /// The device was lost, everything made from it is now useless.
+  DeviceLost(device::DeviceLost),
+  /// Waiting for the device to be idle failed.
+  HostExecution(HostExecutionError),
+  /// A frame was asked for before any frame was drawn.
+  NoFrameDrawn,
+  /// A swapchain frame was asked for, but none was drawn since
+  /// `capture_next_frame`.
+  NoFrameCaptured,
+  /// The swapchain's images can't be copied from, because the surface doesn't
+  /// allow `Usage::TRANSFER_SRC`.
+  CaptureUnsupported,
+  /// The format can't be used for what it was asked to do.
+  UnsupportedFormat(gfx_hal::format::Format),
+  /// None of the formats we tried can be used for this kind of attachment.
+  NoSuitableFormat(&'static str),
+  /// The sources for a layered texture don't fit together, for the reason
+  /// given.
+  InvalidTextureLayers(&'static str),
+  /// The GL backend couldn't make a context.
+  #[cfg(feature = "gl")]
+  GlContext(crate::back::glutin::CreationError),
+  /// glutin needs an X11 or Wayland display to make even a headless GL
+  /// context, and there isn't one.
+  #[cfg(feature = "gl")]
+  GlNoDisplay,
+}
+
+impl fmt::Display for HalError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    use HalError::*;
+    match self {
+      NoSuitableAdapter => write!(f, "Couldn't find a graphical Adapter!"),
+      NoSuitableQueueFamily => write!(f, "Couldn't find a QueueFamily with graphics!"),
+      DeviceCreation(e) => write!(f, "Couldn't open the PhysicalDevice: {:?}", e),
+      NoQueues => write!(
+        f,
+        "The QueueGroup did not have any CommandQueues available!"
+      ),
+      SurfaceIncompatible(why) => write!(f, "The Surface can't be used: {}", why),
+      WindowClosed => write!(f, "Window doesn't exist!"),
+      SwapchainCreation(e) => write!(f, "Failed to create the swapchain: {:?}", e),
+      UnsupportedBackbuffer => write!(f, "Can't handle a framebuffer backbuffer!"),
+      OutOfMemory(e) => write!(f, "Out of memory: {:?}", e),
+      NoSuitableMemoryType => write!(f, "Couldn't find a memory type with the right properties!"),
+      EmptyAllocation => write!(f, "Can't allocate 0 bytes of memory!"),
+      Allocation(e) => write!(f, "Couldn't allocate: {:?}", e),
+      Bind(e) => write!(f, "Couldn't bind memory: {:?}", e),
+      BufferCreation(e) => write!(f, "Couldn't create a buffer: {:?}", e),
+      ImageCreation(e) => write!(f, "Couldn't create an image: {:?}", e),
+      ImageViewCreation(e) => write!(f, "Couldn't create an image view: {:?}", e),
+      Mapping(e) => write!(f, "Couldn't map memory: {:?}", e),
+      ShaderCompilerMissing => write!(f, "shaderc not found!"),
+      ShaderRead { path, source } => write!(f, "Couldn't read {}: {}", path.display(), source),
+      #[cfg(feature = "runtime-shaders")]
+      ShaderCompilation { name, source } => write!(f, "Couldn't compile {}: {}", name, source),
+      ShaderModule(e) => write!(f, "Couldn't make a shader module: {:?}", e),
+      DescriptorAllocation(e) => write!(f, "Couldn't make a Descriptor Set: {:?}", e),
+      Reflection(e) => write!(f, "The shaders don't fit the pipeline: {}", e),
+      PipelineCreation(e) => write!(f, "Couldn't create a graphics pipeline: {:?}", e),
+      OutOfDate => write!(f, "The swapchain is out of date!"),
+      Acquire(e) => write!(f, "Couldn't acquire an image from the swapchain: {:?}", e),
+      PresentFailed => write!(f, "Failed to present into the swapchain!"),
+      DeviceLost(e) => write!(f, "The device was lost: {:?}", e),
+      HostExecution(e) => write!(f, "Couldn't wait for the device to be idle: {:?}", e),
+      NoFrameDrawn => write!(f, "No frame has been drawn yet!"),
+      NoFrameCaptured => write!(f, "No frame was drawn since capture_next_frame!"),
+      CaptureUnsupported => write!(f, "The swapchain's images can't be copied from!"),
+      UnsupportedFormat(format) => write!(f, "The format {:?} isn't supported here!", format),
+      NoSuitableFormat(what) => write!(f, "Couldn't find a usable {} format!", what),
+      InvalidTextureLayers(why) => write!(f, "The texture's layers can't be used: {}", why),
+      #[cfg(feature = "gl")]
+      GlContext(e) => write!(f, "Couldn't create a headless GL context: {}", e),
+      #[cfg(feature = "gl")]
+      GlNoDisplay => write!(f, "Couldn't connect to an X11 or Wayland display for GL!"),
+    }
+  }
+}
+
+impl std::error::Error for HalError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      #[cfg(feature = "runtime-shaders")]
+      HalError::ShaderCompilation { source, .. } => Some(source),
+      HalError::ShaderRead { source, .. } => Some(source),
+      HalError::Reflection(e) => Some(e),
+      #[cfg(feature = "gl")]
+      HalError::GlContext(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<DeviceCreationError> for HalError {
+  fn from(e: DeviceCreationError) -> Self {
+    HalError::DeviceCreation(e)
+  }
+}
+
+impl From<window::CreationError> for HalError {
+  fn from(e: window::CreationError) -> Self {
+    HalError::SwapchainCreation(e)
+  }
+}
+
+impl From<device::OutOfMemory> for HalError {
+  fn from(e: device::OutOfMemory) -> Self {
+    HalError::OutOfMemory(e)
+  }
+}
+
+impl From<device::OomOrDeviceLost> for HalError {
+  fn from(e: device::OomOrDeviceLost) -> Self {
+    match e {
+      device::OomOrDeviceLost::OutOfMemory(e) => HalError::OutOfMemory(e),
+      device::OomOrDeviceLost::DeviceLost(e) => HalError::DeviceLost(e),
+    }
+  }
+}
+
+impl From<device::DeviceLost> for HalError {
+  fn from(e: device::DeviceLost) -> Self {
+    HalError::DeviceLost(e)
+  }
+}
+
+impl From<HostExecutionError> for HalError {
+  fn from(e: HostExecutionError) -> Self {
+    HalError::HostExecution(e)
+  }
+}
+
+impl From<device::AllocationError> for HalError {
+  fn from(e: device::AllocationError) -> Self {
+    HalError::Allocation(e)
+  }
+}
+
+impl From<device::BindError> for HalError {
+  fn from(e: device::BindError) -> Self {
+    HalError::Bind(e)
+  }
+}
+
+impl From<buffer::CreationError> for HalError {
+  fn from(e: buffer::CreationError) -> Self {
+    HalError::BufferCreation(e)
+  }
+}
+
+impl From<ImageCreationError> for HalError {
+  fn from(e: ImageCreationError) -> Self {
+    HalError::ImageCreation(e)
+  }
+}
+
+impl From<ViewError> for HalError {
+  fn from(e: ViewError) -> Self {
+    HalError::ImageViewCreation(e)
+  }
+}
+
+impl From<mapping::Error> for HalError {
+  fn from(e: mapping::Error) -> Self {
+    HalError::Mapping(e)
+  }
+}
+
+impl From<device::ShaderError> for HalError {
+  fn from(e: device::ShaderError) -> Self {
+    HalError::ShaderModule(e)
+  }
+}
+
+impl From<pso::AllocationError> for HalError {
+  fn from(e: pso::AllocationError) -> Self {
+    HalError::DescriptorAllocation(e)
+  }
+}
+
+impl From<crate::ReflectError> for HalError {
+  fn from(e: crate::ReflectError) -> Self {
+    HalError::Reflection(e)
+  }
+}
+
+impl From<pso::CreationError> for HalError {
+  fn from(e: pso::CreationError) -> Self {
+    HalError::PipelineCreation(e)
+  }
+}
+
+impl From<window::AcquireError> for HalError {
+  fn from(e: window::AcquireError) -> Self {
+    match e {
+      window::AcquireError::OutOfDate => HalError::OutOfDate,
+      e => HalError::Acquire(e),
+    }
+  }
+}
+
+#[cfg(feature = "gl")]
+impl From<crate::back::glutin::CreationError> for HalError {
+  fn from(e: crate::back::glutin::CreationError) -> Self {
+    HalError::GlContext(e)
+  }
+}
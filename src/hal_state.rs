@@ -0,0 +1,2287 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+#[cfg(feature = "windowed")]
+use crate::WINDOW_NAME;
+use crate::{
+  back, cast_slice, enumerate_adapters, select_adapter, shaders::CompiledShaders, AdapterReport,
+  Allocator, BufferBundle, ChosenConfig, ColorImage, DepthImage, DescriptorBinding, HalConfig,
+  HalError, LoadedImage, PipelineInterface, SamplerCache, ShaderVariant, ShaderWatcher, Uploader,
+  VertexLayout,
+};
+use arrayvec::ArrayVec;
+use core::{
+  any::Any,
+  mem::{size_of, ManuallyDrop},
+  ops::Deref,
+};
+use gfx_hal::{
+  adapter::{Adapter, PhysicalDevice},
+  buffer::{IndexBufferView, Usage as BufferUsage},
+  command::{
+    BufferImageCopy, ClearColor, ClearDepthStencil, ClearValue, CommandBuffer, MultiShot, OneShot,
+    Primary, Shot,
+  },
+  device::Device,
+  format::{Aspects, ChannelType, Format, SurfaceType, Swizzle},
+  image::{
+    Access as ImageAccess, Extent, Layout, NumSamples, Offset, SubresourceLayers, SubresourceRange,
+    Usage, ViewKind,
+  },
+  memory::{Barrier, Dependencies},
+  pass::{
+    Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, Subpass, SubpassDependency,
+    SubpassDesc, SubpassRef,
+  },
+  pool::{CommandPool, CommandPoolCreateFlags},
+  pso::{
+    AttributeDesc, BakedStates, BasePipeline, BlendDesc, BlendOp, BlendState, ColorBlendDesc,
+    ColorMask, DepthStencilDesc, DepthTest, EntryPoint, Face, Factor, FrontFace,
+    GraphicsPipelineDesc, GraphicsShaderSet, InputAssemblerDesc, LogicOp, Multisampling,
+    PipelineCreationFlags, PipelineStage, PolygonMode, Rasterizer, Rect, ShaderStageFlags,
+    Specialization, StencilTest, VertexBufferDesc, Viewport,
+  },
+  queue::{family::QueueGroup, QueueType, Submission, Supports},
+  window::{
+    Backbuffer, CompositeAlpha, Extent2D, FrameSync, PresentMode, Swapchain, SwapchainConfig,
+  },
+  Backend, DescriptorPool, Gpu, Graphics, IndexType, Instance, Primitive, QueueFamily, Surface,
+  Transfer,
+};
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+#[cfg(feature = "windowed")]
+use winit::Window;
+
+pub const MAX_CUBES: usize = 50000;
+
+/// The GLSL of the cube vertex shader.
+pub const VERTEX_SOURCE: &str = include_str!("../shaders/cube.vert");
+
+/// The GLSL of the cube fragment shader.
+pub const FRAGMENT_SOURCE: &str = include_str!("../shaders/cube.frag");
+
+/// `VERTEX_SOURCE`, compiled to SPIR-V by the build script.
+pub static VERTEX_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/cube.vert.spv"));
+
+/// `FRAGMENT_SOURCE`, compiled to SPIR-V by the build script.
+pub static FRAGMENT_SPIRV: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/cube.frag.spv"));
+
+/// The triangle shaders, compiled to SPIR-V by the build script.
+static TRIANGLE_VERTEX_SPIRV: &[u8] =
+  include_bytes!(concat!(env!("OUT_DIR"), "/triangle.vert.spv"));
+static TRIANGLE_FRAGMENT_SPIRV: &[u8] =
+  include_bytes!(concat!(env!("OUT_DIR"), "/triangle.frag.spv"));
+
+pub static CREATURE_BYTES: &[u8] = include_bytes!("../examples/creature.png");
+
+#[derive(Debug, Clone, Copy, VertexLayout)]
+#[repr(C)]
+pub struct Vertex {
+  pub xyz: [f32; 3],
+  pub uv: [f32; 2],
+}
+
+/// What each cube gets per instance, after the `Vertex` attributes.
+#[derive(Debug, Clone, Copy, VertexLayout)]
+#[repr(C)]
+#[per_instance]
+pub struct CubeInstance {
+  #[location(2)]
+  pub model: glm::Mat4,
+}
+
+/// A triangle in clip space, with a color at each corner that gets blended
+/// across the middle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Triangle {
+  pub points: [[f32; 2]; 3],
+  pub colors: [[f32; 3]; 3],
+}
+impl Triangle {
+  /// A plain white triangle.
+  pub fn white(points: [[f32; 2]; 3]) -> Self {
+    Self {
+      points,
+      colors: [[1.0; 3]; 3],
+    }
+  }
+
+  /// The vertices that get put in the vertex buffer.
+  pub fn vertices(self) -> [TriangleVertex; 3] {
+    let vertex = |i: usize| TriangleVertex {
+      xy: self.points[i],
+      rgb: self.colors[i],
+    };
+    [vertex(0), vertex(1), vertex(2)]
+  }
+}
+
+#[derive(Debug, Clone, Copy, VertexLayout)]
+#[repr(C)]
+pub struct TriangleVertex {
+  pub xy: [f32; 2],
+  pub rgb: [f32; 3],
+}
+
+#[rustfmt::skip]
+pub const CUBE_VERTEXES: [Vertex; 24] = [
+  // Face 1 (front)
+  Vertex { xyz: [0.0, 0.0, 0.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [0.0, 1.0, 0.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [1.0, 0.0, 0.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [1.0, 1.0, 0.0], uv: [1.0, 0.0] }, /* top right */
+  // Face 2 (top)
+  Vertex { xyz: [0.0, 1.0, 0.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [0.0, 1.0, 1.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [1.0, 1.0, 0.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [1.0, 1.0, 1.0], uv: [1.0, 0.0] }, /* top right */
+  // Face 3 (back)
+  Vertex { xyz: [0.0, 0.0, 1.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [0.0, 1.0, 1.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [1.0, 0.0, 1.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [1.0, 1.0, 1.0], uv: [1.0, 0.0] }, /* top right */
+  // Face 4 (bottom)
+  Vertex { xyz: [0.0, 0.0, 0.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [0.0, 0.0, 1.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [1.0, 0.0, 0.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [1.0, 0.0, 1.0], uv: [1.0, 0.0] }, /* top right */
+  // Face 5 (left)
+  Vertex { xyz: [0.0, 0.0, 1.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [0.0, 1.0, 1.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [0.0, 0.0, 0.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [0.0, 1.0, 0.0], uv: [1.0, 0.0] }, /* top right */
+  // Face 6 (right)
+  Vertex { xyz: [1.0, 0.0, 0.0], uv: [0.0, 1.0] }, /* bottom left */
+  Vertex { xyz: [1.0, 1.0, 0.0], uv: [0.0, 0.0] }, /* top left */
+  Vertex { xyz: [1.0, 0.0, 1.0], uv: [1.0, 1.0] }, /* bottom right */
+  Vertex { xyz: [1.0, 1.0, 1.0], uv: [1.0, 0.0] }, /* top right */
+];
+
+#[rustfmt::skip]
+pub const CUBE_INDEXES: [u16; 36] = [
+   0,  1,  2,  2,  1,  3, // front
+   4,  5,  6,  7,  6,  5, // top
+  10,  9,  8,  9, 10, 11, // back
+  12, 14, 13, 15, 13, 14, // bottom
+  16, 17, 18, 19, 18, 17, // left
+  20, 21, 22, 23, 22, 21, // right
+];
+
+/// Where the frames drawn by a `HalState` end up.
+enum RenderTarget<B: Backend> {
+  /// Frames are presented to a window's surface through a swapchain.
+  Swapchain {
+    /// Only `None` if recreating the swapchain failed part way through.
+    swapchain: Option<B::Swapchain>,
+    /// These belong to the swapchain, we only keep them to copy out of.
+    images: Vec<B::Image>,
+    image_views: Vec<B::ImageView>,
+    surface: B::Surface,
+    /// If the images have `Usage::TRANSFER_SRC`, so that frames can be
+    /// captured.
+    transfer_src: bool,
+  },
+  /// Frames are drawn into plain images and never shown anywhere.
+  Offscreen {
+    color_images: Vec<ColorImage<B, B::Device>>,
+  },
+}
+impl<B: Backend> RenderTarget<B> {
+  /// The color image views that get attached to the framebuffers.
+  fn image_views(&self) -> Vec<&B::ImageView> {
+    match self {
+      RenderTarget::Swapchain { image_views, .. } => image_views.iter().collect(),
+      RenderTarget::Offscreen { color_images } => color_images
+        .iter()
+        .map(|color_image| color_image.image_view.deref())
+        .collect(),
+    }
+  }
+
+  /// The color image that the framebuffer of the given index draws into.
+  fn image(&self, index: usize) -> &B::Image {
+    match self {
+      RenderTarget::Swapchain { images, .. } => &images[index],
+      RenderTarget::Offscreen { color_images } => &color_images[index].image,
+    }
+  }
+
+  /// The layout that the color attachment should be left in after a frame.
+  fn final_layout(&self) -> Layout {
+    match self {
+      RenderTarget::Swapchain { .. } => Layout::Present,
+      RenderTarget::Offscreen { .. } => Layout::TransferSrcOptimal,
+    }
+  }
+
+  unsafe fn manually_drop(self, device: &B::Device, allocator: &mut Allocator<B>) {
+    match self {
+      RenderTarget::Swapchain {
+        swapchain,
+        images,
+        image_views,
+        surface,
+        ..
+      } => {
+        for image_view in image_views {
+          device.destroy_image_view(image_view);
+        }
+        // the swapchain destroys its own images, we just forget ours
+        drop(images);
+        if let Some(swapchain) = swapchain {
+          device.destroy_swapchain(swapchain);
+        }
+        drop(surface);
+      }
+      RenderTarget::Offscreen { color_images } => {
+        for color_image in color_images {
+          color_image.manually_drop(device, allocator);
+        }
+      }
+    }
+  }
+}
+
+/// Everything that `create_swapchain` makes or picks.
+struct NewSwapchain<B: Backend> {
+  swapchain: B::Swapchain,
+  extent: Extent2D,
+  format: Format,
+  present_mode: PresentMode,
+  composite_alpha: CompositeAlpha,
+  images: Vec<B::Image>,
+  image_views: Vec<B::ImageView>,
+  /// How many images we asked for, which is how many frames we keep in flight.
+  image_count: usize,
+  transfer_src: bool,
+}
+
+/// A copy of a frame on its way back to the CPU.
+struct Capture<B: Backend> {
+  staging_bundle: BufferBundle<B, B::Device>,
+  row_pitch: usize,
+  extent: Extent2D,
+  /// The image that was copied, whose fence says when the copy is done.
+  image_index: usize,
+}
+
+/// How drawing a frame went, for the problems that aren't plain errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStatus {
+  /// The frame was drawn (and presented, if there's a swapchain).
+  Drawn,
+  /// The swapchain no longer matches the surface (eg: the window was resized),
+  /// so the frame was skipped. The swapchain gets recreated before the next
+  /// frame, so just keep drawing.
+  OutOfDate,
+  /// The device was lost. Nothing more can be drawn with this HalState, so
+  /// drop it and make a new one.
+  DeviceLost,
+}
+
+pub struct HalState<B: Backend> {
+  cube_vertices: BufferBundle<B, B::Device>,
+  cube_instances: Vec<BufferBundle<B, B::Device>>,
+  cube_indexes: BufferBundle<B, B::Device>,
+  triangle_vertices: Vec<BufferBundle<B, B::Device>>,
+  depth_images: Vec<DepthImage<B, B::Device>>,
+  msaa_images: Vec<ColorImage<B, B::Device>>,
+  texture: LoadedImage<B, B::Device>,
+  descriptor_set_layouts: Vec<B::DescriptorSetLayout>,
+  descriptor_pool: ManuallyDrop<B::DescriptorPool>,
+  descriptor_set: ManuallyDrop<B::DescriptorSet>,
+  pipeline_layout: ManuallyDrop<B::PipelineLayout>,
+  /// What the pipeline layout was made from, which reloaded shaders have to fit.
+  pipeline_interface: PipelineInterface,
+  /// The pipeline of each variant that's been used since the shaders were
+  /// last reloaded.
+  pipelines: HashMap<ShaderVariant, B::GraphicsPipeline>,
+  shader_variant: ShaderVariant,
+  triangle_pipeline_layout: ManuallyDrop<B::PipelineLayout>,
+  triangle_pipeline: ManuallyDrop<B::GraphicsPipeline>,
+  shader_watcher: ShaderWatcher,
+  current_frame: usize,
+  frames_in_flight: usize,
+  in_flight_fences: Vec<B::Fence>,
+  render_finished_semaphores: Vec<B::Semaphore>,
+  image_available_semaphores: Vec<B::Semaphore>,
+  command_buffers: Vec<CommandBuffer<B, Graphics, MultiShot, Primary>>,
+  command_pool: ManuallyDrop<CommandPool<B, Graphics>>,
+  framebuffers: Vec<B::Framebuffer>,
+  render_pass: ManuallyDrop<B::RenderPass>,
+  render_area: Rect,
+  extent: Extent2D,
+  format: Format,
+  uploader: Uploader<B>,
+  samplers: SamplerCache<B>,
+  allocator: Allocator<B>,
+  config: HalConfig,
+  chosen: ChosenConfig,
+  last_image: Option<usize>,
+  /// If the next frame should be copied back for `capture_frame`.
+  capture_requested: bool,
+  pending_capture: Option<Capture<B>>,
+  swapchain_out_of_date: bool,
+  queue_group: QueueGroup<B, Graphics>,
+  target: ManuallyDrop<RenderTarget<B>>,
+  device: ManuallyDrop<B::Device>,
+  adapter: Adapter<B>,
+  _instance: ManuallyDrop<Box<dyn Instance<Backend = B>>>,
+  /// Whatever the instance needs to outlive it, like the events loop behind a
+  /// headless GL context. Dropped after the instance.
+  keep_alive: Option<Box<dyn Any>>,
+}
+
+impl<B: Backend> HalState<B> {
+  /// Creates a new, fully initialized HalState that draws to the surface given.
+  ///
+  /// The instance is kept alive for as long as the HalState is, and the
+  /// `window_extent` is the physical size of the window's client area.
+  pub fn from_surface(
+    instance: Box<dyn Instance<Backend = B>>, mut surface: B::Surface, window_extent: Extent2D,
+    config: &HalConfig,
+  ) -> Result<Self, HalError> {
+    // Select An Adapter
+    let adapter = select_adapter(instance.enumerate_adapters(), &config.adapter, |a| {
+      a.queue_families
+        .iter()
+        .any(|qf| qf.supports_graphics() && surface.supports_queue_family(qf))
+    })?;
+
+    // Open A Device and take out a QueueGroup
+    let (device, queue_group, transfer_group) = Self::open_device(&adapter, config, |qf| {
+      qf.supports_graphics() && surface.supports_queue_family(qf)
+    })?;
+
+    // Create A Swapchain and its ImageViews
+    let new = Self::create_swapchain(
+      &adapter,
+      &device,
+      &mut surface,
+      window_extent,
+      config,
+      None,
+      None,
+    )?;
+
+    let allocator = Allocator::new(&adapter);
+    let target = RenderTarget::Swapchain {
+      swapchain: Some(new.swapchain),
+      images: new.images,
+      image_views: new.image_views,
+      surface,
+      transfer_src: new.transfer_src,
+    };
+    Self::from_target(
+      instance,
+      adapter,
+      device,
+      queue_group,
+      transfer_group,
+      allocator,
+      target,
+      new.extent,
+      new.format,
+      config.clone(),
+      Some((new.present_mode, new.composite_alpha)),
+      new.image_count,
+    )
+  }
+
+  /// Creates a new, fully initialized HalState that draws into offscreen
+  /// images instead of a window.
+  ///
+  /// No surface is involved at all, so this works on machines without any
+  /// display. The color images use the first of the config's formats that the
+  /// adapter can render to, and there are two of them unless the config asks
+  /// for some other number of frames in flight.
+  pub fn from_instance_headless(
+    instance: Box<dyn Instance<Backend = B>>, extent: Extent2D, config: &HalConfig,
+  ) -> Result<Self, HalError> {
+    // Select An Adapter
+    let adapter = select_adapter(instance.enumerate_adapters(), &config.adapter, |a| {
+      a.queue_families.iter().any(|qf| qf.supports_graphics())
+    })?;
+
+    // Open A Device and take out a QueueGroup
+    let (device, queue_group, transfer_group) =
+      Self::open_device(&adapter, config, |qf| qf.supports_graphics())?;
+
+    // Create The Render Target Images
+    let format = config.pick_offscreen_format(&adapter)?;
+    let frames_in_flight = config.frames_in_flight.unwrap_or(2);
+    let mut allocator = Allocator::new(&adapter);
+    let color_images = (0..frames_in_flight)
+      .map(|_| ColorImage::new(&device, &mut allocator, extent, format))
+      .collect::<Result<Vec<_>, HalError>>()?;
+
+    let target = RenderTarget::Offscreen { color_images };
+    Self::from_target(
+      instance,
+      adapter,
+      device,
+      queue_group,
+      transfer_group,
+      allocator,
+      target,
+      extent,
+      format,
+      config.clone(),
+      None,
+      frames_in_flight,
+    )
+  }
+
+  /// Opens the adapter's device and takes out a graphics `QueueGroup` from the
+  /// first queue family that passes the filter.
+  ///
+  /// If the config asks for a transfer queue and there's a family that only
+  /// does transfers, a `QueueGroup` from that is taken out too.
+  #[allow(clippy::type_complexity)]
+  fn open_device<F: Fn(&B::QueueFamily) -> bool>(
+    adapter: &Adapter<B>, config: &HalConfig, filter: F,
+  ) -> Result<
+    (
+      B::Device,
+      QueueGroup<B, Graphics>,
+      Option<QueueGroup<B, Transfer>>,
+    ),
+    HalError,
+  > {
+    let queue_family = adapter
+      .queue_families
+      .iter()
+      .find(|qf| filter(qf))
+      .ok_or(HalError::NoSuitableQueueFamily)?;
+    let transfer_family = if config.transfer_queue {
+      adapter
+        .queue_families
+        .iter()
+        .find(|qf| qf.queue_type() == QueueType::Transfer)
+    } else {
+      None
+    };
+    let priorities: &[f32] = &[1.0];
+    let mut families = vec![(queue_family, priorities)];
+    families.extend(transfer_family.map(|qf| (qf, priorities)));
+    let Gpu { device, mut queues } = unsafe { adapter.physical_device.open(&families)? };
+    let queue_group = queues
+      .take::<Graphics>(queue_family.id())
+      .ok_or(HalError::NoQueues)?;
+    if queue_group.queues.len() > 0 {
+      Ok(())
+    } else {
+      Err(HalError::NoQueues)
+    }?;
+    let transfer_group = match transfer_family {
+      Some(qf) => Some(queues.take::<Transfer>(qf.id()).ok_or(HalError::NoQueues)?),
+      None => None,
+    };
+    Ok((device, queue_group, transfer_group))
+  }
+
+  /// Makes a swapchain for the surface along with views of its images.
+  ///
+  /// The present mode, composite alpha and image count follow the config as
+  /// far as the surface allows. If `format` is `None` one is picked with the
+  /// config and the surface's preferred formats, otherwise that format is used
+  /// as is. Passing the `old_swapchain` lets the driver reuse what it can from
+  /// it.
+  fn create_swapchain(
+    adapter: &Adapter<B>, device: &B::Device, surface: &mut B::Surface, window_extent: Extent2D,
+    config: &HalConfig, format: Option<Format>, old_swapchain: Option<B::Swapchain>,
+  ) -> Result<NewSwapchain<B>, HalError> {
+    let (caps, preferred_formats, present_modes, composite_alphas) =
+      surface.compatibility(&adapter.physical_device);
+    info!("{:?}", caps);
+    info!("Preferred Formats: {:?}", preferred_formats);
+    info!("Present Modes: {:?}", present_modes);
+    info!("Composite Alphas: {:?}", composite_alphas);
+    //
+    let present_mode =
+      config
+        .pick_present_mode(&present_modes)
+        .ok_or(HalError::SurfaceIncompatible(
+          "No PresentMode values specified!",
+        ))?;
+    let composite_alpha =
+      config
+        .pick_composite_alpha(&composite_alphas)
+        .ok_or(HalError::SurfaceIncompatible(
+          "No CompositeAlpha values specified!",
+        ))?;
+    let requested_format = format.or_else(|| {
+      config.pick_surface_format(preferred_formats.as_ref().map(|formats| formats.as_slice()))
+    });
+    let format = match (requested_format, preferred_formats) {
+      (Some(format), _) => format,
+      (None, None) => Format::Rgba8Srgb,
+      (None, Some(formats)) => match formats
+        .iter()
+        .find(|format| format.base_format().1 == ChannelType::Srgb)
+        .cloned()
+      {
+        Some(srgb_format) => srgb_format,
+        None => formats
+          .first()
+          .cloned()
+          .ok_or(HalError::SurfaceIncompatible(
+            "Preferred format list was empty!",
+          ))?,
+      },
+    };
+    let extent = Extent2D {
+      width: caps.extents.end.width.min(window_extent.width),
+      height: caps.extents.end.height.min(window_extent.height),
+    };
+    let image_count = match config.frames_in_flight {
+      Some(frames) => (frames as u32)
+        .max(caps.image_count.start)
+        .min(caps.image_count.end - 1),
+      None if present_mode == PresentMode::Mailbox => (caps.image_count.end - 1).min(3),
+      None => (caps.image_count.end - 1).min(2),
+    };
+    let image_layers = 1;
+    let image_usage = if caps.usage.contains(Usage::COLOR_ATTACHMENT) {
+      // We also want to copy out of the images to capture frames, if the
+      // surface allows it.
+      Usage::COLOR_ATTACHMENT | (caps.usage & Usage::TRANSFER_SRC)
+    } else {
+      Err(HalError::SurfaceIncompatible(
+        "The Surface isn't capable of supporting color!",
+      ))?
+    };
+    let swapchain_config = SwapchainConfig {
+      present_mode,
+      composite_alpha,
+      format,
+      extent,
+      image_count,
+      image_layers,
+      image_usage,
+    };
+    info!("{:?}", swapchain_config);
+    //
+    let (swapchain, backbuffer) =
+      unsafe { device.create_swapchain(surface, swapchain_config, old_swapchain)? };
+
+    // Create The ImageViews
+    let (images, image_views) = match backbuffer {
+      Backbuffer::Images(images) => {
+        let image_views = images
+          .iter()
+          .map(|image| unsafe {
+            device
+              .create_image_view(
+                image,
+                ViewKind::D2,
+                format,
+                Swizzle::NO,
+                SubresourceRange {
+                  aspects: Aspects::COLOR,
+                  levels: 0..1,
+                  layers: 0..1,
+                },
+              )
+              .map_err(HalError::from)
+          })
+          .collect::<Result<Vec<_>, HalError>>()?;
+        (images, image_views)
+      }
+      Backbuffer::Framebuffer(_) => {
+        unsafe { device.destroy_swapchain(swapchain) };
+        return Err(HalError::UnsupportedBackbuffer);
+      }
+    };
+
+    Ok(NewSwapchain {
+      swapchain,
+      extent,
+      format,
+      present_mode,
+      composite_alpha,
+      images,
+      image_views,
+      image_count: image_count as usize,
+      transfer_src: image_usage.contains(Usage::TRANSFER_SRC),
+    })
+  }
+
+  /// Builds everything that doesn't care where the frames end up.
+  ///
+  /// `presentation` is the present mode and composite alpha that the swapchain
+  /// was made with, if there is one.
+  #[allow(clippy::too_many_arguments)]
+  fn from_target(
+    instance: Box<dyn Instance<Backend = B>>, adapter: Adapter<B>, device: B::Device,
+    mut queue_group: QueueGroup<B, Graphics>, transfer_group: Option<QueueGroup<B, Transfer>>,
+    mut allocator: Allocator<B>, target: RenderTarget<B>, extent: Extent2D, format: Format,
+    config: HalConfig, presentation: Option<(PresentMode, CompositeAlpha)>,
+    frames_in_flight: usize,
+  ) -> Result<Self, HalError> {
+    let image_count = target.image_views().len();
+
+    // Pick The Depth Format and Sample Count
+    let depth_format = config.pick_depth_format(&adapter)?;
+    let samples = config.pick_samples(&adapter);
+    let chosen = ChosenConfig {
+      adapter: adapter.info.clone(),
+      present_mode: presentation.map(|(present_mode, _)| present_mode),
+      composite_alpha: presentation.map(|(_, composite_alpha)| composite_alpha),
+      format,
+      frames_in_flight,
+      depth_format,
+      samples,
+      transfer_queue: transfer_group.is_some(),
+    };
+    info!("{:?}", chosen);
+
+    // Create Our Sync Primitives
+    let (image_available_semaphores, render_finished_semaphores, in_flight_fences) = {
+      let mut image_available_semaphores: Vec<B::Semaphore> = vec![];
+      let mut render_finished_semaphores: Vec<B::Semaphore> = vec![];
+      let mut in_flight_fences: Vec<B::Fence> = vec![];
+      for _ in 0..frames_in_flight {
+        image_available_semaphores.push(device.create_semaphore()?);
+        render_finished_semaphores.push(device.create_semaphore()?);
+      }
+      // Fences guard the per-image command buffers, so we need one per image.
+      for _ in 0..image_count {
+        in_flight_fences.push(device.create_fence(true)?);
+      }
+      (
+        image_available_semaphores,
+        render_finished_semaphores,
+        in_flight_fences,
+      )
+    };
+
+    // Define A RenderPass
+    let render_pass = Self::create_render_pass(
+      &device,
+      format,
+      depth_format,
+      samples,
+      target.final_layout(),
+    )?;
+
+    // Create The Depth Images, MSAA Images and Framebuffers
+    let (depth_images, msaa_images, framebuffers) = Self::create_frame_attachments(
+      &device,
+      &mut allocator,
+      &render_pass,
+      &target,
+      extent,
+      format,
+      depth_format,
+      samples,
+    )?;
+
+    // Create Our CommandPool
+    let mut command_pool = unsafe {
+      device.create_command_pool_typed(&queue_group, CommandPoolCreateFlags::RESET_INDIVIDUAL)?
+    };
+
+    // Create Our CommandBuffers
+    let command_buffers: Vec<_> = framebuffers
+      .iter()
+      .map(|_| command_pool.acquire_command_buffer())
+      .collect();
+
+    // Build our pipeline, starting to watch the shaders before they're loaded
+    // so that a save in between still counts as a change
+    let shader_variant = config.shader_variant.clone();
+    let shader_watcher = ShaderWatcher::new(&config.shaders, &shader_variant);
+    let compiled = config
+      .shaders
+      .compile(config.spirv_cache.as_ref(), &shader_variant)?;
+    let mut pipeline_interface = compiled.reflect()?;
+    pipeline_interface.merge(&Self::bound_interface())?;
+    let (descriptor_set_layouts, descriptor_pool, descriptor_set, pipeline_layout) =
+      Self::create_pipeline_layout(&device, &pipeline_interface)?;
+    let gfx_pipeline = Self::create_graphics_pipeline(
+      &device,
+      &render_pass,
+      &pipeline_layout,
+      &pipeline_interface,
+      depth_format.is_some(),
+      samples,
+      &compiled,
+    )?;
+    let mut pipelines = HashMap::new();
+    pipelines.insert(shader_variant.clone(), gfx_pipeline);
+    let triangle_pipeline_layout = unsafe {
+      device.create_pipeline_layout(
+        Vec::<B::DescriptorSetLayout>::new(),
+        Vec::<(ShaderStageFlags, core::ops::Range<u32>)>::new(),
+      )?
+    };
+    let triangle_pipeline =
+      Self::create_triangle_pipeline(&device, &render_pass, &triangle_pipeline_layout, samples)?;
+
+    // Create an instance buffer per image. We do this so that we can
+    // write changes into one while another frame is in flight. We'll
+    // write data into it later. The triangle's vertices change every frame
+    // too, so they get the same treatment.
+    let mut cube_instances = Vec::new();
+    let mut triangle_vertices = Vec::new();
+    for _ in 0..image_count {
+      cube_instances.push(BufferBundle::new(
+        &device,
+        &mut allocator,
+        size_of::<CubeInstance>() * MAX_CUBES,
+        BufferUsage::VERTEX,
+      )?);
+      triangle_vertices.push(BufferBundle::new(
+        &device,
+        &mut allocator,
+        size_of::<[TriangleVertex; 3]>(),
+        BufferUsage::VERTEX,
+      )?);
+    }
+
+    // Upload The Cube and its Texture, all in one batch. The cube's vertices
+    // and indexes never change, so they can live in device local memory.
+    let mut uploader = Uploader::new(&device, &queue_group, transfer_group)?;
+    let mut samplers = SamplerCache::new(&adapter);
+    let (cube_vertices, vertices_ready) =
+      uploader.upload_buffer(&device, &mut allocator, &CUBE_VERTEXES, BufferUsage::VERTEX)?;
+    let (cube_indexes, indexes_ready) =
+      uploader.upload_buffer(&device, &mut allocator, &CUBE_INDEXES, BufferUsage::INDEX)?;
+    let (texture, texture_ready) = uploader.upload_image(
+      &adapter,
+      &device,
+      &mut allocator,
+      &mut samplers,
+      image::load_from_memory(CREATURE_BYTES).expect("Binary corrupted!"),
+      &config.texture_desc(),
+    )?;
+    for &token in [vertices_ready, indexes_ready, texture_ready].iter() {
+      uploader.wait(&device, &mut allocator, &mut queue_group.queues[0], token)?;
+    }
+
+    unsafe {
+      device.write_descriptor_sets(vec![
+        gfx_hal::pso::DescriptorSetWrite {
+          set: &descriptor_set,
+          binding: 0,
+          array_offset: 0,
+          descriptors: Some(gfx_hal::pso::Descriptor::Image(
+            texture.image_view.deref(),
+            Layout::ShaderReadOnlyOptimal,
+          )),
+        },
+        gfx_hal::pso::DescriptorSetWrite {
+          set: &descriptor_set,
+          binding: 1,
+          array_offset: 0,
+          descriptors: Some(gfx_hal::pso::Descriptor::Sampler(
+            samplers.sampler(texture.sampler),
+          )),
+        },
+      ]);
+    }
+
+    Ok(Self {
+      cube_vertices,
+      cube_instances,
+      cube_indexes,
+      triangle_vertices,
+      texture,
+      depth_images,
+      msaa_images,
+      descriptor_pool: ManuallyDrop::new(descriptor_pool),
+      descriptor_set: ManuallyDrop::new(descriptor_set),
+      _instance: ManuallyDrop::new(instance),
+      keep_alive: None,
+      adapter,
+      device: ManuallyDrop::new(device),
+      queue_group,
+      target: ManuallyDrop::new(target),
+      render_area: extent.to_extent().rect(),
+      extent,
+      format,
+      uploader,
+      samplers,
+      allocator,
+      config,
+      chosen,
+      last_image: None,
+      capture_requested: false,
+      pending_capture: None,
+      swapchain_out_of_date: false,
+      render_pass: ManuallyDrop::new(render_pass),
+      framebuffers,
+      command_pool: ManuallyDrop::new(command_pool),
+      command_buffers,
+      image_available_semaphores,
+      render_finished_semaphores,
+      in_flight_fences,
+      frames_in_flight,
+      current_frame: 0,
+      descriptor_set_layouts,
+      pipeline_layout: ManuallyDrop::new(pipeline_layout),
+      pipeline_interface,
+      pipelines,
+      shader_variant,
+      triangle_pipeline_layout: ManuallyDrop::new(triangle_pipeline_layout),
+      triangle_pipeline: ManuallyDrop::new(triangle_pipeline),
+      shader_watcher,
+    })
+  }
+
+  /// Makes a depth image (if there's a `depth_format`), a multisampled color
+  /// image (if `samples` is more than 1) and a framebuffer for each of the
+  /// target's images.
+  #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+  fn create_frame_attachments(
+    device: &B::Device, allocator: &mut Allocator<B>, render_pass: &B::RenderPass,
+    target: &RenderTarget<B>, extent: Extent2D, format: Format, depth_format: Option<Format>,
+    samples: NumSamples,
+  ) -> Result<
+    (
+      Vec<DepthImage<B, B::Device>>,
+      Vec<ColorImage<B, B::Device>>,
+      Vec<B::Framebuffer>,
+    ),
+    HalError,
+  > {
+    let image_views = target.image_views();
+    let depth_images = match depth_format {
+      Some(depth_format) => (0..image_views.len())
+        .map(|_| DepthImage::new(device, allocator, extent, depth_format, samples))
+        .collect::<Result<Vec<_>, HalError>>()?,
+      None => Vec::new(),
+    };
+    let msaa_images = if samples > 1 {
+      (0..image_views.len())
+        .map(|_| ColorImage::new_multisampled(device, allocator, extent, format, samples))
+        .collect::<Result<Vec<_>, HalError>>()?
+    } else {
+      Vec::new()
+    };
+    let framebuffers = {
+      let image_extent = gfx_hal::image::Extent {
+        width: extent.width as _,
+        height: extent.height as _,
+        depth: 1,
+      };
+      image_views
+        .into_iter()
+        .enumerate()
+        .map(|(i, view)| unsafe {
+          // This has to match the order of the render pass's attachments.
+          let mut attachments: ArrayVec<[&B::ImageView; 3]> = ArrayVec::new();
+          match msaa_images.get(i) {
+            Some(msaa_image) => attachments.push(&msaa_image.image_view),
+            None => attachments.push(view),
+          }
+          if let Some(depth_image) = depth_images.get(i) {
+            attachments.push(&depth_image.image_view);
+          }
+          if !msaa_images.is_empty() {
+            attachments.push(view);
+          }
+          device
+            .create_framebuffer(render_pass, attachments, image_extent)
+            .map_err(HalError::from)
+        })
+        .collect::<Result<Vec<_>, HalError>>()?
+    };
+    Ok((depth_images, msaa_images, framebuffers))
+  }
+
+  /// Makes our one render pass, with a depth attachment if there's a
+  /// `depth_format`.
+  ///
+  /// The image that ends up with the frame is left in `final_layout` once the
+  /// pass is done. With more than 1 sample that's a resolve attachment that
+  /// comes after the others, and the multisampled color attachment is thrown
+  /// away.
+  fn create_render_pass(
+    device: &B::Device, format: Format, depth_format: Option<Format>, samples: NumSamples,
+    final_layout: Layout,
+  ) -> Result<B::RenderPass, HalError> {
+    let multisampled = samples > 1;
+    let color_attachment = Attachment {
+      format: Some(format),
+      samples,
+      ops: AttachmentOps {
+        load: AttachmentLoadOp::Clear,
+        store: if multisampled {
+          AttachmentStoreOp::DontCare
+        } else {
+          AttachmentStoreOp::Store
+        },
+      },
+      stencil_ops: AttachmentOps::DONT_CARE,
+      layouts: Layout::Undefined..if multisampled {
+        Layout::ColorAttachmentOptimal
+      } else {
+        final_layout
+      },
+    };
+    let depth_attachment = depth_format.map(|depth_format| Attachment {
+      format: Some(depth_format),
+      samples,
+      ops: AttachmentOps {
+        load: AttachmentLoadOp::Clear,
+        store: AttachmentStoreOp::DontCare,
+      },
+      stencil_ops: AttachmentOps::DONT_CARE,
+      layouts: Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
+    });
+    let resolve_attachment = if multisampled {
+      Some(Attachment {
+        format: Some(format),
+        samples: 1,
+        ops: AttachmentOps {
+          load: AttachmentLoadOp::DontCare,
+          store: AttachmentStoreOp::Store,
+        },
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..final_layout,
+      })
+    } else {
+      None
+    };
+    let resolve_index = if depth_attachment.is_some() { 2 } else { 1 };
+    let resolves = [(resolve_index, Layout::ColorAttachmentOptimal)];
+    let subpass = SubpassDesc {
+      colors: &[(0, Layout::ColorAttachmentOptimal)],
+      depth_stencil: if depth_attachment.is_some() {
+        Some(&(1, Layout::DepthStencilAttachmentOptimal))
+      } else {
+        None
+      },
+      inputs: &[],
+      resolves: if multisampled { &resolves } else { &[] },
+      preserves: &[],
+    };
+    let in_dependency = SubpassDependency {
+      passes: SubpassRef::External..SubpassRef::Pass(0),
+      stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+        ..PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS,
+      accesses: ImageAccess::empty()
+        ..(ImageAccess::COLOR_ATTACHMENT_READ
+          | ImageAccess::COLOR_ATTACHMENT_WRITE
+          | ImageAccess::DEPTH_STENCIL_ATTACHMENT_READ
+          | ImageAccess::DEPTH_STENCIL_ATTACHMENT_WRITE),
+    };
+    let out_dependency = SubpassDependency {
+      passes: SubpassRef::Pass(0)..SubpassRef::External,
+      stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS
+        ..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+      accesses: (ImageAccess::COLOR_ATTACHMENT_READ
+        | ImageAccess::COLOR_ATTACHMENT_WRITE
+        | ImageAccess::DEPTH_STENCIL_ATTACHMENT_READ
+        | ImageAccess::DEPTH_STENCIL_ATTACHMENT_WRITE)..ImageAccess::empty(),
+    };
+    let mut attachments: ArrayVec<[Attachment; 3]> = ArrayVec::new();
+    attachments.push(color_attachment);
+    attachments.extend(depth_attachment);
+    attachments.extend(resolve_attachment);
+    unsafe {
+      device
+        .create_render_pass(attachments, &[subpass], &[in_dependency, out_dependency])
+        .map_err(HalError::from)
+    }
+  }
+
+  /// What gets bound whether or not the shaders use it: the texture and its
+  /// sampler in set 0, and the camera's matrix as vertex push constants.
+  fn bound_interface() -> PipelineInterface {
+    let texture = |binding, ty| DescriptorBinding {
+      set: 0,
+      binding,
+      ty,
+      count: 1,
+      stages: ShaderStageFlags::FRAGMENT,
+    };
+    PipelineInterface {
+      inputs: Vec::new(),
+      descriptors: vec![
+        texture(0, gfx_hal::pso::DescriptorType::SampledImage),
+        texture(1, gfx_hal::pso::DescriptorType::Sampler),
+      ],
+      push_constants: vec![(ShaderStageFlags::VERTEX, 0..16)],
+    }
+  }
+
+  /// Makes the descriptor sets and the pipeline layout that the interface
+  /// describes, with set 0 allocated for the texture. They're kept when the
+  /// shaders get reloaded, so the new shaders have to fit them.
+  #[allow(clippy::type_complexity)]
+  fn create_pipeline_layout(
+    device: &B::Device, interface: &PipelineInterface,
+  ) -> Result<
+    (
+      Vec<B::DescriptorSetLayout>,
+      B::DescriptorPool,
+      B::DescriptorSet,
+      B::PipelineLayout,
+    ),
+    HalError,
+  > {
+    let descriptor_set_layouts: Vec<B::DescriptorSetLayout> = interface
+      .set_layouts()
+      .iter()
+      .map(|bindings| unsafe { device.create_descriptor_set_layout(bindings, &[]) })
+      .collect::<Result<_, _>>()?;
+
+    let mut descriptor_pool =
+      unsafe { device.create_descriptor_pool(1, interface.descriptor_ranges())? };
+
+    let descriptor_set = unsafe { descriptor_pool.allocate_set(&descriptor_set_layouts[0])? };
+
+    let push_constants = interface.push_constants.iter().cloned();
+    let layout = unsafe { device.create_pipeline_layout(&descriptor_set_layouts, push_constants)? };
+
+    Ok((
+      descriptor_set_layouts,
+      descriptor_pool,
+      descriptor_set,
+      layout,
+    ))
+  }
+
+  /// Makes the graphics pipeline out of the compiled shaders, after checking
+  /// that they fit the pipeline layout and read the vertex attributes that we
+  /// give them.
+  #[allow(clippy::too_many_arguments)]
+  fn create_graphics_pipeline(
+    device: &B::Device, render_pass: &B::RenderPass, layout: &B::PipelineLayout,
+    layout_interface: &PipelineInterface, use_depth: bool, samples: NumSamples,
+    compiled: &CompiledShaders,
+  ) -> Result<B::GraphicsPipeline, HalError> {
+    let mut attributes: Vec<AttributeDesc> = Vertex::attributes(0);
+    attributes.extend(CubeInstance::attributes(1));
+
+    let interface = compiled.reflect()?;
+    interface.fits_layout(layout_interface)?;
+    interface.check_attributes(&attributes)?;
+
+    // The instance buffer advances once per instance rather than per vertex
+    let vertex_buffers: Vec<VertexBufferDesc> =
+      vec![Vertex::buffer_desc(0), CubeInstance::buffer_desc(1)];
+
+    Self::create_pipeline(
+      device,
+      render_pass,
+      layout,
+      compiled,
+      vertex_buffers,
+      attributes,
+      Face::BACK,
+      use_depth,
+      samples,
+    )
+  }
+
+  /// Makes the pipeline that `draw_triangle_frame` uses, which ignores the
+  /// depth attachment.
+  fn create_triangle_pipeline(
+    device: &B::Device, render_pass: &B::RenderPass, layout: &B::PipelineLayout,
+    samples: NumSamples,
+  ) -> Result<B::GraphicsPipeline, HalError> {
+    let compiled = CompiledShaders {
+      vertex: TRIANGLE_VERTEX_SPIRV.to_vec(),
+      fragment: TRIANGLE_FRAGMENT_SPIRV.to_vec(),
+      constants: Vec::new(),
+      data: Vec::new(),
+    };
+    let attributes = TriangleVertex::attributes(0);
+    compiled.reflect()?.check_attributes(&attributes)?;
+    Self::create_pipeline(
+      device,
+      render_pass,
+      layout,
+      &compiled,
+      vec![TriangleVertex::buffer_desc(0)],
+      attributes,
+      // the triangle's points move around, so it could face either way
+      Face::NONE,
+      false,
+      samples,
+    )
+  }
+
+  /// Makes a pipeline for our render pass out of the compiled shaders, with
+  /// the fixed function state that everything we draw shares.
+  #[allow(clippy::too_many_arguments)]
+  fn create_pipeline(
+    device: &B::Device, render_pass: &B::RenderPass, layout: &B::PipelineLayout,
+    compiled: &CompiledShaders, vertex_buffers: Vec<VertexBufferDesc>,
+    attributes: Vec<AttributeDesc>, cull_face: Face, use_depth: bool, samples: NumSamples,
+  ) -> Result<B::GraphicsPipeline, HalError> {
+    let vertex_shader_module = unsafe { device.create_shader_module(&compiled.vertex)? };
+    let fragment_shader_module = match unsafe { device.create_shader_module(&compiled.fragment) } {
+      Ok(module) => module,
+      Err(e) => {
+        unsafe { device.destroy_shader_module(vertex_shader_module) };
+        return Err(e.into());
+      }
+    };
+    let gfx_pipeline = {
+      let (vs_entry, fs_entry) = (
+        EntryPoint {
+          entry: "main",
+          module: &vertex_shader_module,
+          specialization: Specialization {
+            constants: &compiled.constants,
+            data: &compiled.data,
+          },
+        },
+        EntryPoint {
+          entry: "main",
+          module: &fragment_shader_module,
+          specialization: Specialization {
+            constants: &compiled.constants,
+            data: &compiled.data,
+          },
+        },
+      );
+      let shaders = GraphicsShaderSet {
+        vertex: vs_entry,
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(fs_entry),
+      };
+
+      let input_assembler = InputAssemblerDesc::new(Primitive::TriangleList);
+
+      let rasterizer = Rasterizer {
+        depth_clamping: false,
+        polygon_mode: PolygonMode::Fill,
+        cull_face,
+        front_face: FrontFace::Clockwise,
+        depth_bias: None,
+        conservative: false,
+      };
+
+      let depth_stencil = DepthStencilDesc {
+        depth: if use_depth {
+          DepthTest::On {
+            fun: gfx_hal::pso::Comparison::LessEqual,
+            write: true,
+          }
+        } else {
+          DepthTest::Off
+        },
+        depth_bounds: false,
+        stencil: StencilTest::Off,
+      };
+
+      let blender = {
+        let blend_state = BlendState::On {
+          color: BlendOp::Add {
+            src: Factor::One,
+            dst: Factor::Zero,
+          },
+          alpha: BlendOp::Add {
+            src: Factor::One,
+            dst: Factor::Zero,
+          },
+        };
+        BlendDesc {
+          logic_op: Some(LogicOp::Copy),
+          targets: vec![ColorBlendDesc(ColorMask::ALL, blend_state)],
+        }
+      };
+
+      let multisampling = if samples > 1 {
+        Some(Multisampling {
+          rasterization_samples: samples,
+          sample_shading: None,
+          sample_mask: !0,
+          alpha_coverage: false,
+          alpha_to_one: false,
+        })
+      } else {
+        None
+      };
+
+      // The viewport and scissor are set when recording each frame, so that the
+      // pipeline can outlive a swapchain of one particular size.
+      let baked_states = BakedStates {
+        viewport: None,
+        scissor: None,
+        blend_color: None,
+        depth_bounds: None,
+      };
+
+      let desc = GraphicsPipelineDesc {
+        shaders,
+        rasterizer,
+        vertex_buffers,
+        attributes,
+        input_assembler,
+        blender,
+        depth_stencil,
+        multisampling,
+        baked_states,
+        layout,
+        subpass: Subpass {
+          index: 0,
+          main_pass: render_pass,
+        },
+        flags: PipelineCreationFlags::empty(),
+        parent: BasePipeline::None,
+      };
+
+      unsafe { device.create_graphics_pipeline(&desc, None) }
+    };
+
+    unsafe {
+      device.destroy_shader_module(vertex_shader_module);
+      device.destroy_shader_module(fragment_shader_module);
+    }
+
+    gfx_pipeline.map_err(HalError::from)
+  }
+
+  /// Rebuilds everything that depends on the size of the frames: the swapchain
+  /// and its image views, the depth and MSAA images, and the framebuffers.
+  ///
+  /// The device, buffers, textures and pipeline are all kept, so this is much
+  /// cheaper than making a whole new HalState when the window is resized.
+  /// `extent` is the new physical size of the window's client area, and gets
+  /// clamped to what the surface allows. A headless HalState remakes its
+  /// offscreen color images at the new size instead.
+  pub fn recreate_swapchain(&mut self, extent: Extent2D) -> Result<(), HalError> {
+    self.device.wait_idle()?;
+
+    // Destroy The Old Framebuffers, Depth Images and MSAA Images
+    unsafe {
+      for framebuffer in self.framebuffers.drain(..) {
+        self.device.destroy_framebuffer(framebuffer);
+      }
+      for depth_image in self.depth_images.drain(..) {
+        depth_image.manually_drop(&self.device, &mut self.allocator);
+      }
+      for msaa_image in self.msaa_images.drain(..) {
+        msaa_image.manually_drop(self.device.deref(), &mut self.allocator);
+      }
+    }
+
+    // Remake The Render Target Images
+    let extent = match &mut *self.target {
+      RenderTarget::Swapchain {
+        swapchain,
+        images,
+        image_views,
+        surface,
+        transfer_src,
+      } => {
+        unsafe {
+          for image_view in image_views.drain(..) {
+            self.device.destroy_image_view(image_view);
+          }
+        }
+        // the old swapchain's images go away along with it
+        images.clear();
+        let new = Self::create_swapchain(
+          &self.adapter,
+          &self.device,
+          surface,
+          extent,
+          &self.config,
+          Some(self.format),
+          swapchain.take(),
+        )?;
+        *swapchain = Some(new.swapchain);
+        *images = new.images;
+        *image_views = new.image_views;
+        *transfer_src = new.transfer_src;
+        self.chosen.present_mode = Some(new.present_mode);
+        self.chosen.composite_alpha = Some(new.composite_alpha);
+        new.extent
+      }
+      RenderTarget::Offscreen { color_images } => {
+        unsafe {
+          for color_image in color_images.drain(..) {
+            color_image.manually_drop(self.device.deref(), &mut self.allocator);
+          }
+        }
+        for _ in 0..self.frames_in_flight {
+          color_images.push(ColorImage::new(
+            self.device.deref(),
+            &mut self.allocator,
+            extent,
+            self.format,
+          )?);
+        }
+        extent
+      }
+    };
+    self.extent = extent;
+    self.render_area = extent.to_extent().rect();
+    self.last_image = None;
+
+    // Remake The Sync Primitives, a failed acquire or present can leave a
+    // semaphore signaled with nobody waiting on it.
+    let image_count = self.target.image_views().len();
+    unsafe {
+      for semaphore in self.image_available_semaphores.drain(..) {
+        self.device.destroy_semaphore(semaphore);
+      }
+      for semaphore in self.render_finished_semaphores.drain(..) {
+        self.device.destroy_semaphore(semaphore);
+      }
+      for fence in self.in_flight_fences.drain(..) {
+        self.device.destroy_fence(fence);
+      }
+    }
+    for _ in 0..self.frames_in_flight {
+      self
+        .image_available_semaphores
+        .push(self.device.create_semaphore()?);
+      self
+        .render_finished_semaphores
+        .push(self.device.create_semaphore()?);
+    }
+    for _ in 0..image_count {
+      self.in_flight_fences.push(self.device.create_fence(true)?);
+    }
+    self.current_frame = 0;
+
+    // The Swapchain Might Have A Different Number Of Images Now
+    if self.command_buffers.len() > image_count {
+      unsafe {
+        self
+          .command_pool
+          .free(self.command_buffers.drain(image_count..));
+      }
+    }
+    while self.command_buffers.len() < image_count {
+      self
+        .command_buffers
+        .push(self.command_pool.acquire_command_buffer());
+    }
+    if self.cube_instances.len() > image_count {
+      for buf in self.cube_instances.drain(image_count..) {
+        unsafe { buf.manually_drop(self.device.deref(), &mut self.allocator) };
+      }
+    }
+    while self.cube_instances.len() < image_count {
+      self.cube_instances.push(BufferBundle::new(
+        self.device.deref(),
+        &mut self.allocator,
+        size_of::<CubeInstance>() * MAX_CUBES,
+        BufferUsage::VERTEX,
+      )?);
+    }
+    if self.triangle_vertices.len() > image_count {
+      for buf in self.triangle_vertices.drain(image_count..) {
+        unsafe { buf.manually_drop(self.device.deref(), &mut self.allocator) };
+      }
+    }
+    while self.triangle_vertices.len() < image_count {
+      self.triangle_vertices.push(BufferBundle::new(
+        self.device.deref(),
+        &mut self.allocator,
+        size_of::<[TriangleVertex; 3]>(),
+        BufferUsage::VERTEX,
+      )?);
+    }
+
+    // Create The New Depth Images, MSAA Images and Framebuffers
+    let (depth_images, msaa_images, framebuffers) = Self::create_frame_attachments(
+      &self.device,
+      &mut self.allocator,
+      &self.render_pass,
+      &self.target,
+      extent,
+      self.format,
+      self.chosen.depth_format,
+      self.chosen.samples,
+    )?;
+    self.depth_images = depth_images;
+    self.msaa_images = msaa_images;
+    self.framebuffers = framebuffers;
+    self.swapchain_out_of_date = false;
+    Ok(())
+  }
+
+  /// The allocator that all of our buffers and images get their memory from.
+  pub fn allocator(&self) -> &Allocator<B> {
+    &self.allocator
+  }
+
+  /// How many mip levels the cube texture has, 1 unless mipmaps are on.
+  pub fn texture_mip_levels(&self) -> u8 {
+    self.texture.mip_levels
+  }
+
+  /// The samplers that our textures use.
+  pub fn samplers(&self) -> &SamplerCache<B> {
+    &self.samplers
+  }
+
+  /// What was actually picked for each of the config's settings.
+  pub fn chosen_config(&self) -> &ChosenConfig {
+    &self.chosen
+  }
+
+  /// Compiles the shaders for the variant and makes a pipeline out of them.
+  fn build_pipeline(&self, variant: &ShaderVariant) -> Result<B::GraphicsPipeline, HalError> {
+    let compiled = self
+      .config
+      .shaders
+      .compile(self.config.spirv_cache.as_ref(), variant)?;
+    Self::create_graphics_pipeline(
+      &self.device,
+      &self.render_pass,
+      &self.pipeline_layout,
+      &self.pipeline_interface,
+      self.chosen.depth_format.is_some(),
+      self.chosen.samples,
+      &compiled,
+    )
+  }
+
+  /// Draws with the variant of the shaders from now on.
+  ///
+  /// Each variant's pipeline is made the first time it's used and then kept,
+  /// so switching back and forth between variants is cheap. If the pipeline
+  /// can't be made the error is given back and the current variant is kept.
+  pub fn set_shader_variant(&mut self, variant: ShaderVariant) -> Result<(), HalError> {
+    if !self.pipelines.contains_key(&variant) {
+      let pipeline = self.build_pipeline(&variant)?;
+      self.pipelines.insert(variant.clone(), pipeline);
+    }
+    self
+      .shader_watcher
+      .watch_includes(&self.config.shaders, &variant);
+    self.shader_variant = variant;
+    Ok(())
+  }
+
+  /// The variant of the shaders that's being drawn with.
+  pub fn shader_variant(&self) -> &ShaderVariant {
+    &self.shader_variant
+  }
+
+  /// Recompiles the shaders and rebuilds the graphics pipeline if any of the
+  /// shader files (or the files they include) changed, giving back if the
+  /// pipeline was rebuilt.
+  ///
+  /// If a shader doesn't compile, needs descriptors or push constants that the
+  /// pipeline layout doesn't have, or reads vertex attributes that we don't
+  /// give it, the problem is logged and the old pipeline is kept. Fix the file
+  /// and save it again to have another go. This is called by
+  /// `draw_cubes_frame`, so there's only a need to call it yourself to reload
+  /// at some other time.
+  ///
+  /// Only the current variant is rebuilt. The pipelines of the others are
+  /// thrown out, and get rebuilt when they're next used.
+  pub fn reload_changed_shaders(&mut self) -> Result<bool, HalError> {
+    if !self.shader_watcher.changed() {
+      return Ok(false);
+    }
+    // a shader might include something new now
+    self
+      .shader_watcher
+      .watch_includes(&self.config.shaders, &self.shader_variant);
+    match self.build_pipeline(&self.shader_variant) {
+      Ok(new_pipeline) => unsafe {
+        // frames in flight could still be using the old pipelines
+        if let Err(e) = self.device.wait_idle() {
+          self.device.destroy_graphics_pipeline(new_pipeline);
+          return Err(e.into());
+        }
+        for (_, old_pipeline) in self.pipelines.drain() {
+          self.device.destroy_graphics_pipeline(old_pipeline);
+        }
+        self
+          .pipelines
+          .insert(self.shader_variant.clone(), new_pipeline);
+        info!("Reloaded the shaders");
+        Ok(true)
+      },
+      Err(e) => {
+        error!("Keeping the old shaders: {}", e);
+        Ok(false)
+      }
+    }
+  }
+
+  /// The size that the swapchain should be, going by the surface. Offscreen
+  /// targets just keep their current size.
+  fn surface_extent(&self) -> Extent2D {
+    match &*self.target {
+      RenderTarget::Swapchain { surface, .. } => {
+        let (caps, _, _, _) = surface.compatibility(&self.adapter.physical_device);
+        caps.current_extent.unwrap_or(self.extent)
+      }
+      RenderTarget::Offscreen { .. } => self.extent,
+    }
+  }
+
+  /// Turns the errors that just mean the swapchain has to be remade (or that
+  /// the device is gone) into a `FrameStatus`. An out of date swapchain gets
+  /// recreated at the start of the next frame.
+  fn frame_status(&mut self, result: Result<(), HalError>) -> Result<FrameStatus, HalError> {
+    match result {
+      Ok(()) => Ok(FrameStatus::Drawn),
+      Err(HalError::OutOfDate) => {
+        self.swapchain_out_of_date = true;
+        Ok(FrameStatus::OutOfDate)
+      }
+      // gfx-hal 0.1 doesn't say why presenting failed, so it only counts as out
+      // of date if the surface really did change size. Anything else is a real
+      // error, otherwise we'd just recreate the swapchain every frame forever.
+      Err(HalError::PresentFailed) if self.surface_extent() != self.extent => {
+        self.swapchain_out_of_date = true;
+        Ok(FrameStatus::OutOfDate)
+      }
+      Err(HalError::DeviceLost(_)) => Ok(FrameStatus::DeviceLost),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Picks the next image to draw into and waits until it's free to use.
+  ///
+  /// Gives back the frame index (for the semaphores) and the image index (for
+  /// everything that there's one of per image).
+  fn next_image(&mut self) -> Result<(usize, usize), HalError> {
+    if self.swapchain_out_of_date {
+      let extent = self.surface_extent();
+      if extent.width == 0 || extent.height == 0 {
+        // A minimized window, try again later.
+        return Err(HalError::OutOfDate);
+      }
+      self.recreate_swapchain(extent)?;
+    }
+
+    let frame = self.current_frame;
+    // Advance the frame _before_ we start using the `?` operator
+    self.current_frame = (self.current_frame + 1) % self.frames_in_flight;
+
+    let image_index = match &mut *self.target {
+      RenderTarget::Swapchain { swapchain, .. } => unsafe {
+        let image_available = &self.image_available_semaphores[frame];
+        swapchain
+          .as_mut()
+          .ok_or(HalError::OutOfDate)?
+          .acquire_image(core::u64::MAX, FrameSync::Semaphore(image_available))? as usize
+      },
+      // Offscreen images are used in order, there's nobody else to ask.
+      RenderTarget::Offscreen { color_images } => frame % color_images.len(),
+    };
+
+    // The fence only gets reset right before the submit, so that if anything
+    // fails in between it's still signaled and the next wait doesn't hang.
+    unsafe {
+      self
+        .device
+        .wait_for_fence(&self.in_flight_fences[image_index], core::u64::MAX)?;
+    }
+    Ok((frame, image_index))
+  }
+
+  /// Submits the image's command buffer, then presents the image if we're
+  /// drawing to a swapchain.
+  fn submit_image(&mut self, frame: usize, image_index: usize) -> Result<(), HalError> {
+    self.last_image = Some(image_index);
+    let command_buffers = &self.command_buffers[image_index..=image_index];
+    let flight_fence = &self.in_flight_fences[image_index];
+    let the_command_queue = &mut self.queue_group.queues[0];
+    match &*self.target {
+      RenderTarget::Swapchain { swapchain, .. } => {
+        let image_available = &self.image_available_semaphores[frame];
+        let render_finished = &self.render_finished_semaphores[frame];
+        let wait_semaphores: ArrayVec<[_; 1]> =
+          [(image_available, PipelineStage::COLOR_ATTACHMENT_OUTPUT)].into();
+        let signal_semaphores: ArrayVec<[_; 1]> = [render_finished].into();
+        // yes, you have to write it twice like this. yes, it's silly.
+        let present_wait_semaphores: ArrayVec<[_; 1]> = [render_finished].into();
+        let submission = Submission {
+          command_buffers,
+          wait_semaphores,
+          signal_semaphores,
+        };
+        unsafe {
+          self.device.reset_fence(flight_fence)?;
+          the_command_queue.submit(submission, Some(flight_fence));
+          swapchain
+            .as_ref()
+            .ok_or(HalError::OutOfDate)?
+            .present(
+              the_command_queue,
+              image_index as u32,
+              present_wait_semaphores,
+            )
+            .map_err(|()| HalError::PresentFailed)
+        }
+      }
+      RenderTarget::Offscreen { .. } => {
+        unsafe {
+          self.device.reset_fence(flight_fence)?;
+          the_command_queue.submit_nosemaphores(command_buffers, Some(flight_fence));
+        }
+        Ok(())
+      }
+    }
+  }
+
+  /// Draw a frame that's just cleared to the color specified.
+  pub fn draw_clear_frame(&mut self, color: [f32; 4]) -> Result<FrameStatus, HalError> {
+    let result = self.clear_frame(color);
+    self.frame_status(result)
+  }
+
+  /// Draws a single triangle, with the same background as the cubes.
+  pub fn draw_triangle_frame(&mut self, triangle: Triangle) -> Result<FrameStatus, HalError> {
+    let result = self.triangle_frame(triangle);
+    self.frame_status(result)
+  }
+
+  /// Draws one cube per model matrix given.
+  ///
+  /// Shaders from files are reloaded first if they changed.
+  pub fn draw_cubes_frame(
+    &mut self, view_projection: &glm::TMat4<f32>, models: &[glm::TMat4<f32>],
+  ) -> Result<FrameStatus, HalError> {
+    let result = self
+      .reload_changed_shaders()
+      .and_then(|_| self.cubes_frame(view_projection, models));
+    self.frame_status(result)
+  }
+
+  fn clear_frame(&mut self, color: [f32; 4]) -> Result<(), HalError> {
+    // SETUP FOR THIS FRAME
+    let (frame, i_usize) = self.next_image()?;
+    let capture = self.requested_capture(i_usize)?;
+
+    // RECORD COMMANDS
+    unsafe {
+      let buffer = &mut self.command_buffers[i_usize];
+      let clear_values = [
+        ClearValue::Color(ClearColor::Float(color)),
+        ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)),
+      ];
+      let clear_count = if self.chosen.depth_format.is_some() {
+        2
+      } else {
+        1
+      };
+      buffer.begin(false);
+      buffer.begin_render_pass_inline(
+        &self.render_pass,
+        &self.framebuffers[i_usize],
+        self.render_area,
+        clear_values[..clear_count].iter(),
+      );
+      if let Some(capture) = &capture {
+        Self::record_capture(buffer, &self.target, capture);
+      }
+      buffer.finish();
+    }
+    self.set_pending_capture(capture);
+
+    // SUBMISSION AND PRESENT
+    self.submit_image(frame, i_usize)
+  }
+
+  fn triangle_frame(&mut self, triangle: Triangle) -> Result<(), HalError> {
+    // SETUP FOR THIS FRAME
+    let (frame, i_usize) = self.next_image()?;
+    let capture = self.requested_capture(i_usize)?;
+
+    // WRITE THE TRIANGLE DATA
+    let triangle_buf = &self.triangle_vertices[i_usize];
+    unsafe {
+      let mut data_target = self.device.acquire_mapping_writer(
+        self.allocator.memory(&triangle_buf.allocation),
+        triangle_buf.allocation.range(),
+      )?;
+      data_target[..3].copy_from_slice(&triangle.vertices());
+      self.device.release_mapping_writer(data_target)?;
+    }
+
+    // RECORD COMMANDS
+    unsafe {
+      let buffer = &mut self.command_buffers[i_usize];
+      const TRIANGLE_CLEAR: [ClearValue; 2] = [
+        ClearValue::Color(ClearColor::Float([0.1, 0.2, 0.3, 1.0])),
+        ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)),
+      ];
+      let clear_count = if self.chosen.depth_format.is_some() {
+        2
+      } else {
+        1
+      };
+      buffer.begin(false);
+      buffer.set_viewports(
+        0,
+        &[Viewport {
+          rect: self.render_area,
+          depth: (0.0..1.0),
+        }],
+      );
+      buffer.set_scissors(0, &[self.render_area]);
+      {
+        let mut encoder = buffer.begin_render_pass_inline(
+          &self.render_pass,
+          &self.framebuffers[i_usize],
+          self.render_area,
+          TRIANGLE_CLEAR[..clear_count].iter(),
+        );
+        encoder.bind_graphics_pipeline(&self.triangle_pipeline);
+        encoder.bind_vertex_buffers(0, Some((triangle_buf.buffer.deref(), 0)));
+        encoder.draw(0..3, 0..1);
+      }
+      if let Some(capture) = &capture {
+        Self::record_capture(buffer, &self.target, capture);
+      }
+      buffer.finish();
+    }
+    self.set_pending_capture(capture);
+
+    // SUBMISSION AND PRESENT
+    self.submit_image(frame, i_usize)
+  }
+
+  fn cubes_frame(
+    &mut self, view_projection: &glm::TMat4<f32>, models: &[glm::TMat4<f32>],
+  ) -> Result<(), HalError> {
+    // SETUP FOR THIS FRAME
+    let (frame, i_usize) = self.next_image()?;
+    let capture = self.requested_capture(i_usize)?;
+
+    // Get corresponding instance buffer for this frame
+    let cube_instance_buf = &mut self.cube_instances[i_usize];
+
+    // Since we just waited for the previous submission's fence we know we can write data to the buffer
+    // We write each model matrix given (up to a max of MAX_CUBES because that's what we allocated space for)
+    unsafe {
+      let mut data_target = self.device.acquire_mapping_writer(
+        self.allocator.memory(&cube_instance_buf.allocation),
+        cube_instance_buf.allocation.range(),
+      )?;
+      let stride = 16;
+      for i in 0..models.len().min(MAX_CUBES) {
+        data_target[i * stride..(i + 1) * stride].copy_from_slice(&models[i].data);
+      }
+      self.device.release_mapping_writer(data_target)?;
+    }
+
+    // RECORD COMMANDS
+    unsafe {
+      let buffer = &mut self.command_buffers[i_usize];
+      const CUBE_CLEAR: [ClearValue; 2] = [
+        ClearValue::Color(ClearColor::Float([0.1, 0.2, 0.3, 1.0])),
+        ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)),
+      ];
+      let clear_count = if self.chosen.depth_format.is_some() {
+        2
+      } else {
+        1
+      };
+      buffer.begin(false);
+      buffer.set_viewports(
+        0,
+        &[Viewport {
+          rect: self.render_area,
+          depth: (0.0..1.0),
+        }],
+      );
+      buffer.set_scissors(0, &[self.render_area]);
+      {
+        let mut encoder = buffer.begin_render_pass_inline(
+          &self.render_pass,
+          &self.framebuffers[i_usize],
+          self.render_area,
+          CUBE_CLEAR[..clear_count].iter(),
+        );
+        encoder.bind_graphics_pipeline(&self.pipelines[&self.shader_variant]);
+        // Bind both vertex and the new 'instance' buffer which is also an instance buffer
+        encoder.bind_vertex_buffers(
+          0,
+          vec![
+            (self.cube_vertices.buffer.deref(), 0),
+            (cube_instance_buf.buffer.deref(), 0),
+          ],
+        );
+        encoder.bind_index_buffer(IndexBufferView {
+          buffer: &self.cube_indexes.buffer,
+          offset: 0,
+          index_type: IndexType::U16,
+        });
+        encoder.bind_graphics_descriptor_sets(
+          &self.pipeline_layout,
+          0,
+          Some(self.descriptor_set.deref()),
+          &[],
+        );
+        encoder.push_graphics_constants(
+          &self.pipeline_layout,
+          ShaderStageFlags::VERTEX,
+          0,
+          cast_slice::<f32, u32>(&view_projection.data)
+            .expect("this cast never fails for same-aligned same-size data"),
+        );
+        // Issue only one draw call with as many model matrices as given up to MAX_CUBES
+        encoder.draw_indexed(0..36, 0, 0..models.len().min(MAX_CUBES) as u32);
+      }
+      if let Some(capture) = &capture {
+        Self::record_capture(buffer, &self.target, capture);
+      }
+      buffer.finish();
+    }
+    self.set_pending_capture(capture);
+
+    // SUBMISSION AND PRESENT
+    self.submit_image(frame, i_usize)
+  }
+
+  /// Copies the next frame that gets drawn back to the CPU, for
+  /// `capture_frame` to give back.
+  ///
+  /// A swapchain's images are copied before they're presented, since the
+  /// presentation engine owns them after that. This needs a surface that
+  /// allows `Usage::TRANSFER_SRC`. Offscreen frames can be captured after the
+  /// fact as well, so for them this is optional.
+  pub fn capture_next_frame(&mut self) -> Result<(), HalError> {
+    self.capture_format()?;
+    if let RenderTarget::Swapchain {
+      transfer_src: false,
+      ..
+    } = &*self.target
+    {
+      return Err(HalError::CaptureUnsupported);
+    }
+    self.capture_requested = true;
+    Ok(())
+  }
+
+  /// Copies a frame back to the CPU.
+  ///
+  /// That's the frame drawn after `capture_next_frame`, or for offscreen
+  /// targets the most recently drawn frame if none was asked for. This waits
+  /// for the GPU to finish the copy, so it's for screenshots and tests, not
+  /// for calling every frame. Only 8-bit RGBA and BGRA formats can be
+  /// captured; BGRA gets swizzled into RGBA.
+  pub fn capture_frame(&mut self) -> Result<image::RgbaImage, HalError> {
+    let swizzle_bgra = self.capture_format()?;
+    let capture = match (self.pending_capture.take(), &*self.target) {
+      (Some(capture), _) => capture,
+      (None, RenderTarget::Swapchain { .. }) => return Err(HalError::NoFrameCaptured),
+      (None, RenderTarget::Offscreen { .. }) => {
+        let image_index = self.last_image.ok_or(HalError::NoFrameDrawn)?;
+        self.copy_offscreen_image(image_index)?
+      }
+    };
+    let result = self.read_capture(&capture, swizzle_bgra);
+    unsafe {
+      capture
+        .staging_bundle
+        .manually_drop(self.device.deref(), &mut self.allocator)
+    };
+    result
+  }
+
+  /// If captured frames need their channels swizzled, or an error if they
+  /// can't be captured at all.
+  fn capture_format(&self) -> Result<bool, HalError> {
+    match self.format.base_format().0 {
+      SurfaceType::R8_G8_B8_A8 => Ok(false),
+      SurfaceType::B8_G8_R8_A8 => Ok(true),
+      _ => Err(HalError::UnsupportedFormat(self.format)),
+    }
+  }
+
+  /// Makes the staging buffer for a capture of the image, if one was asked for.
+  fn requested_capture(&mut self, image_index: usize) -> Result<Option<Capture<B>>, HalError> {
+    if !self.capture_requested {
+      return Ok(None);
+    }
+    self.capture_requested = false;
+    self.new_capture(image_index).map(Some)
+  }
+
+  fn new_capture(&mut self, image_index: usize) -> Result<Capture<B>, HalError> {
+    // Compute the padded row size like when we upload an image, just in the
+    // other direction this time.
+    let row_size = size_of::<image::Rgba<u8>>() * (self.extent.width as usize);
+    let limits = self.adapter.physical_device.limits();
+    let row_alignment_mask = limits.min_buffer_copy_pitch_alignment as u32 - 1;
+    let row_pitch = ((row_size as u32 + row_alignment_mask) & !row_alignment_mask) as usize;
+    debug_assert!(row_pitch as usize >= row_size);
+    let staging_bundle = BufferBundle::new_transient(
+      self.device.deref(),
+      &mut self.allocator,
+      row_pitch * self.extent.height as usize,
+      BufferUsage::TRANSFER_DST,
+    )?;
+    Ok(Capture {
+      staging_bundle,
+      row_pitch,
+      extent: self.extent,
+      image_index,
+    })
+  }
+
+  /// Keeps the capture for `capture_frame`, throwing out an older one that
+  /// nobody asked for.
+  fn set_pending_capture(&mut self, capture: Option<Capture<B>>) {
+    if let Some(capture) = capture {
+      if let Some(old) = self.pending_capture.replace(capture) {
+        // the old capture's frame is done, its fence was waited on before the
+        // image could be drawn to again
+        unsafe {
+          old
+            .staging_bundle
+            .manually_drop(self.device.deref(), &mut self.allocator)
+        };
+      }
+    }
+  }
+
+  /// Records the copy of the captured image into its staging buffer, with a
+  /// barrier on either side to get it in and out of TransferSrcOptimal.
+  unsafe fn record_capture<C, S>(
+    cmd_buffer: &mut CommandBuffer<B, C, S>, target: &RenderTarget<B>, capture: &Capture<B>,
+  ) where
+    C: Supports<Transfer>,
+    S: Shot,
+  {
+    let the_image = target.image(capture.image_index);
+    let resting_layout = target.final_layout();
+    let color_range = SubresourceRange {
+      aspects: Aspects::COLOR,
+      levels: 0..1,
+      layers: 0..1,
+    };
+    let image_barrier = Barrier::Image {
+      states: (ImageAccess::COLOR_ATTACHMENT_WRITE, resting_layout)
+        ..(ImageAccess::TRANSFER_READ, Layout::TransferSrcOptimal),
+      target: the_image,
+      families: None,
+      range: color_range.clone(),
+    };
+    cmd_buffer.pipeline_barrier(
+      PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+      Dependencies::empty(),
+      &[image_barrier],
+    );
+    let (width, height) = (capture.extent.width, capture.extent.height);
+    cmd_buffer.copy_image_to_buffer(
+      the_image,
+      Layout::TransferSrcOptimal,
+      &capture.staging_bundle.buffer,
+      &[BufferImageCopy {
+        buffer_offset: 0,
+        buffer_width: (capture.row_pitch / size_of::<image::Rgba<u8>>()) as u32,
+        buffer_height: height,
+        image_layers: SubresourceLayers {
+          aspects: Aspects::COLOR,
+          level: 0,
+          layers: 0..1,
+        },
+        image_offset: Offset { x: 0, y: 0, z: 0 },
+        image_extent: Extent {
+          width,
+          height,
+          depth: 1,
+        },
+      }],
+    );
+    let image_barrier = Barrier::Image {
+      states: (ImageAccess::TRANSFER_READ, Layout::TransferSrcOptimal)
+        ..(ImageAccess::empty(), resting_layout),
+      target: the_image,
+      families: None,
+      range: color_range,
+    };
+    cmd_buffer.pipeline_barrier(
+      PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+      Dependencies::empty(),
+      &[image_barrier],
+    );
+  }
+
+  /// Copies an offscreen image that was already drawn, with a one shot command
+  /// buffer that this waits on. Offscreen images are never presented, so they
+  /// can be copied whenever.
+  fn copy_offscreen_image(&mut self, image_index: usize) -> Result<Capture<B>, HalError> {
+    let capture = self.new_capture(image_index)?;
+    unsafe {
+      // wait for the frame to be done rendering
+      self
+        .device
+        .wait_for_fence(&self.in_flight_fences[image_index], core::u64::MAX)?;
+
+      let mut cmd_buffer = self.command_pool.acquire_command_buffer::<OneShot>();
+      cmd_buffer.begin();
+      Self::record_capture(&mut cmd_buffer, &self.target, &capture);
+      cmd_buffer.finish();
+
+      let copy_fence = self.device.create_fence(false)?;
+      self.queue_group.queues[0].submit_nosemaphores(Some(&cmd_buffer), Some(&copy_fence));
+      self.device.wait_for_fence(&copy_fence, core::u64::MAX)?;
+      self.device.destroy_fence(copy_fence);
+      self.command_pool.free(Some(cmd_buffer));
+    }
+    Ok(capture)
+  }
+
+  /// Waits for the copy to be done, then reads the rows back out, dropping the
+  /// padding and fixing the channel order as we go.
+  fn read_capture(
+    &self, capture: &Capture<B>, swizzle_bgra: bool,
+  ) -> Result<image::RgbaImage, HalError> {
+    let (width, height) = (capture.extent.width, capture.extent.height);
+    let pixel_size = size_of::<image::Rgba<u8>>();
+    let row_size = pixel_size * (width as usize);
+    let mut pixels = Vec::with_capacity(row_size * height as usize);
+    unsafe {
+      // the fences get remade along with the swapchain, after waiting for the
+      // device to be idle, so a missing one means the copy is long done
+      if let Some(fence) = self.in_flight_fences.get(capture.image_index) {
+        self.device.wait_for_fence(fence, core::u64::MAX)?;
+      }
+      let staging_bundle = &capture.staging_bundle;
+      let reader = self.device.acquire_mapping_reader::<u8>(
+        self.allocator.memory(&staging_bundle.allocation),
+        staging_bundle.allocation.range(),
+      )?;
+      for y in 0..height as usize {
+        let src_base = y * capture.row_pitch;
+        pixels.extend_from_slice(&reader[src_base..src_base + row_size]);
+      }
+      self.device.release_mapping_reader(reader);
+    }
+    if swizzle_bgra {
+      for pixel in pixels.chunks_mut(pixel_size) {
+        pixel.swap(0, 2);
+      }
+    }
+    Ok(
+      image::RgbaImage::from_raw(width, height, pixels)
+        .expect("we always read back exactly width * height pixels"),
+    )
+  }
+}
+
+/// A headless instance, and whatever it needs kept alive after it.
+type HeadlessInstance = (
+  Box<dyn Instance<Backend = back::Backend>>,
+  Option<Box<dyn Any>>,
+);
+
+impl HalState<back::Backend> {
+  /// Creates a new, fully initialized HalState for the window given, using the
+  /// backend selected by the crate's features.
+  ///
+  /// Only the native backends (`dx12`, `metal`, `vulkan`) can draw to a plain
+  /// winit window.
+  #[cfg(feature = "windowed")]
+  pub fn new(window: &Window) -> Result<Self, HalError> {
+    Self::new_with_config(window, &HalConfig::default())
+  }
+
+  /// Like [`HalState::new`], but set up according to the config given.
+  #[cfg(feature = "windowed")]
+  pub fn new_with_config(window: &Window, config: &HalConfig) -> Result<Self, HalError> {
+    // Create An Instance
+    let instance = back::Instance::create(WINDOW_NAME, 1);
+
+    // Create A Surface
+    let surface = instance.create_surface(window);
+
+    // Figure out how big the window is
+    let window_extent = {
+      let window_client_area = window
+        .get_inner_size()
+        .ok_or(HalError::WindowClosed)?
+        .to_physical(window.get_hidpi_factor());
+      Extent2D {
+        width: window_client_area.width as u32,
+        height: window_client_area.height as u32,
+      }
+    };
+
+    Self::from_surface(Box::new(instance), surface, window_extent, config)
+  }
+
+  /// Creates a new, fully initialized HalState that draws into offscreen
+  /// `Rgba8Srgb` images of the size given, using the backend selected by the
+  /// crate's features.
+  pub fn new_headless(extent: Extent2D, use_depth: bool) -> Result<Self, HalError> {
+    Self::new_headless_with_config(extent, &HalConfig::default().depth(use_depth))
+  }
+
+  /// Like [`HalState::new_headless`], but set up according to the config
+  /// given. The offscreen images use the first of the config's formats that
+  /// can be rendered to, `Rgba8Srgb` by default.
+  pub fn new_headless_with_config(extent: Extent2D, config: &HalConfig) -> Result<Self, HalError> {
+    // Create An Instance
+    let (instance, keep_alive) = Self::create_headless_instance()?;
+
+    let mut hal_state = Self::from_instance_headless(instance, extent, config)?;
+    hal_state.keep_alive = keep_alive;
+    Ok(hal_state)
+  }
+
+  /// Lists all the adapters that the backend selected by the crate's features
+  /// can see, so you can decide on an `AdapterPreference`.
+  pub fn enumerate_adapters() -> Result<Vec<AdapterReport>, HalError> {
+    let (instance, _keep_alive) = Self::create_headless_instance()?;
+    Ok(enumerate_adapters(instance.as_ref()))
+  }
+
+  #[cfg(feature = "windowed")]
+  fn create_headless_instance() -> Result<HeadlessInstance, HalError> {
+    Ok((Box::new(back::Instance::create(WINDOW_NAME, 1)), None))
+  }
+
+  /// The empty backend never has any adapters, so this will always lead to an
+  /// error later on, but at least everything compiles.
+  #[cfg(feature = "empty")]
+  fn create_headless_instance() -> Result<HeadlessInstance, HalError> {
+    Ok((Box::new(back::Instance), None))
+  }
+
+  /// GL has no instance of its own, so we make a GL context that isn't
+  /// attached to any visible window and enumerate adapters from that. The
+  /// context needs its events loop, so that's handed back to be kept alive.
+  #[cfg(feature = "gl")]
+  fn create_headless_instance() -> Result<HeadlessInstance, HalError> {
+    use back::glutin::{Context, ContextBuilder};
+    let events_loop = gl_events_loop()?;
+    let context = Context::new(&events_loop, ContextBuilder::new(), false)?;
+    let instance = back::Headless(context);
+    Ok((Box::new(instance), Some(Box::new(events_loop))))
+  }
+}
+
+/// On Linux and the BSDs glutin makes the headless context with OSMesa, which
+/// doesn't use the events loop at all, but it still has to be given one, and
+/// `EventsLoop::new` panics when there's no X11 or Wayland display to connect
+/// to. So we ask for one in a way that can fail instead.
+#[cfg(all(
+  feature = "gl",
+  any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "openbsd"
+  )
+))]
+fn gl_events_loop() -> Result<back::glutin::EventsLoop, HalError> {
+  use back::glutin::{os::unix::EventsLoopExt, EventsLoop};
+  match EventsLoop::new_x11() {
+    Ok(events_loop) => Ok(events_loop),
+    // this one panics too if it can't connect, so only try it when there's
+    // a compositor to connect to
+    Err(_) if std::env::var_os("WAYLAND_DISPLAY").is_some() => Ok(EventsLoop::new_wayland()),
+    Err(e) => {
+      debug!("No X11 display: {}", e);
+      Err(HalError::GlNoDisplay)
+    }
+  }
+}
+
+#[cfg(all(
+  feature = "gl",
+  not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "openbsd"
+  ))
+))]
+fn gl_events_loop() -> Result<back::glutin::EventsLoop, HalError> {
+  Ok(back::glutin::EventsLoop::new())
+}
+
+impl<B: Backend> core::ops::Drop for HalState<B> {
+  /// We have to clean up "leaf" elements before "root" elements. Basically, we
+  /// clean up in reverse of the order that we created things.
+  fn drop(&mut self) {
+    let _ = self.device.wait_idle();
+    unsafe {
+      for depth_image in self.depth_images.drain(..) {
+        depth_image.manually_drop(&self.device, &mut self.allocator);
+      }
+      for msaa_image in self.msaa_images.drain(..) {
+        msaa_image.manually_drop(self.device.deref(), &mut self.allocator);
+      }
+      for descriptor_set_layout in self.descriptor_set_layouts.drain(..) {
+        self
+          .device
+          .destroy_descriptor_set_layout(descriptor_set_layout)
+      }
+      for fence in self.in_flight_fences.drain(..) {
+        self.device.destroy_fence(fence)
+      }
+      for semaphore in self.render_finished_semaphores.drain(..) {
+        self.device.destroy_semaphore(semaphore)
+      }
+      for semaphore in self.image_available_semaphores.drain(..) {
+        self.device.destroy_semaphore(semaphore)
+      }
+      for framebuffer in self.framebuffers.drain(..) {
+        self.device.destroy_framebuffer(framebuffer);
+      }
+      if let Some(capture) = self.pending_capture.take() {
+        capture
+          .staging_bundle
+          .manually_drop(self.device.deref(), &mut self.allocator);
+      }
+      // LAST RESORT STYLE CODE, NOT TO BE IMITATED LIGHTLY
+      self
+        .cube_vertices
+        .manually_drop(self.device.deref(), &mut self.allocator);
+      self
+        .cube_indexes
+        .manually_drop(self.device.deref(), &mut self.allocator);
+      for buf in self.cube_instances.drain(..) {
+        buf.manually_drop(self.device.deref(), &mut self.allocator);
+      }
+      for buf in self.triangle_vertices.drain(..) {
+        buf.manually_drop(self.device.deref(), &mut self.allocator);
+      }
+      self
+        .texture
+        .manually_drop(self.device.deref(), &mut self.allocator);
+      self.samplers.manually_drop(self.device.deref());
+      use core::ptr::read;
+      // this implicitly frees all descriptor sets from this pool
+      self
+        .device
+        .destroy_descriptor_pool(ManuallyDrop::into_inner(read(&self.descriptor_pool)));
+      self
+        .device
+        .destroy_pipeline_layout(ManuallyDrop::into_inner(read(&self.pipeline_layout)));
+      for (_, pipeline) in self.pipelines.drain() {
+        self.device.destroy_graphics_pipeline(pipeline);
+      }
+      self
+        .device
+        .destroy_graphics_pipeline(ManuallyDrop::into_inner(read(&self.triangle_pipeline)));
+      self
+        .device
+        .destroy_pipeline_layout(ManuallyDrop::into_inner(read(
+          &self.triangle_pipeline_layout,
+        )));
+      self
+        .device
+        .destroy_command_pool(ManuallyDrop::into_inner(read(&self.command_pool)).into_raw());
+      self
+        .device
+        .destroy_render_pass(ManuallyDrop::into_inner(read(&self.render_pass)));
+      ManuallyDrop::into_inner(read(&self.target)).manually_drop(&self.device, &mut self.allocator);
+      self
+        .uploader
+        .manually_drop(self.device.deref(), &mut self.allocator);
+      self.allocator.manually_drop(self.device.deref());
+      ManuallyDrop::drop(&mut self.device);
+      ManuallyDrop::drop(&mut self._instance);
+    }
+  }
+}
@@ -0,0 +1,99 @@
+#![allow(clippy::len_zero)]
+#![allow(clippy::many_single_char_names)]
+
+//! The reusable parts of the examples, pulled out into one place.
+//!
+//! Each example in the `examples/` directory goes along with a lesson in the
+//! book, and draws with the building blocks in this crate (as of the Instanced
+//! Drawing lesson) instead of pasting them around again and again.
+//!
+//! Everything except [`HalState::new`] and [`HalState::new_headless`] is
+//! generic over the gfx-hal `Backend`. Those two use whichever backend was
+//! picked with the crate's features, which is also re-exported as `back`. The
+//! `gl` and `empty` backends can only be used headless; the `empty` backend
+//! doesn't draw anything at all, it's just for checking that things compile.
+
+#[cfg(not(any(
+  feature = "dx12",
+  feature = "metal",
+  feature = "vulkan",
+  feature = "gl",
+  feature = "empty"
+)))]
+compile_error!(
+  "No backend selected! Enable one of the `dx12`, `metal`, `vulkan`, `gl` or `empty` features, \
+   eg: `cargo build --features=vulkan`"
+);
+
+#[cfg(feature = "dx12")]
+pub use gfx_backend_dx12 as back;
+#[cfg(feature = "empty")]
+pub use gfx_backend_empty as back;
+#[cfg(feature = "gl")]
+pub use gfx_backend_gl as back;
+#[cfg(feature = "metal")]
+pub use gfx_backend_metal as back;
+#[cfg(feature = "vulkan")]
+pub use gfx_backend_vulkan as back;
+
+mod adapter;
+mod allocator;
+mod atlas;
+mod bc_decode;
+mod buffer_bundle;
+mod camera;
+mod cast;
+mod color_image;
+mod compressed;
+mod config;
+mod depth_image;
+mod error;
+mod hal_state;
+mod loaded_image;
+mod reflect;
+mod sampler;
+mod shaders;
+mod texture;
+mod uploader;
+mod vertex_layout;
+mod winit_state;
+
+pub use adapter::{enumerate_adapters, select_adapter, AdapterPreference, AdapterReport};
+pub use allocator::{Allocation, Allocator, Lifetime};
+pub use atlas::{AtlasBuilder, AtlasTooSmall, PackedAtlas, UvRect};
+pub use buffer_bundle::BufferBundle;
+pub use camera::{EulerFPSCamera, QuaternionFreeCamera};
+pub use cast::cast_slice;
+pub use color_image::ColorImage;
+pub use compressed::{CompressedTexture, ContainerError};
+pub use config::{ChosenConfig, HalConfig};
+pub use depth_image::DepthImage;
+pub use error::HalError;
+pub use hal_state::{
+  CubeInstance, FrameStatus, HalState, Triangle, TriangleVertex, Vertex, CREATURE_BYTES,
+  CUBE_INDEXES, CUBE_VERTEXES, FRAGMENT_SOURCE, FRAGMENT_SPIRV, MAX_CUBES, VERTEX_SOURCE,
+  VERTEX_SPIRV,
+};
+pub use learn_gfx_hal_derive::VertexLayout;
+pub use loaded_image::LoadedImage;
+pub use reflect::{DescriptorBinding, PipelineInterface, ReflectError, ShaderReflection};
+pub use sampler::{SamplerCache, SamplerDesc, SamplerId};
+pub use shaders::{ShaderSource, ShaderSources, ShaderVariant, ShaderWatcher, SpirvCache};
+pub use texture::{ColorSpace, Gray16Image, RgbaF32Image, TextureDesc, TextureKind, TextureSource};
+pub use uploader::{UploadToken, Uploader};
+pub use vertex_layout::{VertexFormat, VertexLayout};
+pub use winit_state::{UserInput, WinitState};
+
+// The code that the derive macros make names this crate, so it has to have
+// that name in here too.
+extern crate self as learn_gfx_hal;
+
+/// What the code made by the derive macros uses.
+#[doc(hidden)]
+pub mod __derive {
+  pub use crate::vertex_layout::AttributeBuilder;
+  pub use gfx_hal::{format::Format, pso::AttributeDesc};
+}
+
+/// The name used for the window title and the gfx-hal instance.
+pub const WINDOW_NAME: &str = "learn-gfx-hal";
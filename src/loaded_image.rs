@@ -0,0 +1,420 @@
+use crate::{
+  texture::{LayeredTexels, TexelLevel, TextureShape},
+  Allocation, Allocator, BufferBundle, HalError, Lifetime, SamplerCache, SamplerDesc, SamplerId,
+  TextureDesc, TextureKind, TextureSource,
+};
+use core::{marker::PhantomData, mem::ManuallyDrop};
+use gfx_hal::{
+  adapter::{Adapter, PhysicalDevice},
+  buffer::Usage as BufferUsage,
+  command::{BufferImageCopy, CommandBuffer, ImageBlit, OneShot},
+  device::Device,
+  format::{Aspects, Format},
+  image::{
+    Access as ImageAccess, Filter, Layer, Layout, Level, Offset, SubresourceLayers,
+    SubresourceRange, Usage,
+  },
+  memory::{Barrier, Dependencies, Properties, Requirements},
+  pool::CommandPool,
+  pso::PipelineStage,
+  queue::{
+    capability::{Capability, Supports, Transfer},
+    CommandQueue,
+  },
+  Backend, Graphics,
+};
+
+/// Parts for an image that we uploaded from the CPU and use via sampler
+pub struct LoadedImage<B: Backend, D: Device<B>> {
+  pub image: ManuallyDrop<B::Image>,
+  pub requirements: Requirements,
+  pub allocation: ManuallyDrop<Allocation>,
+  pub image_view: ManuallyDrop<B::ImageView>,
+  /// The sampler lives in the `SamplerCache` so that textures can share it.
+  pub sampler: SamplerId,
+  pub format: Format,
+  /// How many mip levels the image has, 1 if it doesn't have mipmaps.
+  pub mip_levels: Level,
+  pub kind: TextureKind,
+  /// How many layers the image has, 6 for a cube map.
+  pub layers: Layer,
+  pub phantom: PhantomData<D>,
+}
+impl<B: Backend, D: Device<B>> LoadedImage<B, D> {
+  /// Uploads the image and waits for it to be done. Any mip levels are made
+  /// on the CPU, use an `Uploader` to have them blitted on the GPU instead.
+  #[allow(clippy::too_many_arguments)]
+  pub fn new<C: Capability + Supports<Transfer>, S: Into<TextureSource>>(
+    adapter: &Adapter<B>, device: &D, allocator: &mut Allocator<B>, samplers: &mut SamplerCache<B>,
+    command_pool: &mut CommandPool<B, C>, command_queue: &mut CommandQueue<B, C>, source: S,
+    desc: &TextureDesc,
+  ) -> Result<Self, HalError> {
+    Self::new_layered(
+      adapter,
+      device,
+      allocator,
+      samplers,
+      command_pool,
+      command_queue,
+      vec![source],
+      TextureKind::D2,
+      desc,
+    )
+  }
+
+  /// Like `new`, but for a texture array or cube map with one source per
+  /// layer. Every source has to be the same size and end up in the same
+  /// format, and a cube map takes its faces in the order +X, -X, +Y, -Y, +Z,
+  /// -Z.
+  #[allow(clippy::too_many_arguments)]
+  pub fn new_layered<C: Capability + Supports<Transfer>, S: Into<TextureSource>>(
+    adapter: &Adapter<B>, device: &D, allocator: &mut Allocator<B>, samplers: &mut SamplerCache<B>,
+    command_pool: &mut CommandPool<B, C>, command_queue: &mut CommandQueue<B, C>, sources: Vec<S>,
+    kind: TextureKind, desc: &TextureDesc,
+  ) -> Result<Self, HalError> {
+    let sources = sources.into_iter().map(Into::into).collect();
+    let texels = LayeredTexels::new(adapter, sources, kind, desc, false)?;
+    let shape = texels.shape;
+    unsafe {
+      // 1. and 2. put the image data into a staging buffer
+      let (staging_bundle, copy_regions) =
+        stage_images(adapter, device, allocator, &texels.layers)?;
+
+      // 3. 4. and 5. make the image, its memory, its view and the sampler
+      let loaded = Self::new_unfilled(device, allocator, samplers, &shape, &desc.sampler)?;
+
+      // 6. create a command buffer
+      let mut cmd_buffer = command_pool.acquire_command_buffer::<gfx_hal::command::OneShot>();
+      cmd_buffer.begin();
+
+      // 7. Use a pipeline barrier to transition the image from empty/undefined
+      //    to TRANSFER_WRITE/TransferDstOptimal
+      let image_barrier = gfx_hal::memory::Barrier::Image {
+        states: (gfx_hal::image::Access::empty(), Layout::Undefined)
+          ..(
+            gfx_hal::image::Access::TRANSFER_WRITE,
+            Layout::TransferDstOptimal,
+          ),
+        target: &*loaded.image,
+        families: None,
+        range: SubresourceRange {
+          aspects: Aspects::COLOR,
+          levels: 0..shape.mip_levels,
+          layers: 0..shape.layers,
+        },
+      };
+      cmd_buffer.pipeline_barrier(
+        PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+        gfx_hal::memory::Dependencies::empty(),
+        &[image_barrier],
+      );
+
+      // 8. perform copy from staging buffer to image
+      cmd_buffer.copy_buffer_to_image(
+        &staging_bundle.buffer,
+        &loaded.image,
+        Layout::TransferDstOptimal,
+        &copy_regions,
+      );
+
+      // 9. use pipeline barrier to transition the image to SHADER_READ access/
+      //    ShaderReadOnlyOptimal layout
+      let image_barrier = gfx_hal::memory::Barrier::Image {
+        states: (
+          gfx_hal::image::Access::TRANSFER_WRITE,
+          Layout::TransferDstOptimal,
+        )
+          ..(
+            gfx_hal::image::Access::SHADER_READ,
+            Layout::ShaderReadOnlyOptimal,
+          ),
+        target: &*loaded.image,
+        families: None,
+        range: SubresourceRange {
+          aspects: Aspects::COLOR,
+          levels: 0..shape.mip_levels,
+          layers: 0..shape.layers,
+        },
+      };
+      cmd_buffer.pipeline_barrier(
+        PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+        gfx_hal::memory::Dependencies::empty(),
+        &[image_barrier],
+      );
+
+      // 10. Submit the cmd buffer to queue and wait for it
+      cmd_buffer.finish();
+      let upload_fence = device.create_fence(false)?;
+      command_queue.submit_nosemaphores(Some(&cmd_buffer), Some(&upload_fence));
+      device.wait_for_fence(&upload_fence, core::u64::MAX)?;
+      device.destroy_fence(upload_fence);
+
+      // 11. Destroy the staging bundle and one shot buffer now that we're done
+      staging_bundle.manually_drop(device, allocator);
+      command_pool.free(Some(cmd_buffer));
+
+      Ok(loaded)
+    }
+  }
+
+  /// Makes an image that can be sampled and copied into, along with its view
+  /// and a sampler, without putting anything in it yet.
+  ///
+  /// With more than 1 mip level the image can also be blitted from, to fill in
+  /// the smaller levels.
+  pub(crate) fn new_unfilled(
+    device: &D, allocator: &mut Allocator<B>, samplers: &mut SamplerCache<B>, shape: &TextureShape,
+    sampler_desc: &SamplerDesc,
+  ) -> Result<Self, HalError> {
+    let TextureShape {
+      kind,
+      width,
+      height,
+      format,
+      mip_levels,
+      layers,
+    } = *shape;
+    unsafe {
+      // Make an image with transfer_dst and SAMPLED usage
+      let usage = if mip_levels > 1 {
+        Usage::TRANSFER_DST | Usage::TRANSFER_SRC | Usage::SAMPLED
+      } else {
+        Usage::TRANSFER_DST | Usage::SAMPLED
+      };
+      let mut the_image = device.create_image(
+        gfx_hal::image::Kind::D2(width, height, layers, 1),
+        mip_levels,
+        format,
+        gfx_hal::image::Tiling::Optimal,
+        usage,
+        kind.view_capabilities(),
+      )?;
+
+      // allocate memory for the image and bind it
+      let requirements = device.get_image_requirements(&the_image);
+      let allocation = allocator.allocate(
+        device,
+        requirements,
+        Properties::DEVICE_LOCAL,
+        gfx_hal::image::Tiling::Optimal,
+        Lifetime::LongLived,
+      )?;
+      device.bind_image_memory(
+        allocator.memory(&allocation),
+        allocation.offset,
+        &mut the_image,
+      )?;
+
+      // create image view and sampler
+      let image_view = device.create_image_view(
+        &the_image,
+        kind.view_kind(),
+        format,
+        gfx_hal::format::Swizzle::NO,
+        SubresourceRange {
+          aspects: Aspects::COLOR,
+          levels: 0..mip_levels,
+          layers: 0..layers,
+        },
+      )?;
+      let sampler = samplers.get(device, sampler_desc)?;
+
+      Ok(Self {
+        image: ManuallyDrop::new(the_image),
+        requirements,
+        allocation: ManuallyDrop::new(allocation),
+        image_view: ManuallyDrop::new(image_view),
+        sampler,
+        format,
+        mip_levels,
+        kind,
+        layers,
+        phantom: PhantomData,
+      })
+    }
+  }
+
+  /// # Safety
+  ///
+  /// No draw that samples the texture can still be running, and the texture
+  /// has to have come from this `device` and `allocator`.
+  pub unsafe fn manually_drop(&self, device: &D, allocator: &mut Allocator<B>) {
+    use core::ptr::read;
+    device.destroy_image_view(ManuallyDrop::into_inner(read(&self.image_view)));
+    device.destroy_image(ManuallyDrop::into_inner(read(&self.image)));
+    allocator.free(device, ManuallyDrop::into_inner(read(&self.allocation)));
+  }
+}
+
+/// Records filling in mip levels 1 and up by blitting each level down from
+/// the one above it, and leaves every level in `ShaderReadOnlyOptimal`.
+///
+/// All the levels have to be in `TransferDstOptimal` already, with level 0
+/// written. Every layer is blitted at once.
+pub(crate) unsafe fn record_mip_blits<B: Backend>(
+  commands: &mut CommandBuffer<B, Graphics, OneShot>, image: &B::Image, shape: &TextureShape,
+) {
+  let TextureShape {
+    width,
+    height,
+    mip_levels,
+    layers: layer_count,
+    ..
+  } = *shape;
+  let range = |level: Level| SubresourceRange {
+    aspects: Aspects::COLOR,
+    levels: level..level + 1,
+    layers: 0..layer_count,
+  };
+  let layers = |level: Level| SubresourceLayers {
+    aspects: Aspects::COLOR,
+    level,
+    layers: 0..layer_count,
+  };
+  let bounds = |level: Level| {
+    Offset { x: 0, y: 0, z: 0 }..Offset {
+      x: (width >> level).max(1) as i32,
+      y: (height >> level).max(1) as i32,
+      z: 1,
+    }
+  };
+  for level in 1..mip_levels {
+    let src_level = level - 1;
+    commands.pipeline_barrier(
+      PipelineStage::TRANSFER..PipelineStage::TRANSFER,
+      Dependencies::empty(),
+      &[Barrier::Image {
+        states: (ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal)
+          ..(ImageAccess::TRANSFER_READ, Layout::TransferSrcOptimal),
+        target: image,
+        families: None,
+        range: range(src_level),
+      }],
+    );
+    commands.blit_image(
+      image,
+      Layout::TransferSrcOptimal,
+      image,
+      Layout::TransferDstOptimal,
+      Filter::Linear,
+      &[ImageBlit {
+        src_subresource: layers(src_level),
+        src_bounds: bounds(src_level),
+        dst_subresource: layers(level),
+        dst_bounds: bounds(level),
+      }],
+    );
+    commands.pipeline_barrier(
+      PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+      Dependencies::empty(),
+      &[Barrier::Image {
+        states: (ImageAccess::TRANSFER_READ, Layout::TransferSrcOptimal)
+          ..(ImageAccess::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+        target: image,
+        families: None,
+        range: range(src_level),
+      }],
+    );
+  }
+  commands.pipeline_barrier(
+    PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+    Dependencies::empty(),
+    &[Barrier::Image {
+      states: (ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal)
+        ..(ImageAccess::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+      target: image,
+      families: None,
+      range: range(mip_levels - 1),
+    }],
+  );
+}
+
+/// Puts the texels of each level of each layer into one transient staging
+/// buffer, with each row padded out to the adapter's copy pitch alignment, and
+/// gives back the regions to copy from it into the layers and mip levels of a
+/// 2D image, starting at layer 0 and level 0.
+pub(crate) fn stage_images<B: Backend, D: Device<B>>(
+  adapter: &Adapter<B>, device: &D, allocator: &mut Allocator<B>, layers: &[Vec<TexelLevel>],
+) -> Result<(BufferBundle<B, D>, Vec<BufferImageCopy>), HalError> {
+  // every level of every layer, along with where it goes in the image
+  let levels: Vec<(Layer, Level, &TexelLevel)> = layers
+    .iter()
+    .enumerate()
+    .flat_map(|(layer, levels)| {
+      levels
+        .iter()
+        .enumerate()
+        .map(move |(level, texels)| (layer as Layer, level as Level, texels))
+    })
+    .collect();
+  unsafe {
+    // 0. First we compute some memory related values. Texel (and block) sizes
+    //    are all powers of two, so rows and offsets can be aligned with masks.
+    //    A row here is a row of blocks, which is just a row of texels for
+    //    formats that aren't block compressed.
+    let limits = adapter.physical_device.limits();
+    let mut required_bytes = 0;
+    let layouts: Vec<(usize, usize, usize)> = levels
+      .iter()
+      .map(|&(_, _, level)| {
+        let row_alignment_mask =
+          (limits.min_buffer_copy_pitch_alignment as usize).max(level.texel_size) - 1;
+        let offset_alignment_mask =
+          (limits.min_buffer_copy_offset_alignment as usize).max(level.texel_size) - 1;
+        let row_size = level.texel_size * blocks(level.width, level.block_size);
+        let row_pitch = (row_size + row_alignment_mask) & !row_alignment_mask;
+        let offset = (required_bytes + offset_alignment_mask) & !offset_alignment_mask;
+        required_bytes = offset + row_pitch * blocks(level.height, level.block_size);
+        (offset, row_size, row_pitch)
+      })
+      .collect();
+
+    // 1. make a staging buffer with enough memory for every level, and a
+    //    transfer_src usage
+    let staging_bundle =
+      BufferBundle::new_transient(device, allocator, required_bytes, BufferUsage::TRANSFER_SRC)?;
+
+    // 2. use mapping writer to put the image data into that buffer
+    let mut writer = device.acquire_mapping_writer::<u8>(
+      allocator.memory(&staging_bundle.allocation),
+      staging_bundle.allocation.range(),
+    )?;
+    for (&(_, _, level), &(offset, row_size, row_pitch)) in levels.iter().zip(layouts.iter()) {
+      for y in 0..blocks(level.height, level.block_size) {
+        let row = &level.bytes[y * row_size..(y + 1) * row_size];
+        let dest_base = offset + y * row_pitch;
+        writer[dest_base..dest_base + row.len()].copy_from_slice(row);
+      }
+    }
+    device.release_mapping_writer(writer)?;
+
+    let copy_regions = levels
+      .iter()
+      .zip(layouts.iter())
+      .map(
+        |(&(layer, index, level), &(offset, _, row_pitch))| BufferImageCopy {
+          buffer_offset: offset as u64,
+          buffer_width: (row_pitch / level.texel_size) as u32 * level.block_size,
+          buffer_height: blocks(level.height, level.block_size) as u32 * level.block_size,
+          image_layers: SubresourceLayers {
+            aspects: Aspects::COLOR,
+            level: index,
+            layers: layer..layer + 1,
+          },
+          image_offset: Offset { x: 0, y: 0, z: 0 },
+          image_extent: gfx_hal::image::Extent {
+            width: level.width,
+            height: level.height,
+            depth: 1,
+          },
+        },
+      )
+      .collect();
+    Ok((staging_bundle, copy_regions))
+  }
+}
+
+/// How many blocks it takes to cover `texels` texels.
+fn blocks(texels: u32, block_size: u32) -> usize {
+  ((texels + block_size - 1) / block_size) as usize
+}
@@ -0,0 +1,925 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use core::{fmt, ops::Range};
+use gfx_hal::{
+  format::{BaseFormat, ChannelType, Format, SurfaceType},
+  pso::{
+    AttributeDesc, DescriptorRangeDesc, DescriptorSetLayoutBinding, DescriptorType,
+    ShaderStageFlags,
+  },
+};
+use std::collections::HashMap;
+
+/// The first word of every SPIR-V module.
+pub(crate) const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// The opcodes that we look at.
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// The decorations that we look at.
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// The storage classes that we look at.
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// The image dimensionalities that aren't plain images.
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// How deeply types can nest inside each other. Real shaders don't come close,
+/// but a damaged module can have a type that contains itself.
+const MAX_TYPE_DEPTH: u32 = 32;
+
+/// Why a shader's interface couldn't be worked out, or doesn't fit what it's
+/// being used with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReflectError {
+  /// The bytes aren't a SPIR-V module.
+  NotSpirv,
+  /// The module ends in the middle of an instruction.
+  Truncated,
+  /// The module doesn't have an entry point.
+  NoEntryPoint,
+  /// The shader uses something that we can't describe, for the reason given.
+  Unsupported(&'static str),
+  /// Two stages use the same binding for different types of descriptor.
+  ConflictingDescriptor { set: u32, binding: u32 },
+  /// A descriptor is needed that the pipeline layout doesn't have.
+  MissingDescriptor {
+    set: u32,
+    binding: u32,
+    ty: DescriptorType,
+  },
+  /// Push constants are needed that the pipeline layout doesn't have.
+  MissingPushConstants {
+    stage: ShaderStageFlags,
+    range: Range<u32>,
+  },
+  /// The vertex shader reads a location that no attribute gives it.
+  MissingAttribute { location: u32, shader: Format },
+  /// The attribute for a location doesn't fit what the vertex shader reads
+  /// from it.
+  AttributeMismatch {
+    location: u32,
+    shader: Format,
+    attribute: Format,
+  },
+}
+impl fmt::Display for ReflectError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    use ReflectError::*;
+    match self {
+      NotSpirv => write!(f, "Not a SPIR-V module"),
+      Truncated => write!(f, "The SPIR-V module is cut short"),
+      NoEntryPoint => write!(f, "The SPIR-V module doesn't have an entry point"),
+      Unsupported(what) => write!(f, "The shader uses {}, which isn't supported", what),
+      ConflictingDescriptor { set, binding } => write!(
+        f,
+        "Set {} binding {} is a different type of descriptor in each stage",
+        set, binding
+      ),
+      MissingDescriptor { set, binding, ty } => write!(
+        f,
+        "The pipeline layout doesn't have a {:?} at set {} binding {}",
+        ty, set, binding
+      ),
+      MissingPushConstants { stage, range } => write!(
+        f,
+        "The pipeline layout doesn't have push constant words {:?} for {:?}",
+        range, stage
+      ),
+      MissingAttribute { location, shader } => write!(
+        f,
+        "The vertex shader reads a {:?} from location {}, but no attribute gives it one",
+        shader, location
+      ),
+      AttributeMismatch {
+        location,
+        shader,
+        attribute,
+      } => write!(
+        f,
+        "The vertex shader reads a {:?} from location {}, but the attribute is a {:?}",
+        shader, location, attribute
+      ),
+    }
+  }
+}
+impl std::error::Error for ReflectError {}
+
+/// One descriptor that a shader uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+  pub set: u32,
+  pub binding: u32,
+  pub ty: DescriptorType,
+  /// More than 1 for an array of descriptors.
+  pub count: usize,
+  pub stages: ShaderStageFlags,
+}
+
+/// What one shader module takes in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderReflection {
+  pub stage: ShaderStageFlags,
+  /// The location and type of each input. Only filled in for vertex shaders,
+  /// where they're the vertex attributes.
+  pub inputs: Vec<(u32, Format)>,
+  pub descriptors: Vec<DescriptorBinding>,
+  /// The push constants used, in 32-bit words like gfx-hal wants.
+  pub push_constants: Option<Range<u32>>,
+}
+impl ShaderReflection {
+  /// Works out the interface of the module's first entry point.
+  pub fn new(spirv: &[u8]) -> Result<Self, ReflectError> {
+    Module::parse(spirv)?.reflect()
+  }
+}
+
+/// The interface of a whole pipeline, with the stages put together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineInterface {
+  /// The vertex shader's inputs, see `ShaderReflection::inputs`.
+  pub inputs: Vec<(u32, Format)>,
+  /// Sorted by set and then binding.
+  pub descriptors: Vec<DescriptorBinding>,
+  /// The push constant range of each stage that has one.
+  pub push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+}
+impl PipelineInterface {
+  /// Puts the stages together. Descriptors used by more than one stage get
+  /// all of their stages, and have to be the same type in each.
+  pub fn new(stages: &[ShaderReflection]) -> Result<Self, ReflectError> {
+    let mut interface = Self {
+      inputs: Vec::new(),
+      descriptors: Vec::new(),
+      push_constants: Vec::new(),
+    };
+    for stage in stages {
+      if stage.stage == ShaderStageFlags::VERTEX {
+        interface.inputs = stage.inputs.clone();
+      }
+      let push_constants = stage
+        .push_constants
+        .iter()
+        .map(|range| (stage.stage, range.clone()));
+      interface.add(&stage.descriptors, push_constants)?;
+    }
+    Ok(interface)
+  }
+
+  /// Adds the descriptors and push constants of `other`, for when something
+  /// besides the shaders needs them in the pipeline layout. The vertex inputs
+  /// are left alone.
+  pub fn merge(&mut self, other: &PipelineInterface) -> Result<(), ReflectError> {
+    self.add(&other.descriptors, other.push_constants.iter().cloned())
+  }
+
+  fn add<I: IntoIterator<Item = (ShaderStageFlags, Range<u32>)>>(
+    &mut self, descriptors: &[DescriptorBinding], push_constants: I,
+  ) -> Result<(), ReflectError> {
+    for descriptor in descriptors {
+      match self
+        .descriptors
+        .iter_mut()
+        .find(|d| (d.set, d.binding) == (descriptor.set, descriptor.binding))
+      {
+        Some(existing) if existing.ty != descriptor.ty || existing.count != descriptor.count => {
+          return Err(ReflectError::ConflictingDescriptor {
+            set: descriptor.set,
+            binding: descriptor.binding,
+          });
+        }
+        Some(existing) => existing.stages |= descriptor.stages,
+        None => self.descriptors.push(descriptor.clone()),
+      }
+    }
+    self.descriptors.sort_by_key(|d| (d.set, d.binding));
+    // each stage keeps one range, grown to cover everything it needs
+    for (stage, range) in push_constants {
+      match self.push_constants.iter_mut().find(|(s, _)| *s == stage) {
+        Some((_, existing)) => {
+          *existing = existing.start.min(range.start)..existing.end.max(range.end);
+        }
+        None => self.push_constants.push((stage, range)),
+      }
+    }
+    Ok(())
+  }
+
+  /// The bindings of each descriptor set, from set 0 up to the last one used.
+  /// Sets that nothing uses in between are empty.
+  pub fn set_layouts(&self) -> Vec<Vec<DescriptorSetLayoutBinding>> {
+    let set_count = self
+      .descriptors
+      .iter()
+      .map(|d| d.set + 1)
+      .max()
+      .unwrap_or(0);
+    (0..set_count)
+      .map(|set| {
+        self
+          .descriptors
+          .iter()
+          .filter(|d| d.set == set)
+          .map(|d| DescriptorSetLayoutBinding {
+            binding: d.binding,
+            ty: d.ty,
+            count: d.count,
+            stage_flags: d.stages,
+            immutable_samplers: false,
+          })
+          .collect()
+      })
+      .collect()
+  }
+
+  /// How many of each type of descriptor one of each set needs, for making a
+  /// descriptor pool.
+  pub fn descriptor_ranges(&self) -> Vec<DescriptorRangeDesc> {
+    let mut ranges: Vec<DescriptorRangeDesc> = Vec::new();
+    for descriptor in &self.descriptors {
+      match ranges.iter_mut().find(|range| range.ty == descriptor.ty) {
+        Some(range) => range.count += descriptor.count,
+        None => ranges.push(DescriptorRangeDesc {
+          ty: descriptor.ty,
+          count: descriptor.count,
+        }),
+      }
+    }
+    ranges
+  }
+
+  /// Checks that a pipeline layout made from `layout` has everything these
+  /// shaders use, so that they can be used with it.
+  pub fn fits_layout(&self, layout: &PipelineInterface) -> Result<(), ReflectError> {
+    for descriptor in &self.descriptors {
+      let found = layout.descriptors.iter().any(|d| {
+        (d.set, d.binding, d.ty) == (descriptor.set, descriptor.binding, descriptor.ty)
+          && d.count >= descriptor.count
+          && d.stages.contains(descriptor.stages)
+      });
+      if !found {
+        return Err(ReflectError::MissingDescriptor {
+          set: descriptor.set,
+          binding: descriptor.binding,
+          ty: descriptor.ty,
+        });
+      }
+    }
+    for (stage, range) in &self.push_constants {
+      let found = layout
+        .push_constants
+        .iter()
+        .any(|(s, r)| s.contains(*stage) && r.start <= range.start && range.end <= r.end);
+      if !found {
+        return Err(ReflectError::MissingPushConstants {
+          stage: *stage,
+          range: range.clone(),
+        });
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks that every location the vertex shader reads has an attribute with
+  /// the same type of numbers (float, signed, or unsigned) and no more
+  /// components than the shader reads. The shader can read more components
+  /// than the attribute has, the rest are filled in with `(0, 0, 0, 1)`.
+  ///
+  /// Attributes that the shader doesn't read are fine, they're just skipped.
+  pub fn check_attributes(&self, attributes: &[AttributeDesc]) -> Result<(), ReflectError> {
+    for &(location, shader) in &self.inputs {
+      let attribute = match attributes.iter().find(|a| a.location == location) {
+        Some(attribute) => attribute.element.format,
+        None => return Err(ReflectError::MissingAttribute { location, shader }),
+      };
+      let fits = match (vertex_format_shape(shader), vertex_format_shape(attribute)) {
+        (Some((shader_kind, shader_components)), Some((kind, components))) => {
+          shader_kind == kind && components <= shader_components
+        }
+        _ => false,
+      };
+      if !fits {
+        return Err(ReflectError::AttributeMismatch {
+          location,
+          shader,
+          attribute,
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+/// What sort of number a shader sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+  Float,
+  Int,
+  Uint,
+}
+
+/// The sort of number and the component count that a vertex format gives the
+/// shader.
+fn vertex_format_shape(format: Format) -> Option<(Scalar, u32)> {
+  use SurfaceType::*;
+  let BaseFormat(surface, channel) = format.base_format();
+  let scalar = match channel {
+    ChannelType::Int => Scalar::Int,
+    ChannelType::Uint => Scalar::Uint,
+    _ => Scalar::Float,
+  };
+  let components = match surface {
+    R8 | R16 | R32 | R64 => 1,
+    R8_G8 | R16_G16 | R32_G32 | R64_G64 => 2,
+    R8_G8_B8 | B8_G8_R8 | R16_G16_B16 | R32_G32_B32 | R64_G64_B64 => 3,
+    R8_G8_B8_A8 | B8_G8_R8_A8 | A8_B8_G8_R8 | R16_G16_B16_A16 | R32_G32_B32_A32
+    | R64_G64_B64_A64 | A2_B10_G10_R10 | A2_R10_G10_B10 => 4,
+    _ => return None,
+  };
+  Some((scalar, components))
+}
+
+/// A type declared in the module.
+#[derive(Debug, Clone)]
+enum Type {
+  Bool,
+  Int { width: u32, signed: bool },
+  Float { width: u32 },
+  Vector { component: u32, count: u32 },
+  Matrix { column: u32, count: u32 },
+  Image { dim: u32, sampled: u32 },
+  Sampler,
+  SampledImage,
+  Array { element: u32, length: u32 },
+  RuntimeArray,
+  Struct { members: Vec<u32> },
+  Pointer { pointee: u32 },
+}
+
+/// The decorations on an id, or on a struct member.
+#[derive(Debug, Clone, Default)]
+struct Decorations {
+  block: bool,
+  buffer_block: bool,
+  built_in: bool,
+  array_stride: Option<u32>,
+  matrix_stride: Option<u32>,
+  location: Option<u32>,
+  binding: Option<u32>,
+  set: Option<u32>,
+  offset: Option<u32>,
+}
+impl Decorations {
+  fn add(&mut self, decoration: u32, literals: &[u32]) {
+    let literal = literals.first().cloned();
+    match decoration {
+      DECORATION_BLOCK => self.block = true,
+      DECORATION_BUFFER_BLOCK => self.buffer_block = true,
+      DECORATION_BUILT_IN => self.built_in = true,
+      DECORATION_ARRAY_STRIDE => self.array_stride = literal,
+      DECORATION_MATRIX_STRIDE => self.matrix_stride = literal,
+      DECORATION_LOCATION => self.location = literal,
+      DECORATION_BINDING => self.binding = literal,
+      DECORATION_DESCRIPTOR_SET => self.set = literal,
+      DECORATION_OFFSET => self.offset = literal,
+      _ => (),
+    }
+  }
+}
+
+/// The parts of a module that say what its interface is.
+#[derive(Debug, Default)]
+struct Module {
+  /// The execution model and interface ids of the first entry point.
+  entry_point: Option<(u32, Vec<u32>)>,
+  types: HashMap<u32, Type>,
+  /// The first word of each integer constant, for array lengths.
+  constants: HashMap<u32, u32>,
+  /// The id, type, and storage class of each global variable.
+  variables: Vec<(u32, u32, u32)>,
+  decorations: HashMap<u32, Decorations>,
+  member_decorations: HashMap<(u32, u32), Decorations>,
+}
+impl Module {
+  fn parse(spirv: &[u8]) -> Result<Self, ReflectError> {
+    if spirv.len() % 4 != 0 || spirv.len() < 20 {
+      return Err(ReflectError::NotSpirv);
+    }
+    let words: Vec<u32> = spirv
+      .chunks(4)
+      .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+      .collect();
+    if words[0] != SPIRV_MAGIC {
+      return Err(ReflectError::NotSpirv);
+    }
+    let mut module = Module::default();
+    // the header is 5 words, and then it's all instructions
+    let mut i = 5;
+    while i < words.len() {
+      let word_count = (words[i] >> 16) as usize;
+      let opcode = words[i] & 0xFFFF;
+      if word_count == 0 || i + word_count > words.len() {
+        return Err(ReflectError::Truncated);
+      }
+      let operands = &words[i + 1..i + word_count];
+      module.instruction(opcode, operands)?;
+      i += word_count;
+    }
+    Ok(module)
+  }
+
+  fn instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), ReflectError> {
+    let operand = |index: usize| operands.get(index).cloned().ok_or(ReflectError::Truncated);
+    match opcode {
+      OP_ENTRY_POINT if self.entry_point.is_none() => {
+        // the name is a nul terminated string packed into words, and the
+        // interface ids come after it
+        let name_words = operands[2.min(operands.len())..]
+          .iter()
+          .position(|word| word >> 24 == 0)
+          .ok_or(ReflectError::Truncated)?
+          + 1;
+        let interface = operands[2 + name_words..].to_vec();
+        self.entry_point = Some((operand(0)?, interface));
+      }
+      OP_TYPE_BOOL => {
+        self.types.insert(operand(0)?, Type::Bool);
+      }
+      OP_TYPE_INT => {
+        let ty = Type::Int {
+          width: operand(1)?,
+          signed: operand(2)? != 0,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_FLOAT => {
+        let ty = Type::Float { width: operand(1)? };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_VECTOR => {
+        let ty = Type::Vector {
+          component: operand(1)?,
+          count: operand(2)?,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_MATRIX => {
+        let ty = Type::Matrix {
+          column: operand(1)?,
+          count: operand(2)?,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_IMAGE => {
+        let ty = Type::Image {
+          dim: operand(2)?,
+          sampled: operand(6)?,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_SAMPLER => {
+        self.types.insert(operand(0)?, Type::Sampler);
+      }
+      OP_TYPE_SAMPLED_IMAGE => {
+        self.types.insert(operand(0)?, Type::SampledImage);
+      }
+      OP_TYPE_ARRAY => {
+        let ty = Type::Array {
+          element: operand(1)?,
+          length: operand(2)?,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_RUNTIME_ARRAY => {
+        self.types.insert(operand(0)?, Type::RuntimeArray);
+      }
+      OP_TYPE_STRUCT => {
+        let ty = Type::Struct {
+          members: operands[1.min(operands.len())..].to_vec(),
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_TYPE_POINTER => {
+        let ty = Type::Pointer {
+          pointee: operand(2)?,
+        };
+        self.types.insert(operand(0)?, ty);
+      }
+      OP_CONSTANT => {
+        self.constants.insert(operand(1)?, operand(2)?);
+      }
+      OP_VARIABLE => {
+        // variables inside of functions are function storage, which we skip
+        self.variables.push((operand(1)?, operand(0)?, operand(2)?));
+      }
+      OP_DECORATE => {
+        self
+          .decorations
+          .entry(operand(0)?)
+          .or_default()
+          .add(operand(1)?, &operands[2..]);
+      }
+      OP_MEMBER_DECORATE => {
+        self
+          .member_decorations
+          .entry((operand(0)?, operand(1)?))
+          .or_default()
+          .add(operand(2)?, &operands[3..]);
+      }
+      _ => (),
+    }
+    Ok(())
+  }
+
+  fn ty(&self, id: u32) -> Result<&Type, ReflectError> {
+    self.types.get(&id).ok_or(ReflectError::NotSpirv)
+  }
+
+  fn decorations(&self, id: u32) -> Decorations {
+    self.decorations.get(&id).cloned().unwrap_or_default()
+  }
+
+  fn member_decorations(&self, id: u32, member: u32) -> Decorations {
+    self
+      .member_decorations
+      .get(&(id, member))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  fn reflect(&self) -> Result<ShaderReflection, ReflectError> {
+    let (execution_model, interface) = self
+      .entry_point
+      .as_ref()
+      .ok_or(ReflectError::NoEntryPoint)?;
+    let stage = match execution_model {
+      0 => ShaderStageFlags::VERTEX,
+      1 => ShaderStageFlags::HULL,
+      2 => ShaderStageFlags::DOMAIN,
+      3 => ShaderStageFlags::GEOMETRY,
+      4 => ShaderStageFlags::FRAGMENT,
+      5 => ShaderStageFlags::COMPUTE,
+      _ => {
+        return Err(ReflectError::Unsupported(
+          "an execution model besides the graphics ones",
+        ))
+      }
+    };
+
+    let mut inputs = Vec::new();
+    let mut descriptors = Vec::new();
+    let mut push_constants = None;
+    for &(id, pointer, storage) in &self.variables {
+      let ty = match self.ty(pointer)? {
+        Type::Pointer { pointee } => *pointee,
+        _ => return Err(ReflectError::NotSpirv),
+      };
+      let decorations = self.decorations(id);
+      match storage {
+        STORAGE_INPUT if stage == ShaderStageFlags::VERTEX && interface.contains(&id) => {
+          if decorations.built_in {
+            continue;
+          }
+          let location = decorations.location.ok_or(ReflectError::Unsupported(
+            "a vertex input without a location",
+          ))?;
+          self.vertex_inputs(location, ty, &mut inputs, 0)?;
+        }
+        STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+          let (ty, count) = match self.ty(ty)? {
+            Type::Array { element, length } => (*element, self.constant(*length)? as usize),
+            Type::RuntimeArray => {
+              return Err(ReflectError::Unsupported(
+                "a runtime sized array of descriptors",
+              ))
+            }
+            _ => (ty, 1),
+          };
+          descriptors.push(DescriptorBinding {
+            set: decorations.set.unwrap_or(0),
+            binding: decorations.binding.unwrap_or(0),
+            ty: self.descriptor_type(ty, storage)?,
+            count,
+            stages: stage,
+          });
+        }
+        STORAGE_PUSH_CONSTANT => {
+          let (start, end) = self.struct_extent(ty, 0)?;
+          // gfx-hal measures push constants in words, not bytes
+          push_constants = Some(start / 4..(end + 3) / 4);
+        }
+        _ => (),
+      }
+    }
+    inputs.sort_by_key(|&(location, _)| location);
+    Ok(ShaderReflection {
+      stage,
+      inputs,
+      descriptors,
+      push_constants,
+    })
+  }
+
+  fn constant(&self, id: u32) -> Result<u32, ReflectError> {
+    self
+      .constants
+      .get(&id)
+      .cloned()
+      .ok_or(ReflectError::Unsupported(
+        "an array sized by a specialization constant",
+      ))
+  }
+
+  /// Adds the format read from each location that a vertex input takes up.
+  /// Matrices take one location per column, and arrays one per element.
+  fn vertex_inputs(
+    &self, location: u32, ty: u32, inputs: &mut Vec<(u32, Format)>, depth: u32,
+  ) -> Result<u32, ReflectError> {
+    if depth > MAX_TYPE_DEPTH {
+      return Err(ReflectError::NotSpirv);
+    }
+    match self.ty(ty)? {
+      Type::Matrix { column, count } => {
+        for i in 0..*count {
+          self.vertex_inputs(location + i, *column, inputs, depth + 1)?;
+        }
+        Ok(*count)
+      }
+      Type::Array { element, length } => {
+        let mut used = 0;
+        for _ in 0..self.constant(*length)? {
+          used += self.vertex_inputs(location + used, *element, inputs, depth + 1)?;
+        }
+        Ok(used)
+      }
+      Type::Vector { component, count } => {
+        inputs.push((location, self.input_format(*component, *count)?));
+        Ok(1)
+      }
+      _ => {
+        inputs.push((location, self.input_format(ty, 1)?));
+        Ok(1)
+      }
+    }
+  }
+
+  /// The format that a vertex input with this many components of the scalar
+  /// type reads.
+  fn input_format(&self, scalar: u32, count: u32) -> Result<Format, ReflectError> {
+    let formats = match self.ty(scalar)? {
+      Type::Float { width: 32 } => [
+        Format::R32Float,
+        Format::Rg32Float,
+        Format::Rgb32Float,
+        Format::Rgba32Float,
+      ],
+      Type::Int {
+        width: 32,
+        signed: true,
+      } => [
+        Format::R32Int,
+        Format::Rg32Int,
+        Format::Rgb32Int,
+        Format::Rgba32Int,
+      ],
+      Type::Int {
+        width: 32,
+        signed: false,
+      } => [
+        Format::R32Uint,
+        Format::Rg32Uint,
+        Format::Rgb32Uint,
+        Format::Rgba32Uint,
+      ],
+      _ => {
+        return Err(ReflectError::Unsupported(
+          "a vertex input that isn't 32-bit numbers",
+        ))
+      }
+    };
+    (count as usize)
+      .checked_sub(1)
+      .and_then(|index| formats.get(index))
+      .cloned()
+      .ok_or(ReflectError::Unsupported(
+        "a vertex input without 1 to 4 components",
+      ))
+  }
+
+  fn descriptor_type(&self, ty: u32, storage: u32) -> Result<DescriptorType, ReflectError> {
+    let decorations = self.decorations(ty);
+    Ok(match self.ty(ty)? {
+      Type::Sampler => DescriptorType::Sampler,
+      Type::SampledImage => DescriptorType::CombinedImageSampler,
+      Type::Image { dim, .. } if *dim == DIM_SUBPASS_DATA => DescriptorType::InputAttachment,
+      Type::Image { dim, sampled } if *dim == DIM_BUFFER && *sampled == 2 => {
+        DescriptorType::StorageTexelBuffer
+      }
+      Type::Image { dim, .. } if *dim == DIM_BUFFER => DescriptorType::UniformTexelBuffer,
+      Type::Image { sampled, .. } if *sampled == 2 => DescriptorType::StorageImage,
+      Type::Image { .. } => DescriptorType::SampledImage,
+      Type::Struct { .. } if storage == STORAGE_STORAGE_BUFFER || decorations.buffer_block => {
+        DescriptorType::StorageBuffer
+      }
+      Type::Struct { .. } if decorations.block => DescriptorType::UniformBuffer,
+      _ => return Err(ReflectError::Unsupported("a descriptor of an unknown type")),
+    })
+  }
+
+  /// The first byte that a struct's members use, and the byte after the last.
+  fn struct_extent(&self, ty: u32, depth: u32) -> Result<(u32, u32), ReflectError> {
+    let members = match self.ty(ty)? {
+      Type::Struct { members } => members,
+      _ => {
+        return Err(ReflectError::Unsupported(
+          "push constants that aren't a block",
+        ))
+      }
+    };
+    let mut extent: Option<(u32, u32)> = None;
+    for (index, &member) in members.iter().enumerate() {
+      let decorations = self.member_decorations(ty, index as u32);
+      let offset = decorations.offset.unwrap_or(0);
+      let end = offset + self.size(member, decorations.matrix_stride, depth + 1)?;
+      extent = Some(match extent {
+        Some((start, old_end)) => (start.min(offset), old_end.max(end)),
+        None => (offset, end),
+      });
+    }
+    Ok(extent.unwrap_or((0, 0)))
+  }
+
+  /// How many bytes a value of the type takes up inside of a block.
+  fn size(&self, ty: u32, matrix_stride: Option<u32>, depth: u32) -> Result<u32, ReflectError> {
+    if depth > MAX_TYPE_DEPTH {
+      return Err(ReflectError::NotSpirv);
+    }
+    Ok(match self.ty(ty)? {
+      Type::Bool => 4,
+      Type::Int { width, .. } | Type::Float { width } => width / 8,
+      Type::Vector { component, count } => count * self.size(*component, None, depth + 1)?,
+      Type::Matrix { column, count } => match matrix_stride {
+        Some(stride) => count * stride,
+        None => count * self.size(*column, None, depth + 1)?,
+      },
+      Type::Array { element, length } => {
+        let stride = match self.decorations(ty).array_stride {
+          Some(stride) => stride,
+          None => self.size(*element, None, depth + 1)?,
+        };
+        self.constant(*length)? * stride
+      }
+      Type::Struct { .. } => self.struct_extent(ty, depth + 1)?.1,
+      _ => {
+        return Err(ReflectError::Unsupported(
+          "an opaque type inside of a block",
+        ))
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{CubeInstance, Vertex, VertexLayout, FRAGMENT_SPIRV, VERTEX_SPIRV};
+
+  /// Assembles a module out of `(opcode, operands)` instructions.
+  fn module(instructions: &[(u32, &[u32])]) -> Vec<u8> {
+    let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+    for &(opcode, operands) in instructions {
+      words.push((operands.len() as u32 + 1) << 16 | opcode);
+      words.extend_from_slice(operands);
+    }
+    words
+      .iter()
+      .flat_map(|word| word.to_le_bytes().to_vec())
+      .collect()
+  }
+
+  /// A vertex shader whose only input, `%10` at location 0, is of type `%3`.
+  fn vertex_input_of(types: &[(u32, &[u32])]) -> Vec<u8> {
+    let entry_point = [0, 1, u32::from_le_bytes(*b"main"), 0, 10];
+    let mut instructions: Vec<(u32, &[u32])> = vec![(OP_ENTRY_POINT, &entry_point)];
+    instructions.extend_from_slice(types);
+    instructions.push((OP_TYPE_POINTER, &[4, STORAGE_INPUT, 3]));
+    instructions.push((OP_VARIABLE, &[4, 10, STORAGE_INPUT]));
+    instructions.push((OP_DECORATE, &[10, DECORATION_LOCATION, 0]));
+    module(&instructions)
+  }
+
+  #[test]
+  fn reflection_matches_the_cube_shaders() {
+    let interface = PipelineInterface::new(&[
+      ShaderReflection::new(VERTEX_SPIRV).unwrap(),
+      ShaderReflection::new(FRAGMENT_SPIRV).unwrap(),
+    ])
+    .unwrap();
+    assert_eq!(
+      interface.push_constants,
+      vec![(ShaderStageFlags::VERTEX, 0..16)]
+    );
+    let set_layouts = interface.set_layouts();
+    assert_eq!(set_layouts.len(), 1);
+    assert_eq!(set_layouts[0][0].ty, DescriptorType::SampledImage);
+    assert_eq!(set_layouts[0][1].ty, DescriptorType::Sampler);
+
+    // the vertex alone is missing the instance matrix, which is locations 2 to 5
+    let mut attributes = Vertex::attributes(0);
+    assert_eq!(
+      interface.check_attributes(&attributes),
+      Err(ReflectError::MissingAttribute {
+        location: 2,
+        shader: Format::Rgba32Float,
+      })
+    );
+    attributes.extend(CubeInstance::attributes(1));
+    assert_eq!(interface.check_attributes(&attributes), Ok(()));
+    attributes[1].element.format = Format::Rgb32Float;
+    assert_eq!(
+      interface.check_attributes(&attributes),
+      Err(ReflectError::AttributeMismatch {
+        location: 1,
+        shader: Format::Rg32Float,
+        attribute: Format::Rgb32Float,
+      })
+    );
+    assert_eq!(
+      ShaderReflection::new(b"not spir-v!!").unwrap_err(),
+      ReflectError::NotSpirv
+    );
+  }
+  #[test]
+  fn vertex_inputs_need_1_to_4_components() {
+    let float = (OP_TYPE_FLOAT, &[2, 32][..]);
+    let vec4 = vertex_input_of(&[float, (OP_TYPE_VECTOR, &[3, 2, 4])]);
+    assert_eq!(
+      ShaderReflection::new(&vec4).unwrap().inputs,
+      vec![(0, Format::Rgba32Float)]
+    );
+    for &count in &[0, 5] {
+      let bytes = vertex_input_of(&[float, (OP_TYPE_VECTOR, &[3, 2, count])]);
+      assert_eq!(
+        ShaderReflection::new(&bytes).unwrap_err(),
+        ReflectError::Unsupported("a vertex input without 1 to 4 components")
+      );
+    }
+  }
+
+  #[test]
+  fn types_that_contain_themselves() {
+    // an array of 2 of itself, as a vertex input
+    let bytes = vertex_input_of(&[
+      (OP_TYPE_INT, &[6, 32, 0]),
+      (OP_CONSTANT, &[6, 5, 2]),
+      (OP_TYPE_ARRAY, &[3, 3, 5]),
+    ]);
+    assert_eq!(
+      ShaderReflection::new(&bytes).unwrap_err(),
+      ReflectError::NotSpirv
+    );
+    // a struct with itself as a member, as push constants
+    let main = u32::from_le_bytes(*b"main");
+    let bytes = module(&[
+      (OP_ENTRY_POINT, &[0, 1, main, 0]),
+      (OP_TYPE_STRUCT, &[7, 7]),
+      (OP_TYPE_POINTER, &[8, STORAGE_PUSH_CONSTANT, 7]),
+      (OP_VARIABLE, &[8, 11, STORAGE_PUSH_CONSTANT]),
+    ]);
+    assert_eq!(
+      ShaderReflection::new(&bytes).unwrap_err(),
+      ReflectError::NotSpirv
+    );
+  }
+}
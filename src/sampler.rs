@@ -0,0 +1,220 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::HalError;
+use core::ops::Range;
+use gfx_hal::{
+  adapter::{Adapter, PhysicalDevice},
+  device::Device,
+  image::{Anisotropic, Filter, Lod, PackedColor, SamplerInfo, WrapMode},
+  pso::Comparison,
+  Backend, Features,
+};
+
+/// Everything about how a texture gets sampled.
+///
+/// Two textures with equal descriptions share one sampler, see
+/// [`SamplerCache`].
+///
+/// ```no_run
+/// # use gfx_hal::image::WrapMode;
+/// # use learn_gfx_hal::SamplerDesc;
+/// let desc = SamplerDesc::trilinear(WrapMode::Mirror)
+///   .anisotropy(16)
+///   .lod_bias(-0.5);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplerDesc {
+  /// Used when the texture is drawn smaller than it is.
+  pub min_filter: Filter,
+  /// Used when the texture is drawn bigger than it is.
+  pub mag_filter: Filter,
+  /// Used between mip levels.
+  pub mip_filter: Filter,
+  /// The wrap modes for the u, v, and w coordinates.
+  pub wrap_mode: (WrapMode, WrapMode, WrapMode),
+  /// Added to the mip level that the GPU picks.
+  pub lod_bias: f32,
+  /// The mip levels that can be picked from.
+  pub lod_range: Range<f32>,
+  /// The most samples to take for anisotropic filtering, `None` for off. This
+  /// gets clamped to what the device allows, and is off if it can't do it.
+  pub anisotropy: Option<u8>,
+  /// Makes this a comparison sampler (for shadow maps), which gives back the
+  /// result of comparing against the texel instead of the texel.
+  pub comparison: Option<Comparison>,
+  /// The color outside of the texture with `WrapMode::Border`.
+  pub border_color: [f32; 4],
+}
+impl Default for SamplerDesc {
+  /// Nearest filtering and tiling, like you'd want for pixel art.
+  fn default() -> Self {
+    Self::new(Filter::Nearest, WrapMode::Tile)
+  }
+}
+impl SamplerDesc {
+  /// Uses one filter for everything and one wrap mode for every coordinate.
+  pub fn new(filter: Filter, wrap: WrapMode) -> Self {
+    Self {
+      min_filter: filter,
+      mag_filter: filter,
+      mip_filter: filter,
+      wrap_mode: (wrap, wrap, wrap),
+      lod_bias: 0.0,
+      lod_range: 0.0..1000.0,
+      anisotropy: None,
+      comparison: None,
+      border_color: [0.0, 0.0, 0.0, 0.0],
+    }
+  }
+
+  /// Linear filtering within and between mip levels.
+  pub fn trilinear(wrap: WrapMode) -> Self {
+    Self::new(Filter::Linear, wrap)
+  }
+
+  /// A comparison sampler for a shadow map. Everything outside of the map
+  /// counts as lit.
+  pub fn shadow(comparison: Comparison) -> Self {
+    Self::new(Filter::Linear, WrapMode::Border)
+      .comparison(comparison)
+      .border_color([1.0, 1.0, 1.0, 1.0])
+  }
+
+  /// Sets the min, mag, and mip filters.
+  pub fn filters(mut self, min_filter: Filter, mag_filter: Filter, mip_filter: Filter) -> Self {
+    self.min_filter = min_filter;
+    self.mag_filter = mag_filter;
+    self.mip_filter = mip_filter;
+    self
+  }
+
+  /// Sets the wrap modes for the u, v, and w coordinates.
+  pub fn wrap_modes(mut self, u: WrapMode, v: WrapMode, w: WrapMode) -> Self {
+    self.wrap_mode = (u, v, w);
+    self
+  }
+
+  pub fn lod_bias(mut self, lod_bias: f32) -> Self {
+    self.lod_bias = lod_bias;
+    self
+  }
+
+  pub fn lod_range(mut self, lod_range: Range<f32>) -> Self {
+    self.lod_range = lod_range;
+    self
+  }
+
+  /// Asks for anisotropic filtering with up to this many samples.
+  pub fn anisotropy(mut self, max_samples: u8) -> Self {
+    self.anisotropy = Some(max_samples);
+    self
+  }
+
+  pub fn comparison(mut self, comparison: Comparison) -> Self {
+    self.comparison = Some(comparison);
+    self
+  }
+
+  pub fn border_color(mut self, border_color: [f32; 4]) -> Self {
+    self.border_color = border_color;
+    self
+  }
+
+  /// The gfx-hal version of this, with the anisotropy clamped to at most
+  /// `max_anisotropy` (which is 0 if the device can't do it at all).
+  fn info(&self, max_anisotropy: u8) -> SamplerInfo {
+    let anisotropic = match self.anisotropy {
+      Some(samples) if samples > 1 && max_anisotropy > 1 => {
+        Anisotropic::On(samples.min(max_anisotropy))
+      }
+      _ => Anisotropic::Off,
+    };
+    SamplerInfo {
+      min_filter: self.min_filter,
+      mag_filter: self.mag_filter,
+      mip_filter: self.mip_filter,
+      wrap_mode: self.wrap_mode,
+      lod_bias: Lod::from(self.lod_bias),
+      lod_range: Lod::from(self.lod_range.start)..Lod::from(self.lod_range.end),
+      comparison: self.comparison,
+      border: PackedColor::from(self.border_color),
+      anisotropic,
+    }
+  }
+}
+
+/// Which sampler in a [`SamplerCache`] something uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerId(usize);
+
+/// Makes each different sampler just once, so that textures with the same
+/// [`SamplerDesc`] can share it.
+///
+/// There's never more than a handful of different samplers, so they're kept
+/// in a list and looked up by comparing the descriptions.
+pub struct SamplerCache<B: Backend> {
+  max_anisotropy: u8,
+  samplers: Vec<(SamplerDesc, B::Sampler)>,
+}
+impl<B: Backend> SamplerCache<B> {
+  pub fn new(adapter: &Adapter<B>) -> Self {
+    let max_anisotropy = if adapter
+      .physical_device
+      .features()
+      .contains(Features::SAMPLER_ANISOTROPY)
+    {
+      adapter
+        .physical_device
+        .limits()
+        .max_sampler_anisotropy
+        .min(255.0) as u8
+    } else {
+      0
+    };
+    Self {
+      max_anisotropy,
+      samplers: Vec::new(),
+    }
+  }
+
+  /// The sampler for the description, which gets made if we don't have one
+  /// like it yet.
+  pub fn get<D: Device<B>>(
+    &mut self, device: &D, desc: &SamplerDesc,
+  ) -> Result<SamplerId, HalError> {
+    if let Some(index) = self.samplers.iter().position(|(d, _)| d == desc) {
+      return Ok(SamplerId(index));
+    }
+    let sampler = unsafe { device.create_sampler(desc.info(self.max_anisotropy))? };
+    debug!("Created sampler {:?}", desc);
+    self.samplers.push((desc.clone(), sampler));
+    Ok(SamplerId(self.samplers.len() - 1))
+  }
+
+  pub fn sampler(&self, id: SamplerId) -> &B::Sampler {
+    &self.samplers[id.0].1
+  }
+
+  /// The most anisotropic filtering samples the device allows, 0 if it can't
+  /// do anisotropic filtering.
+  pub fn max_anisotropy(&self) -> u8 {
+    self.max_anisotropy
+  }
+
+  /// How many different samplers there are.
+  pub fn sampler_count(&self) -> usize {
+    self.samplers.len()
+  }
+
+  /// Destroys every sampler.
+  ///
+  /// # Safety
+  ///
+  /// Nothing can be using them any more.
+  pub unsafe fn manually_drop<D: Device<B>>(&mut self, device: &D) {
+    for (_, sampler) in self.samplers.drain(..) {
+      device.destroy_sampler(sampler);
+    }
+  }
+}
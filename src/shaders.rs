@@ -0,0 +1,645 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  reflect::SPIRV_MAGIC, HalError, PipelineInterface, ReflectError, ShaderReflection,
+  FRAGMENT_SPIRV, VERTEX_SPIRV,
+};
+use gfx_hal::pso::SpecializationConstant;
+use std::{
+  borrow::Cow,
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime},
+};
+
+/// How often a `ShaderWatcher` actually looks at the files.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where the code for one shader stage comes from.
+///
+/// Only `Precompiled` shaders are sure to work without the `runtime-shaders`
+/// feature. The GLSL ones need shaderc, unless they're already in the
+/// `SpirvCache`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderSource {
+  /// SPIR-V built into the program, like `VERTEX_SPIRV`.
+  Precompiled(&'static [u8]),
+  /// GLSL built into the program, so it never changes.
+  Embedded(&'static str),
+  /// GLSL read from a file every time the pipeline is made, and watched for
+  /// changes.
+  File(PathBuf),
+}
+
+/// The shaders that the cubes get drawn with.
+///
+/// ```no_run
+/// # use learn_gfx_hal::ShaderSources;
+/// let shaders = ShaderSources::files("shaders/cube.vert", "shaders/cube.frag");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderSources {
+  pub vertex: ShaderSource,
+  pub fragment: ShaderSource,
+}
+impl Default for ShaderSources {
+  /// `VERTEX_SPIRV` and `FRAGMENT_SPIRV`.
+  fn default() -> Self {
+    Self {
+      vertex: ShaderSource::Precompiled(VERTEX_SPIRV),
+      fragment: ShaderSource::Precompiled(FRAGMENT_SPIRV),
+    }
+  }
+}
+impl ShaderSources {
+  /// Both shaders come from files.
+  pub fn files<V: Into<PathBuf>, F: Into<PathBuf>>(vertex: V, fragment: F) -> Self {
+    Self {
+      vertex: ShaderSource::File(vertex.into()),
+      fragment: ShaderSource::File(fragment.into()),
+    }
+  }
+
+  /// Gets both shaders as SPIR-V, compiling any GLSL that isn't in the cache.
+  /// shaderc is only started if something actually has to be compiled.
+  pub(crate) fn compile(
+    &self, cache: Option<&SpirvCache>, variant: &ShaderVariant,
+  ) -> Result<CompiledShaders, HalError> {
+    let mut compiler = Compiler::default();
+    let (constants, data) = variant.specialization();
+    Ok(CompiledShaders {
+      vertex: compile(&mut compiler, cache, &self.vertex, Stage::Vertex, variant)?,
+      fragment: compile(
+        &mut compiler,
+        cache,
+        &self.fragment,
+        Stage::Fragment,
+        variant,
+      )?,
+      constants,
+      data,
+    })
+  }
+
+  /// The files that the shaders come from, and the files that they include
+  /// when compiled for the variant.
+  fn paths(&self, variant: &ShaderVariant) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for &(source, stage) in [
+      (&self.vertex, Stage::Vertex),
+      (&self.fragment, Stage::Fragment),
+    ]
+    .iter()
+    {
+      let (glsl, name) = match source {
+        ShaderSource::Precompiled(_) => continue,
+        ShaderSource::Embedded(glsl) => (Cow::Borrowed(*glsl), stage.embedded_name().to_string()),
+        ShaderSource::File(path) => {
+          paths.push(path.clone());
+          match std::fs::read_to_string(path) {
+            Ok(glsl) => (Cow::Owned(glsl), path.display().to_string()),
+            Err(_) => continue,
+          }
+        }
+      };
+      for (path, _) in resolve_includes(&glsl, &name, &variant.include_dirs) {
+        if !paths.contains(&path) {
+          paths.push(path);
+        }
+      }
+    }
+    paths
+  }
+}
+
+/// Gets one shader as SPIR-V.
+fn compile(
+  compiler: &mut Compiler, cache: Option<&SpirvCache>, source: &ShaderSource, stage: Stage,
+  variant: &ShaderVariant,
+) -> Result<Vec<u8>, HalError> {
+  let (glsl, name) = match source {
+    ShaderSource::Precompiled(spirv) => {
+      if variant.changes_glsl() {
+        warn!("Precompiled shaders can't use the variant's defines or include dirs");
+      }
+      return Ok(spirv.to_vec());
+    }
+    ShaderSource::Embedded(glsl) => (Cow::Borrowed(*glsl), stage.embedded_name().to_string()),
+    ShaderSource::File(path) => {
+      let glsl = std::fs::read_to_string(path).map_err(|source| HalError::ShaderRead {
+        path: path.clone(),
+        source,
+      })?;
+      (Cow::Owned(glsl), path.display().to_string())
+    }
+  };
+  // an included file can change without the GLSL itself changing, so the
+  // cache has to look at those too
+  let includes = if glsl.contains("#include") {
+    resolve_includes(&glsl, &name, &variant.include_dirs)
+  } else {
+    Vec::new()
+  };
+  if let Some(spirv) = cache.and_then(|cache| cache.load(stage, &glsl, variant, &includes)) {
+    return Ok(spirv);
+  }
+  let spirv = compiler.compile(&glsl, stage, name, variant)?;
+  if let Some(cache) = cache {
+    cache.store(stage, &glsl, variant, &includes, &spirv);
+  }
+  Ok(spirv)
+}
+
+/// One version of the shaders, for drawing things a bit differently without
+/// writing more shaders.
+///
+/// Specialization constants work with any shaders, defines and include dirs
+/// only with GLSL (they're ignored, with a warning, for precompiled SPIR-V).
+/// Each variant gets its own pipeline, see `HalState::set_shader_variant`.
+///
+/// ```no_run
+/// # use learn_gfx_hal::ShaderVariant;
+/// // layout (constant_id = 0) const bool TEXTURED = true;
+/// let untextured = ShaderVariant::new().constant_bool(0, false);
+/// let fancy = ShaderVariant::new().define("FANCY").define_value("LIGHTS", "4");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderVariant {
+  /// The 32-bit value of each constant id.
+  constants: BTreeMap<u32, u32>,
+  defines: BTreeMap<String, Option<String>>,
+  include_dirs: Vec<PathBuf>,
+}
+impl ShaderVariant {
+  /// The shaders as they're written.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn constant_u32(mut self, id: u32, value: u32) -> Self {
+    self.constants.insert(id, value);
+    self
+  }
+
+  pub fn constant_i32(self, id: u32, value: i32) -> Self {
+    self.constant_u32(id, value as u32)
+  }
+
+  pub fn constant_f32(self, id: u32, value: f32) -> Self {
+    self.constant_u32(id, value.to_bits())
+  }
+
+  pub fn constant_bool(self, id: u32, value: bool) -> Self {
+    self.constant_u32(id, value as u32)
+  }
+
+  /// `#define name` at the top of the GLSL.
+  pub fn define<N: Into<String>>(mut self, name: N) -> Self {
+    self.defines.insert(name.into(), None);
+    self
+  }
+
+  /// `#define name value` at the top of the GLSL.
+  pub fn define_value<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+    self.defines.insert(name.into(), Some(value.into()));
+    self
+  }
+
+  /// Somewhere to look for `#include <file>`, in the order they're added.
+  /// `#include "file"` looks next to the shader's file first.
+  pub fn include_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+    self.include_dirs.push(dir.into());
+    self
+  }
+
+  /// The constants the way gfx-hal wants them, each one's range being where
+  /// its bytes are in the data.
+  pub(crate) fn specialization(&self) -> (Vec<SpecializationConstant>, Vec<u8>) {
+    let mut constants = Vec::with_capacity(self.constants.len());
+    let mut data = Vec::with_capacity(self.constants.len() * 4);
+    for (&id, value) in &self.constants {
+      let start = data.len() as u16;
+      data.extend_from_slice(&value.to_ne_bytes());
+      constants.push(SpecializationConstant {
+        id,
+        range: start..start + 4,
+      });
+    }
+    (constants, data)
+  }
+
+  /// If this needs the GLSL to be compiled differently.
+  fn changes_glsl(&self) -> bool {
+    !self.defines.is_empty() || !self.include_dirs.is_empty()
+  }
+}
+
+/// Which stage a shader is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+  Vertex,
+  Fragment,
+}
+impl Stage {
+  /// The name shaderc gets for GLSL that isn't from a file.
+  fn embedded_name(self) -> &'static str {
+    match self {
+      Stage::Vertex => "vertex.vert",
+      Stage::Fragment => "fragment.frag",
+    }
+  }
+}
+
+/// shaderc, started the first time that it's needed.
+#[derive(Default)]
+struct Compiler {
+  #[cfg(feature = "runtime-shaders")]
+  compiler: Option<shaderc::Compiler>,
+}
+impl Compiler {
+  #[cfg(feature = "runtime-shaders")]
+  fn compile(
+    &mut self, glsl: &str, stage: Stage, name: String, variant: &ShaderVariant,
+  ) -> Result<Vec<u8>, HalError> {
+    if self.compiler.is_none() {
+      self.compiler = Some(shaderc::Compiler::new().ok_or(HalError::ShaderCompilerMissing)?);
+    }
+    let compiler = self.compiler.as_mut().expect("we just started shaderc");
+    let kind = match stage {
+      Stage::Vertex => shaderc::ShaderKind::Vertex,
+      Stage::Fragment => shaderc::ShaderKind::Fragment,
+    };
+    let mut options = shaderc::CompileOptions::new().ok_or(HalError::ShaderCompilerMissing)?;
+    for (define, value) in &variant.defines {
+      options.add_macro_definition(define, value.as_ref().map(String::as_str));
+    }
+    let include_dirs = variant.include_dirs.clone();
+    options.set_include_callback(move |requested, include_type, requesting, _depth| {
+      let relative = match include_type {
+        shaderc::IncludeType::Relative => true,
+        shaderc::IncludeType::Standard => false,
+      };
+      resolve_include(&include_dirs, requested, relative, requesting)
+        .map(|(path, content)| shaderc::ResolvedInclude {
+          resolved_name: path.display().to_string(),
+          content,
+        })
+        .ok_or_else(|| format!("Couldn't find {} to include", requested))
+    });
+    compiler
+      .compile_into_spirv(glsl, kind, &name, "main", Some(&options))
+      .map(|artifact| artifact.as_binary_u8().to_vec())
+      .map_err(|source| HalError::ShaderCompilation { name, source })
+  }
+
+  #[cfg(not(feature = "runtime-shaders"))]
+  fn compile(
+    &mut self, _glsl: &str, _stage: Stage, name: String, _variant: &ShaderVariant,
+  ) -> Result<Vec<u8>, HalError> {
+    warn!("Can't compile {} without the runtime-shaders feature", name);
+    Err(HalError::ShaderCompilerMissing)
+  }
+}
+
+/// Finds and reads an included file. `"file"` includes look next to the file
+/// doing the including first, and then both kinds look in the include dirs.
+fn resolve_include(
+  include_dirs: &[PathBuf], requested: &str, relative: bool, requesting: &str,
+) -> Option<(PathBuf, String)> {
+  let next_to_requesting = Path::new(requesting)
+    .parent()
+    .filter(|_| relative)
+    .map(|dir| dir.join(requested));
+  next_to_requesting
+    .into_iter()
+    .chain(include_dirs.iter().map(|dir| dir.join(requested)))
+    .filter_map(|path| {
+      let content = std::fs::read_to_string(&path).ok()?;
+      Some((path, content))
+    })
+    .next()
+}
+
+/// Every file that the GLSL includes, directly or from another include, found
+/// the same way that shaderc finds them.
+///
+/// The GLSL isn't preprocessed first, so an include inside an `#if` that's off
+/// still counts. Includes that can't be found are left out, shaderc complains
+/// about them if they're really needed.
+fn resolve_includes(glsl: &str, name: &str, include_dirs: &[PathBuf]) -> Vec<(PathBuf, String)> {
+  let mut found: Vec<(PathBuf, String)> = Vec::new();
+  let mut pending = vec![(name.to_string(), glsl.to_string())];
+  while let Some((requesting, source)) = pending.pop() {
+    for (requested, relative) in source.lines().filter_map(parse_include) {
+      if let Some((path, content)) = resolve_include(include_dirs, requested, relative, &requesting)
+      {
+        // included twice (or included by itself, behind an include guard)
+        if found.iter().any(|(seen, _)| *seen == path) {
+          continue;
+        }
+        pending.push((path.display().to_string(), content.clone()));
+        found.push((path, content));
+      }
+    }
+  }
+  found
+}
+
+/// The file named by an `#include` line, and if it's a `"file"` include.
+fn parse_include(line: &str) -> Option<(&str, bool)> {
+  let line = line.trim_start();
+  if !line.starts_with('#') {
+    return None;
+  }
+  let rest = line[1..].trim_start();
+  if !rest.starts_with("include") {
+    return None;
+  }
+  let rest = rest["include".len()..].trim_start();
+  let (close, relative) = match rest.chars().next()? {
+    '<' => ('>', false),
+    '"' => ('"', true),
+    _ => return None,
+  };
+  let len = rest[1..].find(close)?;
+  Some((&rest[1..1 + len], relative))
+}
+
+/// An on-disk cache of SPIR-V compiled from GLSL at runtime, so that each
+/// version of a shader only ever gets compiled once.
+///
+/// Each entry is a file named after a hash of the stage, the GLSL, the
+/// variant's defines, and the path and contents of every file it includes, so
+/// an edited shader (or header) just gets a new entry. Nothing is ever removed,
+/// clear out the directory whenever you like. Problems reading or writing the cache are logged and otherwise
+/// ignored, the shader just gets compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpirvCache {
+  dir: PathBuf,
+}
+impl SpirvCache {
+  /// Uses the directory, which gets made when the first entry is stored.
+  pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  fn entry_path(
+    &self, stage: Stage, glsl: &str, variant: &ShaderVariant, includes: &[(PathBuf, String)],
+  ) -> PathBuf {
+    let mut hash = Fnv1a::default();
+    hash.write(stage.embedded_name().as_bytes());
+    hash.write(glsl.as_bytes());
+    // the include dirs only matter through the files that were found in them,
+    // and the constants are only applied to the SPIR-V
+    for (path, content) in includes {
+      hash.write(b"\0#include ");
+      hash.write(path.display().to_string().as_bytes());
+      hash.write(b"\0");
+      hash.write(content.as_bytes());
+    }
+    for (define, value) in &variant.defines {
+      hash.write(b"\0#define ");
+      hash.write(define.as_bytes());
+      if let Some(value) = value {
+        hash.write(b" ");
+        hash.write(value.as_bytes());
+      }
+    }
+    self.dir.join(format!("{:016x}.spv", hash.0))
+  }
+
+  /// The cached SPIR-V for the GLSL, if there is any that looks valid.
+  fn load(
+    &self, stage: Stage, glsl: &str, variant: &ShaderVariant, includes: &[(PathBuf, String)],
+  ) -> Option<Vec<u8>> {
+    let path = self.entry_path(stage, glsl, variant, includes);
+    let spirv = std::fs::read(&path).ok()?;
+    let looks_valid = spirv.len() % 4 == 0
+      && spirv.len() >= 4
+      && u32::from_le_bytes([spirv[0], spirv[1], spirv[2], spirv[3]]) == SPIRV_MAGIC;
+    if looks_valid {
+      trace!("Loaded {} from the SPIR-V cache", path.display());
+      Some(spirv)
+    } else {
+      warn!("Ignoring {}, it isn't SPIR-V", path.display());
+      None
+    }
+  }
+
+  fn store(
+    &self, stage: Stage, glsl: &str, variant: &ShaderVariant, includes: &[(PathBuf, String)],
+    spirv: &[u8],
+  ) {
+    let path = self.entry_path(stage, glsl, variant, includes);
+    let result = std::fs::create_dir_all(&self.dir).and_then(|_| std::fs::write(&path, spirv));
+    match result {
+      Ok(()) => debug!("Stored {} in the SPIR-V cache", path.display()),
+      Err(e) => warn!(
+        "Couldn't store {} in the SPIR-V cache: {}",
+        path.display(),
+        e
+      ),
+    }
+  }
+}
+
+/// The 64-bit FNV-1a hash. Unlike std's hasher it's the same on every build,
+/// which the cache's file names rely on.
+struct Fnv1a(u64);
+impl Default for Fnv1a {
+  fn default() -> Self {
+    Fnv1a(0xcbf2_9ce4_8422_2325)
+  }
+}
+impl Fnv1a {
+  fn write(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      self.0 ^= u64::from(byte);
+      self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+  }
+}
+
+/// The SPIR-V of both shaders, and the variant's specialization constants.
+pub(crate) struct CompiledShaders {
+  pub vertex: Vec<u8>,
+  pub fragment: Vec<u8>,
+  pub constants: Vec<SpecializationConstant>,
+  pub data: Vec<u8>,
+}
+impl CompiledShaders {
+  /// What the shaders take in between them.
+  pub fn reflect(&self) -> Result<PipelineInterface, ReflectError> {
+    PipelineInterface::new(&[
+      ShaderReflection::new(&self.vertex)?,
+      ShaderReflection::new(&self.fragment)?,
+    ])
+  }
+}
+
+/// Notices when shader files, or the files they `#include`, are saved, by
+/// polling their modification times.
+///
+/// Only shaders from files and included files are watched, so with none of
+/// those nothing ever changes.
+#[derive(Debug, Clone)]
+pub struct ShaderWatcher {
+  files: Vec<(PathBuf, Option<SystemTime>)>,
+  last_poll: Instant,
+}
+impl ShaderWatcher {
+  /// Starts watching the files as they are right now, with the includes that
+  /// the variant's include dirs lead to.
+  pub fn new(sources: &ShaderSources, variant: &ShaderVariant) -> Self {
+    let mut watcher = Self {
+      files: Vec::new(),
+      last_poll: Instant::now(),
+    };
+    watcher.watch_includes(sources, variant);
+    watcher
+  }
+
+  /// Also watches whatever the shaders include now, for when they've been
+  /// edited to include something else or the variant has other include dirs.
+  /// Files that are already watched keep their old modification time.
+  pub fn watch_includes(&mut self, sources: &ShaderSources, variant: &ShaderVariant) {
+    for path in sources.paths(variant) {
+      if self.files.iter().all(|(watched, _)| *watched != path) {
+        let last_modified = modified(&path);
+        self.files.push((path, last_modified));
+      }
+    }
+  }
+
+  /// If any of the files changed since the last time this said so. The files
+  /// are only looked at every so often, so this is cheap to call every frame.
+  pub fn changed(&mut self) -> bool {
+    if self.files.is_empty() || self.last_poll.elapsed() < POLL_INTERVAL {
+      return false;
+    }
+    self.last_poll = Instant::now();
+    let mut changed = false;
+    for (path, last_modified) in self.files.iter_mut() {
+      let now_modified = modified(path);
+      if now_modified != *last_modified {
+        debug!("{} changed", path.display());
+        *last_modified = now_modified;
+        changed = true;
+      }
+    }
+    changed
+  }
+}
+
+/// When the file was last modified, or `None` if we can't tell (eg: it's being
+/// replaced right now).
+fn modified(path: &Path) -> Option<SystemTime> {
+  std::fs::metadata(path)
+    .and_then(|meta| meta.modified())
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A fresh directory with the files given in it.
+  fn dir_with(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("learn-gfx-hal-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, content) in files {
+      std::fs::write(dir.join(file), content).unwrap();
+    }
+    dir
+  }
+
+  #[test]
+  fn parse_include_lines() {
+    assert_eq!(parse_include("#include <a.glsl>"), Some(("a.glsl", false)));
+    assert_eq!(
+      parse_include("  #  include \"b/c.glsl\" // note"),
+      Some(("b/c.glsl", true))
+    );
+    assert_eq!(parse_include("#version 450"), None);
+    assert_eq!(parse_include("// #include <a.glsl>"), None);
+    assert_eq!(parse_include("#include <unclosed"), None);
+  }
+
+  #[test]
+  fn includes_are_found_through_other_includes() {
+    let dir = dir_with(
+      "includes",
+      &[
+        ("a.glsl", "#include \"b.glsl\"\n#include <a.glsl>\n"),
+        ("b.glsl", "const int B = 1;\n"),
+      ],
+    );
+    let glsl = "#version 450\n#include <a.glsl>\n#include <missing.glsl>\n";
+    let found: Vec<PathBuf> = resolve_includes(glsl, "x.frag", core::slice::from_ref(&dir))
+      .into_iter()
+      .map(|(path, _)| path)
+      .collect();
+    assert_eq!(found, vec![dir.join("a.glsl"), dir.join("b.glsl")]);
+    // without the include dir nothing can be found
+    assert!(resolve_includes(glsl, "x.frag", &[]).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn cache_entries_change_with_the_includes() {
+    let dir = dir_with("cache-includes", &[("a.glsl", "const int A = 1;\n")]);
+    let glsl = "#version 450\n#include <a.glsl>\n";
+    let variant = ShaderVariant::new().include_dir(&dir);
+    let cache = SpirvCache::new(dir.join("cache"));
+    let entry = || {
+      let includes = resolve_includes(glsl, "x.frag", &variant.include_dirs);
+      cache.entry_path(Stage::Fragment, glsl, &variant, &includes)
+    };
+    let before = entry();
+    assert_eq!(entry(), before);
+    std::fs::write(dir.join("a.glsl"), "const int A = 2;\n").unwrap();
+    assert_ne!(entry(), before);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn watcher_watches_the_includes() {
+    let dir = dir_with(
+      "watch-includes",
+      &[
+        ("shader.frag", "#version 450\n#include \"a.glsl\"\n"),
+        ("a.glsl", "const int A = 1;\n"),
+        ("b.glsl", "const int B = 1;\n"),
+      ],
+    );
+    let sources = ShaderSources {
+      vertex: ShaderSource::Embedded("#version 450\n#include <b.glsl>\n"),
+      fragment: ShaderSource::File(dir.join("shader.frag")),
+    };
+    let watched = |watcher: &ShaderWatcher| -> Vec<PathBuf> {
+      watcher.files.iter().map(|(path, _)| path.clone()).collect()
+    };
+    let mut watcher = ShaderWatcher::new(&sources, &ShaderVariant::new());
+    assert_eq!(
+      watched(&watcher),
+      vec![dir.join("shader.frag"), dir.join("a.glsl")]
+    );
+    // a variant that can find the vertex shader's include adds it
+    watcher.watch_includes(&sources, &ShaderVariant::new().include_dir(&dir));
+    assert_eq!(
+      watched(&watcher),
+      vec![
+        dir.join("shader.frag"),
+        dir.join("a.glsl"),
+        dir.join("b.glsl")
+      ]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,497 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{cast_slice, compressed::block_bytes, CompressedTexture, HalError, SamplerDesc};
+use core::mem::size_of;
+use gfx_hal::{
+  adapter::{Adapter, PhysicalDevice},
+  format::{Format, ImageFeature},
+  image::{Layer, Level, Tiling, Usage, ViewCapabilities, ViewKind},
+  memory::Pod,
+  Backend,
+};
+use image::{
+  Bgra, DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, Luma, Pixel, Rgba, RgbaImage,
+};
+
+/// A 16-bit single channel image, like a heightmap.
+pub type Gray16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// A float RGBA image, like an HDR environment map.
+pub type RgbaF32Image = ImageBuffer<Rgba<f32>, Vec<f32>>;
+
+/// If 8-bit texel data is sRGB encoded color, or plain linear values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+  /// Decoded to linear when sampled. Use this for things that are meant to be
+  /// looked at, like albedo textures.
+  Srgb,
+  /// Sampled as is. Use this for data, like normal maps, roughness maps, and
+  /// masks.
+  Linear,
+}
+
+/// How a texture should be made, besides its texels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureDesc {
+  /// Only matters for uncompressed 8-bit sources. 16-bit and float ones are
+  /// always linear, and compressed ones keep what their file says.
+  pub color_space: ColorSpace,
+  /// If the texture gets a full mip chain. Compressed textures can't have one
+  /// made, they just have the levels that were in their file.
+  pub mipmaps: bool,
+  pub sampler: SamplerDesc,
+}
+impl Default for TextureDesc {
+  fn default() -> Self {
+    Self {
+      color_space: ColorSpace::Srgb,
+      mipmaps: false,
+      sampler: SamplerDesc::default(),
+    }
+  }
+}
+
+/// How the layers of a texture are put together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+  /// Just one 2D image.
+  D2,
+  /// Any number of 2D images of the same size, picked between in the shader
+  /// with a layer index.
+  D2Array,
+  /// Six square faces, in the order +X, -X, +Y, -Y, +Z, -Z, sampled with a
+  /// direction.
+  Cube,
+}
+impl TextureKind {
+  pub(crate) fn view_kind(self) -> ViewKind {
+    match self {
+      TextureKind::D2 => ViewKind::D2,
+      TextureKind::D2Array => ViewKind::D2Array,
+      TextureKind::Cube => ViewKind::Cube,
+    }
+  }
+
+  pub(crate) fn view_capabilities(self) -> ViewCapabilities {
+    match self {
+      TextureKind::Cube => ViewCapabilities::KIND_CUBE,
+      _ => ViewCapabilities::empty(),
+    }
+  }
+}
+
+/// The texels of a texture, in any of the layouts we can upload.
+///
+/// Each kind of source has a matching format (eg: `Luma8` is `R8Srgb` or
+/// `R8Unorm`). If the adapter can't sample that format, the texels are spread
+/// out to four channels instead, placed so that sampling gives exactly what the
+/// smaller format would have given (eg: `Luma8` becomes `(l, 0, 0, 255)`).
+pub enum TextureSource {
+  Luma8(GrayImage),
+  LumaA8(GrayAlphaImage),
+  Rgba8(RgbaImage),
+  Bgra8(ImageBuffer<Bgra<u8>, Vec<u8>>),
+  Luma16(Gray16Image),
+  RgbaF32(RgbaF32Image),
+  /// Uploaded as is if the adapter can sample the format, otherwise decoded
+  /// on the CPU first.
+  Compressed(CompressedTexture),
+}
+impl From<DynamicImage> for TextureSource {
+  /// There aren't many adapters that can sample three channel formats, so
+  /// RGB and BGR images get an alpha channel.
+  fn from(img: DynamicImage) -> Self {
+    match img {
+      DynamicImage::ImageLuma8(img) => TextureSource::Luma8(img),
+      DynamicImage::ImageLumaA8(img) => TextureSource::LumaA8(img),
+      DynamicImage::ImageRgba8(img) => TextureSource::Rgba8(img),
+      DynamicImage::ImageBgra8(img) => TextureSource::Bgra8(img),
+      DynamicImage::ImageRgb8(_) => TextureSource::Rgba8(img.to_rgba()),
+      DynamicImage::ImageBgr8(_) => TextureSource::Bgra8(img.to_bgra()),
+    }
+  }
+}
+impl From<RgbaImage> for TextureSource {
+  fn from(img: RgbaImage) -> Self {
+    TextureSource::Rgba8(img)
+  }
+}
+impl From<Gray16Image> for TextureSource {
+  fn from(img: Gray16Image) -> Self {
+    TextureSource::Luma16(img)
+  }
+}
+impl From<RgbaF32Image> for TextureSource {
+  fn from(img: RgbaF32Image) -> Self {
+    TextureSource::RgbaF32(img)
+  }
+}
+impl From<CompressedTexture> for TextureSource {
+  fn from(texture: CompressedTexture) -> Self {
+    TextureSource::Compressed(texture)
+  }
+}
+impl TextureSource {
+  /// Decodes a Radiance HDR (`.hdr`) file, which the `image` crate can only
+  /// give us as floats this way.
+  pub fn from_hdr(bytes: &[u8]) -> Result<Self, image::ImageError> {
+    let decoder = image::hdr::HDRDecoder::new(bytes)?;
+    let metadata = decoder.metadata();
+    let texels = decoder.read_image_hdr()?;
+    let mut rgba = Vec::with_capacity(texels.len() * 4);
+    for texel in texels {
+      rgba.extend_from_slice(&texel.data);
+      rgba.push(1.0);
+    }
+    let img = RgbaF32Image::from_raw(metadata.width, metadata.height, rgba)
+      .expect("the decoder gave us the wrong number of texels");
+    Ok(TextureSource::RgbaF32(img))
+  }
+
+  pub fn dimensions(&self) -> (u32, u32) {
+    match self {
+      TextureSource::Luma8(img) => img.dimensions(),
+      TextureSource::LumaA8(img) => img.dimensions(),
+      TextureSource::Rgba8(img) => img.dimensions(),
+      TextureSource::Bgra8(img) => img.dimensions(),
+      TextureSource::Luma16(img) => img.dimensions(),
+      TextureSource::RgbaF32(img) => img.dimensions(),
+      TextureSource::Compressed(texture) => (texture.width, texture.height),
+    }
+  }
+
+  /// How many mip levels the texture will have.
+  pub(crate) fn mip_levels(&self, mipmaps: bool) -> Level {
+    match self {
+      TextureSource::Compressed(texture) => texture.levels.len() as Level,
+      _ if mipmaps => {
+        let (width, height) = self.dimensions();
+        (32 - width.max(height).max(1).leading_zeros()) as Level
+      }
+      _ => 1,
+    }
+  }
+
+  /// Picks the first format for the source that the adapter can sample, and
+  /// lays the texels out for it.
+  ///
+  /// With more than 1 mip level, the levels are blitted on the GPU when
+  /// `can_blit` and the format allows it, otherwise they're made here.
+  pub(crate) fn into_texels<B: Backend>(
+    self, adapter: &Adapter<B>, color_space: ColorSpace, mip_levels: Level, can_blit: bool,
+  ) -> Result<Texels, HalError> {
+    let srgb = color_space == ColorSpace::Srgb;
+    let pick = |srgb_format: Format, unorm_format: Format| {
+      if srgb {
+        srgb_format
+      } else {
+        unorm_format
+      }
+    };
+    let sampled = |format: Format| supports(adapter, format, ImageFeature::SAMPLED);
+    let gpu_mips = |format: Format| {
+      mip_levels > 1
+        && can_blit
+        && supports(
+          adapter,
+          format,
+          ImageFeature::BLIT_SRC | ImageFeature::BLIT_DST | ImageFeature::SAMPLED_LINEAR,
+        )
+    };
+    let rgba8 = pick(Format::Rgba8Srgb, Format::Rgba8Unorm);
+    let texels = match self {
+      TextureSource::Luma8(img) => {
+        let format = pick(Format::R8Srgb, Format::R8Unorm);
+        if sampled(format) {
+          Texels::new(img, format, mip_levels, gpu_mips(format))
+        } else {
+          let img = ImageBuffer::from_fn(img.width(), img.height(), |x, y| Rgba {
+            data: [img.get_pixel(x, y).data[0], 0, 0, 255],
+          });
+          Texels::new(img, rgba8, mip_levels, gpu_mips(rgba8))
+        }
+      }
+      TextureSource::LumaA8(img) => {
+        let format = pick(Format::Rg8Srgb, Format::Rg8Unorm);
+        if sampled(format) {
+          Texels::new(img, format, mip_levels, gpu_mips(format))
+        } else {
+          let img = ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+            let [l, a] = img.get_pixel(x, y).data;
+            Rgba {
+              data: [l, a, 0, 255],
+            }
+          });
+          Texels::new(img, rgba8, mip_levels, gpu_mips(rgba8))
+        }
+      }
+      TextureSource::Rgba8(img) => Texels::new(img, rgba8, mip_levels, gpu_mips(rgba8)),
+      TextureSource::Bgra8(img) => {
+        let format = pick(Format::Bgra8Srgb, Format::Bgra8Unorm);
+        if sampled(format) {
+          Texels::new(img, format, mip_levels, gpu_mips(format))
+        } else {
+          let img = ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+            img.get_pixel(x, y).to_rgba()
+          });
+          Texels::new(img, rgba8, mip_levels, gpu_mips(rgba8))
+        }
+      }
+      TextureSource::Luma16(img) => {
+        if sampled(Format::R16Unorm) {
+          Texels::new(
+            img,
+            Format::R16Unorm,
+            mip_levels,
+            gpu_mips(Format::R16Unorm),
+          )
+        } else {
+          let img: ImageBuffer<Rgba<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(img.width(), img.height(), |x, y| Rgba {
+              data: [img.get_pixel(x, y).data[0], 0, 0, core::u16::MAX],
+            });
+          let format = Format::Rgba16Unorm;
+          Texels::new(img, format, mip_levels, gpu_mips(format))
+        }
+      }
+      TextureSource::RgbaF32(img) => {
+        let format = Format::Rgba32Float;
+        Texels::new(img, format, mip_levels, gpu_mips(format))
+      }
+      TextureSource::Compressed(texture) => {
+        if sampled(texture.format) {
+          Texels::compressed(texture)
+        } else {
+          let format = texture.decompressed_format();
+          let levels = texture
+            .decompress()
+            .ok_or(HalError::NoSuitableFormat("compressed texture"))?;
+          debug!(
+            "Decompressed a {:?} texture on the CPU, since the adapter can't sample it",
+            texture.format
+          );
+          Texels {
+            format,
+            levels: levels.iter().map(TexelLevel::new).collect(),
+            gpu_mips: false,
+          }
+        }
+      }
+    };
+    if sampled(texels.format) {
+      Ok(texels)
+    } else {
+      Err(HalError::NoSuitableFormat("texture"))
+    }
+  }
+}
+
+/// Texels laid out for one particular format, ready to be staged.
+pub(crate) struct Texels {
+  pub format: Format,
+  /// Level 0, then the rest of the mip levels if they were made on the CPU.
+  pub levels: Vec<TexelLevel>,
+  /// If the rest of the mip levels still have to be blitted on the GPU.
+  pub gpu_mips: bool,
+}
+impl Texels {
+  fn new<P>(
+    img: ImageBuffer<P, Vec<P::Subpixel>>, format: Format, mip_levels: Level, gpu_mips: bool,
+  ) -> Self
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Pod + 'static,
+  {
+    let mut levels = vec![TexelLevel::new(&img)];
+    if !gpu_mips {
+      // each level is resized straight from the full size image
+      for level in 1..mip_levels {
+        let smaller = image::imageops::resize(
+          &img,
+          (img.width() >> level).max(1),
+          (img.height() >> level).max(1),
+          image::FilterType::Triangle,
+        );
+        levels.push(TexelLevel::new(&smaller));
+      }
+    }
+    if mip_levels > 1 {
+      debug!(
+        "Making {} mip levels of a {:?} texture on the {}",
+        mip_levels,
+        format,
+        if gpu_mips { "GPU" } else { "CPU" }
+      );
+    }
+    Self {
+      format,
+      levels,
+      gpu_mips,
+    }
+  }
+
+  fn compressed(texture: CompressedTexture) -> Self {
+    let (width, height, format) = (texture.width, texture.height, texture.format);
+    let levels = texture
+      .levels
+      .into_iter()
+      .enumerate()
+      .map(|(level, bytes)| TexelLevel {
+        width: (width >> level).max(1),
+        height: (height >> level).max(1),
+        block_size: 4,
+        texel_size: block_bytes(format),
+        bytes,
+      })
+      .collect();
+    Self {
+      format: texture.format,
+      levels,
+      gpu_mips: false,
+    }
+  }
+}
+
+/// Everything about an image except what's in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TextureShape {
+  pub kind: TextureKind,
+  pub width: u32,
+  pub height: u32,
+  pub format: Format,
+  pub mip_levels: Level,
+  pub layers: Layer,
+}
+
+/// The texels of every layer of a texture, which all have the same size,
+/// format, and mip levels.
+pub(crate) struct LayeredTexels {
+  pub shape: TextureShape,
+  /// The levels of each layer, see [`Texels::levels`].
+  pub layers: Vec<Vec<TexelLevel>>,
+  pub gpu_mips: bool,
+}
+impl LayeredTexels {
+  /// Checks that the sources fit the kind of texture and the device's limits,
+  /// and lays each of them out the same way.
+  pub fn new<B: Backend>(
+    adapter: &Adapter<B>, sources: Vec<TextureSource>, kind: TextureKind, desc: &TextureDesc,
+    can_blit: bool,
+  ) -> Result<Self, HalError> {
+    let (width, height) = match sources.first() {
+      Some(source) => source.dimensions(),
+      None => return Err(HalError::InvalidTextureLayers("there aren't any layers")),
+    };
+    match kind {
+      TextureKind::D2 if sources.len() != 1 => {
+        return Err(HalError::InvalidTextureLayers(
+          "a 2D texture has just 1 layer",
+        ));
+      }
+      TextureKind::Cube if sources.len() != 6 => {
+        return Err(HalError::InvalidTextureLayers("a cube map has 6 faces"));
+      }
+      TextureKind::Cube if width != height => {
+        return Err(HalError::InvalidTextureLayers(
+          "cube map faces have to be square",
+        ));
+      }
+      _ => (),
+    }
+    if sources
+      .iter()
+      .any(|source| source.dimensions() != (width, height))
+    {
+      return Err(HalError::InvalidTextureLayers(
+        "the layers aren't all the same size",
+      ));
+    }
+
+    let mip_levels = sources[0].mip_levels(desc.mipmaps);
+    let mut format = None;
+    let mut gpu_mips = false;
+    let mut layers = Vec::with_capacity(sources.len());
+    for source in sources {
+      if source.mip_levels(desc.mipmaps) != mip_levels {
+        return Err(HalError::InvalidTextureLayers(
+          "the layers don't all have the same mip levels",
+        ));
+      }
+      let texels = source.into_texels(adapter, desc.color_space, mip_levels, can_blit)?;
+      if *format.get_or_insert(texels.format) != texels.format {
+        return Err(HalError::InvalidTextureLayers(
+          "the layers don't all have the same format",
+        ));
+      }
+      gpu_mips = texels.gpu_mips;
+      layers.push(texels.levels);
+    }
+    let format = format.expect("there's at least one layer");
+    // Some backends (like GL) don't know the limits, and then we just try it
+    let properties = adapter.physical_device.image_format_properties(
+      format,
+      2,
+      Tiling::Optimal,
+      Usage::SAMPLED | Usage::TRANSFER_DST,
+      kind.view_capabilities(),
+    );
+    if let Some(properties) = properties {
+      if layers.len() > properties.max_layers as usize {
+        return Err(HalError::InvalidTextureLayers(
+          "there are more layers than the device allows",
+        ));
+      }
+    }
+    Ok(Self {
+      shape: TextureShape {
+        kind,
+        width,
+        height,
+        format,
+        mip_levels,
+        layers: layers.len() as Layer,
+      },
+      layers,
+      gpu_mips,
+    })
+  }
+}
+
+/// The bytes of one mip level, tightly packed.
+pub(crate) struct TexelLevel {
+  pub width: u32,
+  pub height: u32,
+  /// How wide and high a block of texels is, 1 unless the format is block
+  /// compressed.
+  pub block_size: u32,
+  /// How many bytes one block takes.
+  pub texel_size: usize,
+  pub bytes: Vec<u8>,
+}
+impl TexelLevel {
+  fn new<P>(img: &ImageBuffer<P, Vec<P::Subpixel>>) -> Self
+  where
+    P: Pixel + 'static,
+    P::Subpixel: Pod + 'static,
+  {
+    Self {
+      width: img.width(),
+      height: img.height(),
+      block_size: 1,
+      texel_size: size_of::<P::Subpixel>() * P::channel_count() as usize,
+      bytes: cast_slice::<P::Subpixel, u8>(img)
+        .expect("any slice can be seen as bytes")
+        .to_vec(),
+    }
+  }
+}
+
+/// If images of the format can have the features with optimal tiling.
+fn supports<B: Backend>(adapter: &Adapter<B>, format: Format, features: ImageFeature) -> bool {
+  adapter
+    .physical_device
+    .format_properties(Some(format))
+    .optimal_tiling
+    .contains(features)
+}
@@ -0,0 +1,549 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+  loaded_image::{record_mip_blits, stage_images},
+  texture::LayeredTexels,
+  Allocator, BufferBundle, HalError, Lifetime, LoadedImage, SamplerCache, TextureDesc, TextureKind,
+  TextureSource,
+};
+use core::mem::{size_of_val, ManuallyDrop};
+use gfx_hal::{
+  adapter::Adapter,
+  buffer::{Access as BufferAccess, Usage as BufferUsage},
+  command::{BufferCopy, CommandBuffer, OneShot},
+  device::Device,
+  format::Aspects,
+  image::{Access as ImageAccess, Layout, SubresourceRange},
+  memory::{Barrier, Dependencies, Properties},
+  pool::{CommandPool, CommandPoolCreateFlags},
+  pso::PipelineStage,
+  queue::{family::QueueGroup, CommandQueue, QueueFamilyId, Submission},
+  Backend, Graphics, Transfer,
+};
+
+/// Tells you when an upload is done, see [`Uploader::is_ready`].
+///
+/// Every upload recorded between two flushes gets the same token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadToken(u64);
+impl UploadToken {
+  /// For uploads that were written straight into memory and never needed a
+  /// batch at all.
+  const DONE: UploadToken = UploadToken(0);
+}
+
+/// A queue from a family that only does transfers, and the pool for it.
+struct TransferQueue<B: Backend> {
+  command_pool: CommandPool<B, Transfer>,
+  queue_group: QueueGroup<B, Transfer>,
+}
+
+/// The command buffers and staging buffers of one batch of uploads.
+struct Batch<B: Backend> {
+  /// The copies and the release barriers, when there's a transfer queue.
+  transfer_commands: Option<CommandBuffer<B, Transfer, OneShot>>,
+  /// The copies when there isn't a transfer queue, otherwise just the acquire
+  /// barriers.
+  graphics_commands: CommandBuffer<B, Graphics, OneShot>,
+  staging_bundles: Vec<BufferBundle<B, B::Device>>,
+}
+
+/// A batch that's been submitted, and what tells us when it's done.
+struct Submitted<B: Backend> {
+  token: UploadToken,
+  batch: Batch<B>,
+  fence: B::Fence,
+  /// Signaled by the transfer queue and waited on by the graphics queue.
+  semaphore: Option<B::Semaphore>,
+}
+
+/// Records buffer and image uploads into one batch, and submits the whole
+/// batch at once when flushed instead of waiting on each upload by itself.
+///
+/// With a dedicated transfer queue the copies run there, and ownership of each
+/// resource is then handed over to the graphics queue family with a release
+/// and acquire barrier pair. Without one everything runs on the graphics queue.
+pub struct Uploader<B: Backend> {
+  graphics_pool: ManuallyDrop<CommandPool<B, Graphics>>,
+  graphics_family: QueueFamilyId,
+  transfer: Option<TransferQueue<B>>,
+  recording: Option<Batch<B>>,
+  submitted: Vec<Submitted<B>>,
+  /// The token of the batch being recorded.
+  next_token: UploadToken,
+}
+impl<B: Backend> Uploader<B> {
+  pub fn new(
+    device: &B::Device, graphics_group: &QueueGroup<B, Graphics>,
+    transfer_group: Option<QueueGroup<B, Transfer>>,
+  ) -> Result<Self, HalError> {
+    let graphics_pool = unsafe {
+      device.create_command_pool_typed(graphics_group, CommandPoolCreateFlags::TRANSIENT)?
+    };
+    let transfer = match transfer_group {
+      Some(queue_group) => Some(TransferQueue {
+        command_pool: unsafe {
+          device.create_command_pool_typed(&queue_group, CommandPoolCreateFlags::TRANSIENT)?
+        },
+        queue_group,
+      }),
+      None => None,
+    };
+    Ok(Self {
+      graphics_pool: ManuallyDrop::new(graphics_pool),
+      graphics_family: graphics_group.family(),
+      transfer,
+      recording: None,
+      submitted: Vec::new(),
+      next_token: UploadToken(1),
+    })
+  }
+
+  /// If the copies run on a dedicated transfer queue.
+  pub fn has_transfer_queue(&self) -> bool {
+    self.transfer.is_some()
+  }
+
+  /// Makes a device local buffer and records copying the data into it. The
+  /// buffer can be used the way `usage` says once the token is ready.
+  ///
+  /// If there's memory that's device local and CPU visible at once, the data
+  /// is written straight into it and the token is ready right away.
+  pub fn upload_buffer<T: Copy>(
+    &mut self, device: &B::Device, allocator: &mut Allocator<B>, data: &[T], usage: BufferUsage,
+  ) -> Result<(BufferBundle<B, B::Device>, UploadToken), HalError> {
+    let size = size_of_val(data);
+    match BufferBundle::with_properties(
+      device,
+      allocator,
+      size,
+      usage,
+      Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE,
+      Lifetime::LongLived,
+    ) {
+      Ok(bundle) => {
+        unsafe { bundle.write(device, allocator, data)? };
+        return Ok((bundle, UploadToken::DONE));
+      }
+      Err(HalError::NoSuitableMemoryType) => (),
+      Err(e) => return Err(e),
+    }
+
+    let bundle = BufferBundle::with_properties(
+      device,
+      allocator,
+      size,
+      usage | BufferUsage::TRANSFER_DST,
+      Properties::DEVICE_LOCAL,
+      Lifetime::LongLived,
+    )?;
+    let staging_bundle =
+      BufferBundle::new_transient(device, allocator, size, BufferUsage::TRANSFER_SRC)?;
+    unsafe { staging_bundle.write(device, allocator, data)? };
+
+    let families = self.ownership_transfer();
+    let batch = self.recording();
+    let regions = [BufferCopy {
+      src: 0,
+      dst: 0,
+      size: size as u64,
+    }];
+    let (read_access, read_stage) = buffer_reads(usage);
+    unsafe {
+      match &mut batch.transfer_commands {
+        Some(transfer_commands) => {
+          transfer_commands.copy_buffer(&staging_bundle.buffer, &bundle.buffer, &regions);
+          let release = Barrier::Buffer {
+            states: BufferAccess::TRANSFER_WRITE..BufferAccess::empty(),
+            target: &*bundle.buffer,
+            families: families.clone(),
+            range: None..None,
+          };
+          transfer_commands.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+            Dependencies::empty(),
+            &[release],
+          );
+          let acquire = Barrier::Buffer {
+            states: BufferAccess::empty()..read_access,
+            target: &*bundle.buffer,
+            families,
+            range: None..None,
+          };
+          batch.graphics_commands.pipeline_barrier(
+            PipelineStage::TRANSFER..read_stage,
+            Dependencies::empty(),
+            &[acquire],
+          );
+        }
+        None => {
+          let graphics_commands = &mut batch.graphics_commands;
+          graphics_commands.copy_buffer(&staging_bundle.buffer, &bundle.buffer, &regions);
+          let barrier = Barrier::Buffer {
+            states: BufferAccess::TRANSFER_WRITE..read_access,
+            target: &*bundle.buffer,
+            families: None,
+            range: None..None,
+          };
+          graphics_commands.pipeline_barrier(
+            PipelineStage::TRANSFER..read_stage,
+            Dependencies::empty(),
+            &[barrier],
+          );
+        }
+      }
+    }
+    batch.staging_bundles.push(staging_bundle);
+    Ok((bundle, self.next_token))
+  }
+
+  /// Makes a sampled image and records copying the texels into it. The image
+  /// is in `ShaderReadOnlyOptimal` once the token is ready.
+  ///
+  /// The format is picked from the source and the desc's color space, see
+  /// [`TextureSource`]. With mipmaps the smaller levels are blitted on the GPU
+  /// if the format can be blitted with linear filtering, otherwise they're
+  /// resized on the CPU and uploaded along with level 0. The sampler comes
+  /// from the cache, so it's shared with every other image that uses the same
+  /// description.
+  pub fn upload_image<S: Into<TextureSource>>(
+    &mut self, adapter: &Adapter<B>, device: &B::Device, allocator: &mut Allocator<B>,
+    samplers: &mut SamplerCache<B>, source: S, desc: &TextureDesc,
+  ) -> Result<(LoadedImage<B, B::Device>, UploadToken), HalError> {
+    self.upload_layered(
+      adapter,
+      device,
+      allocator,
+      samplers,
+      vec![source],
+      TextureKind::D2,
+      desc,
+    )
+  }
+
+  /// Like `upload_image`, but for a texture array or cube map with one source
+  /// per layer. Every source has to be the same size and end up in the same
+  /// format, and a cube map takes its faces in the order +X, -X, +Y, -Y, +Z,
+  /// -Z.
+  ///
+  /// All the layers are copied and barriered together, and any mip levels
+  /// are blitted for every layer at once.
+  #[allow(clippy::too_many_arguments)]
+  pub fn upload_layered<S: Into<TextureSource>>(
+    &mut self, adapter: &Adapter<B>, device: &B::Device, allocator: &mut Allocator<B>,
+    samplers: &mut SamplerCache<B>, sources: Vec<S>, kind: TextureKind, desc: &TextureDesc,
+  ) -> Result<(LoadedImage<B, B::Device>, UploadToken), HalError> {
+    let sources = sources.into_iter().map(Into::into).collect();
+    let texels = LayeredTexels::new(adapter, sources, kind, desc, true)?;
+    let (shape, gpu_mips) = (texels.shape, texels.gpu_mips);
+    let (staging_bundle, copy_regions) = stage_images(adapter, device, allocator, &texels.layers)?;
+    let loaded = LoadedImage::new_unfilled(device, allocator, samplers, &shape, &desc.sampler)?;
+
+    let families = self.ownership_transfer();
+    let batch = self.recording();
+    let range = SubresourceRange {
+      aspects: Aspects::COLOR,
+      levels: 0..shape.mip_levels,
+      layers: 0..shape.layers,
+    };
+    let to_transfer_dst = Barrier::Image {
+      states: (ImageAccess::empty(), Layout::Undefined)
+        ..(ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal),
+      target: &*loaded.image,
+      families: None,
+      range: range.clone(),
+    };
+    unsafe {
+      match &mut batch.transfer_commands {
+        Some(transfer_commands) => {
+          transfer_commands.pipeline_barrier(
+            PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[to_transfer_dst],
+          );
+          transfer_commands.copy_buffer_to_image(
+            &staging_bundle.buffer,
+            &loaded.image,
+            Layout::TransferDstOptimal,
+            &copy_regions,
+          );
+          // The layout change happens as part of the ownership transfer, so
+          // both barriers have to agree on it. Blits need the graphics queue,
+          // so when there are any the image stays ready to be written to.
+          let (access, layout, stage) = if gpu_mips {
+            (
+              ImageAccess::TRANSFER_WRITE,
+              Layout::TransferDstOptimal,
+              PipelineStage::TRANSFER,
+            )
+          } else {
+            (
+              ImageAccess::SHADER_READ,
+              Layout::ShaderReadOnlyOptimal,
+              PipelineStage::FRAGMENT_SHADER,
+            )
+          };
+          let release = Barrier::Image {
+            states: (ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal)
+              ..(ImageAccess::empty(), layout),
+            target: &*loaded.image,
+            families: families.clone(),
+            range: range.clone(),
+          };
+          transfer_commands.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+            Dependencies::empty(),
+            &[release],
+          );
+          let acquire = Barrier::Image {
+            states: (ImageAccess::empty(), Layout::TransferDstOptimal)..(access, layout),
+            target: &*loaded.image,
+            families,
+            range,
+          };
+          batch.graphics_commands.pipeline_barrier(
+            PipelineStage::TRANSFER..stage,
+            Dependencies::empty(),
+            &[acquire],
+          );
+          if gpu_mips {
+            record_mip_blits(&mut batch.graphics_commands, &loaded.image, &shape);
+          }
+        }
+        None => {
+          let graphics_commands = &mut batch.graphics_commands;
+          graphics_commands.pipeline_barrier(
+            PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[to_transfer_dst],
+          );
+          graphics_commands.copy_buffer_to_image(
+            &staging_bundle.buffer,
+            &loaded.image,
+            Layout::TransferDstOptimal,
+            &copy_regions,
+          );
+          if gpu_mips {
+            record_mip_blits(graphics_commands, &loaded.image, &shape);
+          } else {
+            let to_shader_read = Barrier::Image {
+              states: (ImageAccess::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                ..(ImageAccess::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+              target: &*loaded.image,
+              families: None,
+              range,
+            };
+            graphics_commands.pipeline_barrier(
+              PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+              Dependencies::empty(),
+              &[to_shader_read],
+            );
+          }
+        }
+      }
+    }
+    batch.staging_bundles.push(staging_bundle);
+    Ok((loaded, self.next_token))
+  }
+
+  /// Submits everything recorded since the last flush as one batch, without
+  /// waiting for it.
+  pub fn flush(
+    &mut self, device: &B::Device, graphics_queue: &mut CommandQueue<B, Graphics>,
+  ) -> Result<(), HalError> {
+    let mut batch = match self.recording.take() {
+      Some(batch) => batch,
+      None => return Ok(()),
+    };
+    unsafe {
+      let fence = device.create_fence(false)?;
+      let semaphore = match (&mut batch.transfer_commands, &mut self.transfer) {
+        (Some(transfer_commands), Some(transfer)) => {
+          transfer_commands.finish();
+          let semaphore = device.create_semaphore()?;
+          let submission = Submission {
+            command_buffers: Some(&*transfer_commands),
+            wait_semaphores: core::iter::empty::<(&B::Semaphore, PipelineStage)>(),
+            signal_semaphores: Some(&semaphore),
+          };
+          transfer.queue_group.queues[0].submit(submission, None);
+          Some(semaphore)
+        }
+        _ => None,
+      };
+      batch.graphics_commands.finish();
+      match &semaphore {
+        Some(semaphore) => {
+          let submission = Submission {
+            command_buffers: Some(&batch.graphics_commands),
+            wait_semaphores: Some((semaphore, PipelineStage::TRANSFER)),
+            signal_semaphores: core::iter::empty::<&B::Semaphore>(),
+          };
+          graphics_queue.submit(submission, Some(&fence));
+        }
+        None => graphics_queue.submit_nosemaphores(Some(&batch.graphics_commands), Some(&fence)),
+      }
+      debug!(
+        "Submitted upload batch {:?} with {} staging buffers",
+        self.next_token,
+        batch.staging_bundles.len()
+      );
+      self.submitted.push(Submitted {
+        token: self.next_token,
+        batch,
+        fence,
+        semaphore,
+      });
+    }
+    self.next_token.0 += 1;
+    Ok(())
+  }
+
+  /// If the uploads that gave out this token are done, so that the resources
+  /// can be used. Uploads that haven't been flushed are never ready.
+  pub fn is_ready(
+    &mut self, device: &B::Device, allocator: &mut Allocator<B>, token: UploadToken,
+  ) -> Result<bool, HalError> {
+    self.poll(device, allocator)?;
+    Ok(token < self.next_token && self.submitted.iter().all(|s| s.token != token))
+  }
+
+  /// Blocks until the uploads that gave out this token are done, flushing them
+  /// first if need be.
+  pub fn wait(
+    &mut self, device: &B::Device, allocator: &mut Allocator<B>,
+    graphics_queue: &mut CommandQueue<B, Graphics>, token: UploadToken,
+  ) -> Result<(), HalError> {
+    if token >= self.next_token {
+      self.flush(device, graphics_queue)?;
+    }
+    if let Some(submitted) = self.submitted.iter().find(|s| s.token == token) {
+      unsafe { device.wait_for_fence(&submitted.fence, core::u64::MAX)? };
+    }
+    self.poll(device, allocator)
+  }
+
+  /// The queue family range for ownership transfer barriers, if there's a
+  /// transfer queue.
+  fn ownership_transfer(&self) -> Option<core::ops::Range<QueueFamilyId>> {
+    self
+      .transfer
+      .as_ref()
+      .map(|transfer| transfer.queue_group.family()..self.graphics_family)
+  }
+
+  /// The batch being recorded, which gets started if there isn't one.
+  fn recording(&mut self) -> &mut Batch<B> {
+    if self.recording.is_none() {
+      let mut graphics_commands = self.graphics_pool.acquire_command_buffer::<OneShot>();
+      unsafe { graphics_commands.begin() };
+      let transfer_commands = self.transfer.as_mut().map(|transfer| {
+        let mut transfer_commands = transfer.command_pool.acquire_command_buffer::<OneShot>();
+        unsafe { transfer_commands.begin() };
+        transfer_commands
+      });
+      self.recording = Some(Batch {
+        transfer_commands,
+        graphics_commands,
+        staging_bundles: Vec::new(),
+      });
+    }
+    self.recording.as_mut().expect("we just started recording")
+  }
+
+  /// Cleans up after every submitted batch that's done.
+  fn poll(&mut self, device: &B::Device, allocator: &mut Allocator<B>) -> Result<(), HalError> {
+    let mut i = 0;
+    while i < self.submitted.len() {
+      if unsafe { device.get_fence_status(&self.submitted[i].fence)? } {
+        let submitted = self.submitted.remove(i);
+        unsafe { self.retire(device, allocator, submitted) };
+      } else {
+        i += 1;
+      }
+    }
+    Ok(())
+  }
+
+  unsafe fn retire(
+    &mut self, device: &B::Device, allocator: &mut Allocator<B>, submitted: Submitted<B>,
+  ) {
+    device.destroy_fence(submitted.fence);
+    if let Some(semaphore) = submitted.semaphore {
+      device.destroy_semaphore(semaphore);
+    }
+    self.free_batch(device, allocator, submitted.batch);
+  }
+
+  unsafe fn free_batch(
+    &mut self, device: &B::Device, allocator: &mut Allocator<B>, batch: Batch<B>,
+  ) {
+    for staging_bundle in batch.staging_bundles {
+      staging_bundle.manually_drop(device, allocator);
+    }
+    self.graphics_pool.free(Some(batch.graphics_commands));
+    if let (Some(transfer_commands), Some(transfer)) = (batch.transfer_commands, &mut self.transfer)
+    {
+      transfer.command_pool.free(Some(transfer_commands));
+    }
+  }
+
+  /// Waits for everything that was submitted, then frees it all along with
+  /// the command pools. Anything recorded but not flushed is thrown away.
+  ///
+  /// # Safety
+  ///
+  /// `device` and `allocator` have to be the ones the uploads were made with.
+  pub unsafe fn manually_drop(&mut self, device: &B::Device, allocator: &mut Allocator<B>) {
+    use core::ptr::read;
+    while let Some(submitted) = self.submitted.pop() {
+      let _ = device.wait_for_fence(&submitted.fence, core::u64::MAX);
+      self.retire(device, allocator, submitted);
+    }
+    if let Some(batch) = self.recording.take() {
+      self.free_batch(device, allocator, batch);
+    }
+    if let Some(transfer) = self.transfer.take() {
+      device.destroy_command_pool(transfer.command_pool.into_raw());
+    }
+    device.destroy_command_pool(ManuallyDrop::into_inner(read(&self.graphics_pool)).into_raw());
+  }
+}
+
+/// How a buffer with the given usage gets read after an upload, and the
+/// earliest stage that reads it.
+fn buffer_reads(usage: BufferUsage) -> (BufferAccess, PipelineStage) {
+  let mut access = BufferAccess::empty();
+  let mut stage = PipelineStage::empty();
+  if usage.contains(BufferUsage::INDIRECT) {
+    access |= BufferAccess::INDIRECT_COMMAND_READ;
+    stage |= PipelineStage::DRAW_INDIRECT;
+  }
+  if usage.contains(BufferUsage::INDEX) {
+    access |= BufferAccess::INDEX_BUFFER_READ;
+    stage |= PipelineStage::VERTEX_INPUT;
+  }
+  if usage.contains(BufferUsage::VERTEX) {
+    access |= BufferAccess::VERTEX_BUFFER_READ;
+    stage |= PipelineStage::VERTEX_INPUT;
+  }
+  if usage.contains(BufferUsage::UNIFORM) {
+    access |= BufferAccess::CONSTANT_BUFFER_READ;
+    stage |= PipelineStage::VERTEX_SHADER | PipelineStage::FRAGMENT_SHADER;
+  }
+  if usage
+    .intersects(BufferUsage::STORAGE | BufferUsage::UNIFORM_TEXEL | BufferUsage::STORAGE_TEXEL)
+  {
+    access |= BufferAccess::SHADER_READ;
+    stage |= PipelineStage::VERTEX_SHADER | PipelineStage::FRAGMENT_SHADER;
+  }
+  if usage.contains(BufferUsage::TRANSFER_SRC) {
+    access |= BufferAccess::TRANSFER_READ;
+    stage |= PipelineStage::TRANSFER;
+  }
+  if stage.is_empty() {
+    stage = PipelineStage::BOTTOM_OF_PIPE;
+  }
+  (access, stage)
+}
@@ -0,0 +1,198 @@
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use core::mem::{align_of, size_of};
+use gfx_hal::{
+  format::Format,
+  pso::{AttributeDesc, BufferIndex, ElemOffset, ElemStride, Element, VertexBufferDesc},
+};
+use nalgebra_glm as glm;
+
+/// The vertex attributes of a struct that gets put in a vertex buffer.
+///
+/// Derive this with `#[derive(VertexLayout)]` instead of writing it out, the
+/// derive gets the offsets right for any `#[repr(C)]` struct:
+///
+/// ```no_run
+/// # use learn_gfx_hal::VertexLayout;
+/// #[derive(VertexLayout)]
+/// #[repr(C)]
+/// struct Instance {
+///   #[location(2)]
+///   model: [[f32; 4]; 4],
+///   #[format(Rgba8Uint)]
+///   bone_ids: [u8; 4],
+/// }
+/// ```
+pub trait VertexLayout: Sized {
+  /// If the buffer advances once per instance instead of once per vertex.
+  const PER_INSTANCE: bool = false;
+
+  /// One attribute per field, reading from the buffer at `binding`. Matrix
+  /// fields get one attribute per column, since an attribute that takes up
+  /// more than one location doesn't work on dx12.
+  fn attributes(binding: BufferIndex) -> Vec<AttributeDesc>;
+
+  /// The vertex buffer that the attributes read from.
+  fn buffer_desc(binding: BufferIndex) -> VertexBufferDesc {
+    VertexBufferDesc {
+      binding,
+      stride: size_of::<Self>() as ElemStride,
+      rate: if Self::PER_INSTANCE { 1 } else { 0 },
+    }
+  }
+}
+
+/// The format that a vertex attribute field is read with.
+pub trait VertexFormat {
+  /// The format of each column.
+  const FORMAT: Format;
+  /// How many locations the field takes up. More than 1 only for matrices.
+  const COLUMNS: u32 = 1;
+}
+
+macro_rules! vertex_formats {
+  ($($ty:ty => $format:ident $(* $columns:expr)?,)*) => {
+    $(
+      impl VertexFormat for $ty {
+        const FORMAT: Format = Format::$format;
+        $(const COLUMNS: u32 = $columns;)?
+      }
+    )*
+  };
+}
+
+vertex_formats! {
+  f32 => R32Float,
+  [f32; 2] => Rg32Float,
+  [f32; 3] => Rgb32Float,
+  [f32; 4] => Rgba32Float,
+  i32 => R32Int,
+  [i32; 2] => Rg32Int,
+  [i32; 3] => Rgb32Int,
+  [i32; 4] => Rgba32Int,
+  u32 => R32Uint,
+  [u32; 2] => Rg32Uint,
+  [u32; 3] => Rgb32Uint,
+  [u32; 4] => Rgba32Uint,
+  [u8; 4] => Rgba8Unorm,
+  [[f32; 2]; 2] => Rg32Float * 2,
+  [[f32; 3]; 3] => Rgb32Float * 3,
+  [[f32; 4]; 4] => Rgba32Float * 4,
+  glm::Vec2 => Rg32Float,
+  glm::Vec3 => Rgb32Float,
+  glm::Vec4 => Rgba32Float,
+  glm::Mat2 => Rg32Float * 2,
+  glm::Mat3 => Rgb32Float * 3,
+  glm::Mat4 => Rgba32Float * 4,
+}
+
+/// Lays out fields the way `#[repr(C)]` does, for the derive.
+#[doc(hidden)]
+pub struct AttributeBuilder {
+  binding: BufferIndex,
+  location: u32,
+  offset: usize,
+  attributes: Vec<AttributeDesc>,
+}
+impl AttributeBuilder {
+  pub fn new(binding: BufferIndex) -> Self {
+    Self {
+      binding,
+      location: 0,
+      offset: 0,
+      attributes: Vec::new(),
+    }
+  }
+
+  /// Adds the next field, at the location given or else right after the last
+  /// field's.
+  pub fn field<T: VertexFormat>(&mut self, location: Option<u32>, format: Option<Format>) {
+    let align = align_of::<T>();
+    self.offset = (self.offset + align - 1) / align * align;
+    if let Some(location) = location {
+      self.location = location;
+    }
+    let column_size = size_of::<T>() / T::COLUMNS as usize;
+    for column in 0..T::COLUMNS {
+      self.attributes.push(AttributeDesc {
+        location: self.location,
+        binding: self.binding,
+        element: Element {
+          format: format.unwrap_or(T::FORMAT),
+          offset: (self.offset + column as usize * column_size) as ElemOffset,
+        },
+      });
+      self.location += 1;
+    }
+    self.offset += size_of::<T>();
+  }
+
+  pub fn finish(self) -> Vec<AttributeDesc> {
+    self.attributes
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::VertexLayout;
+
+  #[allow(dead_code)]
+  #[derive(VertexLayout)]
+  #[repr(C)]
+  struct Particle {
+    position: [f32; 3],
+    color: [u8; 4],
+    #[format(Rgba8Uint)]
+    flags: [u8; 4],
+    size: f32,
+  }
+
+  #[allow(dead_code)]
+  #[derive(VertexLayout)]
+  #[repr(C)]
+  #[per_instance]
+  struct Emitter {
+    #[location(4)]
+    transform: [[f32; 4]; 4],
+    lifetime: f32,
+  }
+
+  #[test]
+  fn derived_vertex_layouts() {
+    let formats_and_offsets = |attributes: &[AttributeDesc]| -> Vec<(u32, Format, u32)> {
+      attributes
+        .iter()
+        .map(|a| (a.location, a.element.format, a.element.offset))
+        .collect()
+    };
+    assert_eq!(
+      formats_and_offsets(&Particle::attributes(0)),
+      vec![
+        (0, Format::Rgb32Float, 0),
+        (1, Format::Rgba8Unorm, 12),
+        (2, Format::Rgba8Uint, 16),
+        (3, Format::R32Float, 20),
+      ]
+    );
+    assert_eq!(Particle::buffer_desc(0).stride, 24);
+    assert_eq!(Particle::buffer_desc(0).rate, 0);
+
+    // the matrix is split into a column per location
+    let attributes = Emitter::attributes(1);
+    assert_eq!(
+      formats_and_offsets(&attributes),
+      vec![
+        (4, Format::Rgba32Float, 0),
+        (5, Format::Rgba32Float, 16),
+        (6, Format::Rgba32Float, 32),
+        (7, Format::Rgba32Float, 48),
+        (8, Format::R32Float, 64),
+      ]
+    );
+    assert!(attributes.iter().all(|a| a.binding == 1));
+    assert_eq!(Emitter::buffer_desc(1).stride, 68);
+    assert_eq!(Emitter::buffer_desc(1).rate, 1);
+  }
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "gl")]
+
+//! glutin can only make a GL context (even a headless OSMesa one) while
+//! connected to an X11 or Wayland display. Without one we have to get an error
+//! back, not a panic, and with one the context has to actually draw.
+
+use gfx_hal::window::Extent2D;
+use learn_gfx_hal::{HalError, HalState};
+
+#[test]
+fn headless_gl_draws_or_says_why_not() {
+  let extent = Extent2D {
+    width: 64,
+    height: 48,
+  };
+  let has_display =
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some();
+  match HalState::new_headless(extent, false) {
+    Ok(mut hal_state) => {
+      hal_state.draw_clear_frame([1.0, 0.0, 0.0, 1.0]).unwrap();
+      let frame = hal_state.capture_frame().unwrap();
+      assert_eq!(frame.dimensions(), (64, 48));
+      assert!(frame.pixels().all(|pixel| pixel.data == [255, 0, 0, 255]));
+    }
+    Err(HalError::GlNoDisplay) => assert!(!has_display),
+    // There's a display, but glutin couldn't load OSMesa.
+    Err(HalError::GlContext(e)) => {
+      assert!(has_display, "{}", e);
+      eprintln!("Skipping the draw, no GL context: {}", e);
+    }
+    Err(e) => panic!("{}", e),
+  }
+}
@@ -0,0 +1,506 @@
+#![cfg(feature = "windowed")]
+
+//! Renders the example scenes offscreen and compares them against the
+//! reference images in `tests/golden/`.
+//!
+//! These need a real GPU (or a software driver like llvmpipe or SwiftShader)
+//! for the selected backend. They don't run with `gl`, which needs a display
+//! even when it's headless, see `tests/gl_headless.rs` for that one. To (re)generate the reference images after an
+//! intentional change, run the tests with `LEARN_GFX_HAL_BLESS=1` set and then
+//! look over the new images before committing them.
+
+use gfx_hal::{
+  image::{Filter, WrapMode},
+  window::Extent2D,
+};
+use image::{Rgba, RgbaImage};
+use learn_gfx_hal::{
+  back, AdapterPreference, HalConfig, HalState, QuaternionFreeCamera, SamplerDesc, ShaderVariant,
+  Triangle, MAX_CUBES,
+};
+use nalgebra_glm as glm;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::path::PathBuf;
+
+const WIDTH: u32 = 400;
+const HEIGHT: u32 = 300;
+
+/// How far apart two channel values can be and still count as the same.
+const CHANNEL_TOLERANCE: u8 = 3;
+
+/// How many pixels (per 10,000) can differ before the images don't match.
+///
+/// Different drivers don't rasterize edges in exactly the same way, so we
+/// allow for a thin line of pixels here and there to be off.
+const BAD_PIXELS_PER_10K: usize = 20;
+
+/// Uses the adapter named by `LEARN_GFX_HAL_ADAPTER` if it's set (eg: `cpu` to
+/// force llvmpipe or SwiftShader), otherwise the first one that works.
+fn headless(use_depth: bool) -> HalState<back::Backend> {
+  headless_with_config(HalConfig::default().depth(use_depth))
+}
+
+/// Like `headless`, but with the rest of the config given.
+fn headless_with_config(config: HalConfig) -> HalState<back::Backend> {
+  let preference: AdapterPreference = std::env::var("LEARN_GFX_HAL_ADAPTER")
+    .map(|name| name.parse().expect("Couldn't parse LEARN_GFX_HAL_ADAPTER!"))
+    .unwrap_or_default();
+  HalState::new_headless_with_config(
+    Extent2D {
+      width: WIDTH,
+      height: HEIGHT,
+    },
+    &config.adapter(preference),
+  )
+  .expect("Couldn't make a headless HalState!")
+}
+
+fn perspective() -> glm::TMat4<f32> {
+  let mut temp = glm::perspective_lh_zo(
+    WIDTH as f32 / HEIGHT as f32,
+    f32::to_radians(50.0),
+    0.1,
+    1000.0,
+  );
+  temp[(1, 1)] *= -1.0;
+  temp
+}
+
+fn golden_path(name: &str) -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("tests")
+    .join("golden")
+    .join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, suffix: &str) -> PathBuf {
+  let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("target")
+    .join("golden");
+  std::fs::create_dir_all(&dir).expect("Couldn't make the golden output directory!");
+  dir.join(format!("{}-{}.png", name, suffix))
+}
+
+fn pixels_match(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+  a.data
+    .iter()
+    .zip(b.data.iter())
+    .all(|(&x, &y)| (x as i16 - y as i16).abs() <= CHANNEL_TOLERANCE as i16)
+}
+
+/// Checks the image against the reference of the given name.
+///
+/// On a mismatch the actual image and a diff image (differing pixels in red
+/// over a darkened copy of the reference) are saved under `target/golden/`.
+fn check_golden(name: &str, actual: &RgbaImage) {
+  let golden = golden_path(name);
+  if std::env::var_os("LEARN_GFX_HAL_BLESS").is_some() {
+    actual
+      .save(&golden)
+      .expect("Couldn't save the blessed image!");
+    return;
+  }
+  if !golden.exists() {
+    let actual_path = output_path(name, "actual");
+    actual
+      .save(&actual_path)
+      .expect("Couldn't save the actual image!");
+    panic!(
+      "{}: there's no reference image at {} yet. Run the tests with LEARN_GFX_HAL_BLESS=1 to make it, and look it over before committing it. This run's image is at {}",
+      name,
+      golden.display(),
+      actual_path.display()
+    );
+  }
+  let expected = image::open(&golden)
+    .unwrap_or_else(|e| {
+      panic!(
+        "Couldn't load the reference image {}: {}",
+        golden.display(),
+        e
+      )
+    })
+    .to_rgba();
+  assert_eq!(
+    expected.dimensions(),
+    actual.dimensions(),
+    "{}: the image size changed",
+    name
+  );
+  let mut bad_pixels = 0;
+  let diff = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+    let (a, e) = (actual.get_pixel(x, y), expected.get_pixel(x, y));
+    if pixels_match(a, e) {
+      Rgba {
+        data: [e.data[0] / 4, e.data[1] / 4, e.data[2] / 4, 255],
+      }
+    } else {
+      bad_pixels += 1;
+      Rgba {
+        data: [255, 0, 0, 255],
+      }
+    }
+  });
+  let allowed = (actual.width() * actual.height()) as usize * BAD_PIXELS_PER_10K / 10_000;
+  if bad_pixels > allowed {
+    let actual_path = output_path(name, "actual");
+    let diff_path = output_path(name, "diff");
+    actual
+      .save(&actual_path)
+      .expect("Couldn't save the actual image!");
+    diff
+      .save(&diff_path)
+      .expect("Couldn't save the diff image!");
+    panic!(
+      "{}: {} pixels differ (only {} allowed). See {} and {}",
+      name,
+      bad_pixels,
+      allowed,
+      actual_path.display(),
+      diff_path.display()
+    );
+  }
+}
+
+/// Checks that a frame is exactly the same as another one drawn in the same
+/// run, for features that shouldn't change the picture at all.
+fn assert_same_frame(actual: &RgbaImage, expected: &RgbaImage) {
+  assert_eq!(actual.dimensions(), expected.dimensions());
+  let differing = actual
+    .pixels()
+    .zip(expected.pixels())
+    .filter(|(a, e)| a != e)
+    .count();
+  assert_eq!(differing, 0, "pixels differ between the two frames");
+}
+
+#[test]
+fn clear_the_window() {
+  let mut hal_state = headless(false);
+  hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+  check_golden("clear_the_window", &hal_state.capture_frame().unwrap());
+}
+
+#[test]
+fn clear_the_window_multisampled() {
+  // Resolving a clear gives the same color whatever the sample count, so
+  // this has to match the plain clear exactly.
+  let mut hal_state = headless_with_config(HalConfig::default().samples(4));
+  assert!(hal_state.chosen_config().samples <= 4);
+  hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+  check_golden("clear_the_window", &hal_state.capture_frame().unwrap());
+}
+
+/// The triangle from the `triangle_intro` example, with the mouse's corner
+/// pinned in place.
+#[test]
+fn triangle_intro() {
+  let mut hal_state = headless(false);
+  let triangle = Triangle::white([[-0.5, 0.5], [-0.5, -0.5], [0.5, 0.25]]);
+  hal_state.draw_triangle_frame(triangle).unwrap();
+  check_golden("triangle_intro", &hal_state.capture_frame().unwrap());
+}
+
+/// The colored triangle from the `shaders` example, at full brightness.
+#[test]
+fn shaders_triangle() {
+  let mut hal_state = headless(false);
+  let triangle = Triangle {
+    points: [[-0.5, 0.5], [-0.5, -0.5], [0.5, 0.25]],
+    colors: [[0.9, 0.0, 0.0], [0.0, 0.9, 0.0], [0.0, 0.0, 0.9]],
+  };
+  hal_state.draw_triangle_frame(triangle).unwrap();
+  check_golden("shaders_triangle", &hal_state.capture_frame().unwrap());
+}
+
+/// The front face of one cube, seen straight on with no perspective, is
+/// exactly a textured quad.
+fn draw_textured_quad(hal_state: &mut HalState<back::Backend>) -> RgbaImage {
+  let camera = QuaternionFreeCamera::at_position(glm::make_vec3(&[0.0, 0.0, -2.0]));
+  let projection = {
+    let aspect = WIDTH as f32 / HEIGHT as f32;
+    let mut temp = glm::ortho_lh_zo(-aspect, aspect, -1.0, 1.0, 0.1, 100.0);
+    temp[(1, 1)] *= -1.0;
+    temp
+  };
+  let view_projection = projection * camera.make_view_matrix();
+  let model = glm::translation(&glm::make_vec3(&[-0.5, -0.5, 0.0]));
+  hal_state
+    .draw_cubes_frame(&view_projection, &[model])
+    .unwrap();
+  hal_state.capture_frame().unwrap()
+}
+
+#[test]
+fn textured_quad() {
+  let mut hal_state = headless(true);
+  check_golden("textured_quad", &draw_textured_quad(&mut hal_state));
+}
+
+/// Draws the textured quad with a fresh `HalState` made from the config.
+fn textured_quad_with_config(config: HalConfig) -> RgbaImage {
+  draw_textured_quad(&mut headless_with_config(config))
+}
+
+#[test]
+fn textured_quad_uploaded_on_a_transfer_queue() {
+  // Adapters without a transfer only queue family just use the graphics queue,
+  // either way the texture and cube have to arrive exactly as they would on
+  // the graphics queue.
+  assert_same_frame(
+    &textured_quad_with_config(HalConfig::default().transfer_queue(true)),
+    &textured_quad_with_config(HalConfig::default().transfer_queue(false)),
+  );
+}
+
+#[test]
+fn textured_quad_with_a_clamped_sampler() {
+  // Nearest filtering never reaches past the edge of the texture on a quad
+  // seen straight on, so the wrap mode can't change anything.
+  let desc = SamplerDesc::new(Filter::Nearest, WrapMode::Clamp).anisotropy(16);
+  let mut hal_state = headless_with_config(HalConfig::default().texture_sampler(desc));
+  assert_eq!(hal_state.samplers().sampler_count(), 1);
+  assert_same_frame(
+    &draw_textured_quad(&mut hal_state),
+    &textured_quad_with_config(HalConfig::default()),
+  );
+}
+
+#[test]
+fn textured_quad_with_mipmaps() {
+  // The quad is drawn smaller than the texture, so trilinear filtering reads
+  // from the generated levels. Blending texels rounds differently on each
+  // driver, so compare the upload queues against each other rather than
+  // against a reference image.
+  let frames: Vec<_> = [false, true]
+    .iter()
+    .map(|&transfer_queue| {
+      let mut hal_state = headless_with_config(
+        HalConfig::default()
+          .mipmaps(true)
+          .transfer_queue(transfer_queue),
+      );
+      assert!(hal_state.texture_mip_levels() > 1);
+      draw_textured_quad(&mut hal_state)
+    })
+    .collect();
+  assert_same_frame(&frames[1], &frames[0]);
+}
+
+#[test]
+#[cfg(feature = "runtime-shaders")]
+fn shaders_reload_from_files() {
+  use learn_gfx_hal::{ShaderSources, FRAGMENT_SOURCE, VERTEX_SOURCE};
+  use std::time::Duration;
+
+  let dir = std::env::temp_dir().join(format!("learn-gfx-hal-shaders-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let (vertex_path, fragment_path) = (dir.join("cube.vert"), dir.join("cube.frag"));
+  std::fs::write(&vertex_path, VERTEX_SOURCE).unwrap();
+  std::fs::write(&fragment_path, FRAGMENT_SOURCE).unwrap();
+  let mut hal_state = headless_with_config(
+    HalConfig::default().shaders(ShaderSources::files(&vertex_path, &fragment_path)),
+  );
+  // the same GLSL as the built in shaders, so the same picture
+  let built_in = textured_quad_with_config(HalConfig::default());
+  assert_same_frame(&draw_textured_quad(&mut hal_state), &built_in);
+
+  // Modification times can be as coarse as a second, so wait that long before
+  // each save to be sure that it's noticed.
+  let save = |source: &str| {
+    std::thread::sleep(Duration::from_millis(1100));
+    std::fs::write(&fragment_path, source).unwrap();
+  };
+  save("#version 450\nthis isn't GLSL");
+  assert!(!hal_state.reload_changed_shaders().unwrap());
+  assert_same_frame(&draw_textured_quad(&mut hal_state), &built_in);
+
+  save(
+    "#version 450
+layout (location = 0) out vec4 color;
+void main()
+{
+  color = vec4(1.0, 0.0, 0.0, 1.0);
+}",
+  );
+  assert!(hal_state.reload_changed_shaders().unwrap());
+  let frame = draw_textured_quad(&mut hal_state);
+  assert_eq!(
+    frame.get_pixel(WIDTH / 2, HEIGHT / 2).data,
+    [255, 0, 0, 255]
+  );
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(feature = "runtime-shaders")]
+fn spirv_cache_keeps_compiled_shaders() {
+  use learn_gfx_hal::{ShaderSource, ShaderSources, FRAGMENT_SOURCE, VERTEX_SOURCE};
+
+  let dir = std::env::temp_dir().join(format!("learn-gfx-hal-spirv-{}", std::process::id()));
+  let config = || {
+    HalConfig::default()
+      .shaders(ShaderSources {
+        vertex: ShaderSource::Embedded(VERTEX_SOURCE),
+        fragment: ShaderSource::Embedded(FRAGMENT_SOURCE),
+      })
+      .spirv_cache(&dir)
+  };
+  let cache_entries = || std::fs::read_dir(&dir).unwrap().count();
+  let compiled = textured_quad_with_config(config());
+  assert_eq!(cache_entries(), 2);
+
+  // the second time around both shaders come out of the cache
+  assert_same_frame(&textured_quad_with_config(config()), &compiled);
+  assert_eq!(cache_entries(), 2);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn untextured_shader_variant() {
+  let mut hal_state = headless(true);
+  let textured = draw_textured_quad(&mut hal_state);
+  let untextured = ShaderVariant::new().constant_bool(0, false);
+  hal_state.set_shader_variant(untextured.clone()).unwrap();
+  assert_eq!(hal_state.shader_variant(), &untextured);
+  let frame = draw_textured_quad(&mut hal_state);
+  assert_eq!(
+    frame.get_pixel(WIDTH / 2, HEIGHT / 2).data,
+    [255, 255, 255, 255]
+  );
+
+  // going back uses the pipeline that was made at the start
+  hal_state.set_shader_variant(ShaderVariant::new()).unwrap();
+  assert_same_frame(&draw_textured_quad(&mut hal_state), &textured);
+}
+
+#[test]
+#[cfg(feature = "runtime-shaders")]
+fn shader_variants_with_defines_and_includes() {
+  use learn_gfx_hal::{ShaderSource, ShaderSources, VERTEX_SOURCE};
+
+  let dir = std::env::temp_dir().join(format!("learn-gfx-hal-variants-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(
+    dir.join("colors.glsl"),
+    "const vec4 RED = vec4(1.0, 0.0, 0.0, 1.0);\nconst vec4 BLUE = vec4(0.0, 0.0, 1.0, 1.0);\n",
+  )
+  .unwrap();
+  let fragment = "#version 450
+#extension GL_GOOGLE_include_directive : require
+#include <colors.glsl>
+layout (location = 0) out vec4 color;
+void main()
+{
+#ifdef RED_CUBES
+  color = RED;
+#else
+  color = BLUE;
+#endif
+}";
+  let mut hal_state = headless_with_config(
+    HalConfig::default()
+      .shaders(ShaderSources {
+        vertex: ShaderSource::Embedded(VERTEX_SOURCE),
+        fragment: ShaderSource::Embedded(fragment),
+      })
+      .shader_variant(ShaderVariant::new().include_dir(&dir)),
+  );
+  let center = |hal_state: &mut HalState<back::Backend>| {
+    draw_textured_quad(hal_state)
+      .get_pixel(WIDTH / 2, HEIGHT / 2)
+      .data
+  };
+  assert_eq!(center(&mut hal_state), [0, 0, 255, 255]);
+  let red = ShaderVariant::new().include_dir(&dir).define("RED_CUBES");
+  hal_state.set_shader_variant(red).unwrap();
+  assert_eq!(center(&mut hal_state), [255, 0, 0, 255]);
+
+  // without the include dir the header can't be found, so nothing changes
+  let lost = ShaderVariant::new().define("RED_CUBES");
+  assert!(hal_state.set_shader_variant(lost).is_err());
+  assert_eq!(center(&mut hal_state), [255, 0, 0, 255]);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn depth_buffer() {
+  // The cubes overlap, so this only looks right if the depth test works.
+  let mut hal_state = headless(true);
+  let camera = QuaternionFreeCamera::at_position(glm::make_vec3(&[0.0, 0.0, -5.0]));
+  let view_projection = perspective() * camera.make_view_matrix();
+  let models = [
+    glm::translation(&glm::make_vec3(&[-1.0, -0.5, 1.0])),
+    glm::rotate(
+      &glm::translation(&glm::make_vec3(&[-0.3, -0.7, 0.5])),
+      f32::to_radians(30.0),
+      &glm::make_vec3(&[0.3, 0.4, 0.5]).normalize(),
+    ),
+    glm::rotate(
+      &glm::translation(&glm::make_vec3(&[0.2, -0.2, 1.5])),
+      f32::to_radians(60.0),
+      &glm::make_vec3(&[0.0, 1.0, 0.0]),
+    ),
+  ];
+  hal_state
+    .draw_cubes_frame(&view_projection, &models)
+    .unwrap();
+  check_golden("depth_buffer", &hal_state.capture_frame().unwrap());
+}
+
+#[test]
+fn instanced_drawing() {
+  let mut hal_state = headless(true);
+  let mut rng = StdRng::seed_from_u64(0x1EA7_9FA1);
+  let scaling = (MAX_CUBES as f32).cbrt() * 3.0;
+  let models: Vec<_> = (0..MAX_CUBES)
+    .map(|_| {
+      let rand_vec = glm::vec3(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+      glm::translation(&(scaling * rand_vec))
+    })
+    .collect();
+  let camera = QuaternionFreeCamera::at_position(glm::make_vec3(&[0.0, 0.0, -5.0]));
+  let view_projection = perspective() * camera.make_view_matrix();
+  hal_state
+    .draw_cubes_frame(&view_projection, &models)
+    .unwrap();
+  check_golden("instanced_drawing", &hal_state.capture_frame().unwrap());
+}
+
+#[test]
+fn recreate_swapchain_resizes_the_frames() {
+  let mut hal_state = headless(true);
+  hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+  hal_state
+    .recreate_swapchain(Extent2D {
+      width: WIDTH / 2,
+      height: HEIGHT / 2,
+    })
+    .unwrap();
+  hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+  let frame = hal_state.capture_frame().unwrap();
+  assert_eq!(frame.dimensions(), (WIDTH / 2, HEIGHT / 2));
+  assert!(frame.pixels().all(|pixel| pixel == frame.get_pixel(0, 0)));
+}
+
+#[test]
+fn resizing_reuses_memory() {
+  // Everything that gets remade on a resize (or captured) is freed first, so
+  // once we've done each of those the allocator shouldn't need any more
+  // device allocations.
+  let mut hal_state = headless(true);
+  let extent = Extent2D {
+    width: WIDTH / 2,
+    height: HEIGHT / 2,
+  };
+  hal_state.recreate_swapchain(extent).unwrap();
+  hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+  hal_state.capture_frame().unwrap();
+  let allocation_count = hal_state.allocator().allocation_count();
+  for _ in 0..5 {
+    hal_state.recreate_swapchain(extent).unwrap();
+    hal_state.draw_clear_frame([0.1, 0.2, 0.3, 1.0]).unwrap();
+    hal_state.capture_frame().unwrap();
+  }
+  assert_eq!(hal_state.allocator().allocation_count(), allocation_count);
+}